@@ -0,0 +1,88 @@
+//! Which exchanges may send which asset pairs.
+//!
+//! The list is a comma separated list of `{exchange}={pair}:{pair}`. For example
+//! `kraken=BTC/USD:ETH/USD,binance=BNB/BTC`. An exchange that isn't on the list may not send
+//! anything.
+use std::collections::{HashMap, HashSet};
+use std::str;
+
+use common::{asset, exchange};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowList {
+    /// `None` lets everything through.
+    allowed: Option<HashMap<exchange::Exchange, HashSet<asset::Pair>>>,
+}
+
+impl AllowList {
+    /// Lets every exchange send every asset pair.
+    pub fn everything() -> Self {
+        AllowList::default()
+    }
+
+    pub fn permits(&self, exchange: exchange::Exchange, asset_pair: asset::Pair) -> bool {
+        match self.allowed {
+            Some(ref allowed) => allowed
+                .get(&exchange)
+                .map(|pairs| pairs.contains(&asset_pair))
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+}
+
+impl str::FromStr for AllowList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut allowed = HashMap::new();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let invalid = || format!("Invalid allow list entry: {}", entry);
+            let mut sides = entry.splitn(2, '=');
+            let exchange: exchange::Exchange = sides
+                .next()
+                .unwrap_or("")
+                .parse()
+                .map_err(|_| invalid())?;
+            let pairs = sides
+                .next()
+                .ok_or_else(invalid)?
+                .split(':')
+                .map(|ap_str| ap_str.parse().map_err(|_| invalid()))
+                .collect::<Result<Vec<asset::Pair>, _>>()?;
+
+            allowed
+                .entry(exchange)
+                .or_insert_with(HashSet::new)
+                .extend(pairs);
+        }
+
+        Ok(AllowList { allowed: Some(allowed) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common::exchange::Exchange;
+
+    #[test]
+    fn parse_and_permit() {
+        let list: AllowList = "kraken=BTC/USD:ETH/USD, binance=BNB/BTC,kraken=ETH/BTC"
+            .parse()
+            .unwrap();
+
+        assert!(list.permits(Exchange::Kraken, asset::BTC_USD));
+        assert!(list.permits(Exchange::Kraken, asset::ETH_BTC));
+        assert!(list.permits(Exchange::Binance, asset::BNB_BTC));
+        assert!(!list.permits(Exchange::Binance, asset::BTC_USD));
+        assert!(!list.permits(Exchange::Coinbase, asset::BTC_USD));
+
+        assert!(AllowList::everything().permits(Exchange::Coinbase, asset::BNB_USD));
+
+        assert!("kraken".parse::<AllowList>().is_err());
+        assert!("kraken=BTC/JPY".parse::<AllowList>().is_err());
+        assert!("mtgox=BTC/USD".parse::<AllowList>().is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! Live trades. Every batch of newly stored trade history is sent on to whoever has
+//! subscribed to its exchange and asset pair.
+//!
+//! Each subscriber gets a buffer of `LIVE_BUFFER` batches. A subscriber that lets it fill
+//! up is dropped rather than holding up the storing of trade history.
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use futures::sync::mpsc;
+use actix::prelude::*;
+use serde_json;
+
+use common::{asset, exchange};
+use trade_history::model::TradeItem;
+
+/// Batches held for a subscriber before it is dropped.
+pub const LIVE_BUFFER: usize = 64;
+
+/// Which trades a subscriber wants. `None` is all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subscription {
+    exchanges: Option<HashSet<exchange::Exchange>>,
+    asset_pairs: Option<HashSet<asset::Pair>>,
+}
+
+impl Subscription {
+    /// Read from the `exchange` and `assetpair` query parameters. Each is a comma
+    /// separated list, `?exchange=kraken,binance&assetpair=BTC/USD`.
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        let exchanges = match query.get("exchange") {
+            Some(list) => Some(parse_list(list)?),
+            None => None,
+        };
+        let asset_pairs = match query.get("assetpair") {
+            Some(list) => Some(parse_list(list)?),
+            None => None,
+        };
+
+        Ok(Subscription { exchanges, asset_pairs })
+    }
+
+    pub fn wants(&self, exchange: exchange::Exchange, asset_pair: asset::Pair) -> bool {
+        let exchange_wanted = self.exchanges
+            .as_ref()
+            .map(|exchanges| exchanges.contains(&exchange))
+            .unwrap_or(true);
+        let pair_wanted = self.asset_pairs
+            .as_ref()
+            .map(|pairs| pairs.contains(&asset_pair))
+            .unwrap_or(true);
+
+        exchange_wanted && pair_wanted
+    }
+}
+
+fn parse_list<T>(list: &str) -> Result<HashSet<T>, String>
+where T: ::std::str::FromStr + ::std::hash::Hash + Eq {
+    list.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().map_err(|_| format!("Unknown `{}`.", item)))
+        .collect()
+}
+
+/// Newly stored trades as sent to subscribers.
+#[derive(Debug, Clone, Serialize)]
+struct LiveTrades<'a> {
+    exchange: &'a str,
+    assetpair: String,
+    trades: &'a [TradeItem],
+}
+
+/// Trade history that has just been stored. Only the items that weren't already.
+#[derive(Debug, Clone, Message)]
+pub struct Stored {
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+    trades: Vec<TradeItem>,
+}
+
+impl Stored {
+    pub fn new(
+        exchange: exchange::Exchange, asset_pair: asset::Pair, trades: Vec<TradeItem>,
+    ) -> Self {
+        Stored {
+            exchange, asset_pair, trades,
+        }
+    }
+
+    pub fn exchange(&self) -> exchange::Exchange {
+        self.exchange
+    }
+
+    pub fn asset_pair(&self) -> asset::Pair {
+        self.asset_pair
+    }
+
+    pub fn trades(&self) -> &[TradeItem] {
+        &self.trades
+    }
+}
+
+/// Start sending live trades down the `sender`. Each batch is a line of JSON. The
+/// receiving end ends once the subscriber has been dropped.
+#[derive(Debug, Message)]
+pub struct Subscribe {
+    subscription: Subscription,
+    sender: mpsc::Sender<String>,
+}
+
+impl Subscribe {
+    pub fn new(subscription: Subscription, sender: mpsc::Sender<String>) -> Self {
+        Subscribe {
+            subscription, sender,
+        }
+    }
+}
+
+/// Pass every stored batch on to the `recipient` as is. For actors within the collector.
+/// They are never dropped.
+#[derive(Message)]
+pub struct Forward {
+    recipient: Recipient<Stored>,
+}
+
+impl Forward {
+    pub fn new(recipient: Recipient<Stored>) -> Self {
+        Forward { recipient }
+    }
+}
+
+struct Subscriber {
+    subscription: Subscription,
+    sender: mpsc::Sender<String>,
+}
+
+/// Sends stored trade history on to subscribers.
+#[derive(Default)]
+pub struct Broadcaster {
+    subscribers: Vec<Subscriber>,
+    recipients: Vec<Recipient<Stored>>,
+}
+
+impl Broadcaster {
+    pub fn new() -> Self {
+        Broadcaster::default()
+    }
+
+    /// Send the `json` to the subscribers that want it. Those that are full or gone are
+    /// dropped.
+    fn publish(
+        &mut self, exchange: exchange::Exchange, asset_pair: asset::Pair, json: String,
+    ) {
+        let subscribers = mem::replace(&mut self.subscribers, Vec::new());
+        self.subscribers = subscribers
+            .into_iter()
+            .filter_map(|mut subscriber| {
+                if !subscriber.subscription.wants(exchange, asset_pair) {
+                    return Some(subscriber);
+                }
+
+                match subscriber.sender.try_send(json.clone()) {
+                    Ok(()) => Some(subscriber),
+                    Err(ref e) if e.is_full() => {
+                        warn!("Dropping a live trade subscriber that can't keep up.");
+                        None
+                    },
+                    Err(_) => {
+                        debug!("Live trade subscriber has gone.");
+                        None
+                    },
+                }
+            })
+            .collect();
+    }
+}
+
+impl Actor for Broadcaster {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        debug!("Live trade broadcaster started.");
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        debug!("Live trade broadcaster stopped.");
+    }
+}
+
+impl Handler<Subscribe> for Broadcaster {
+    type Result = ();
+
+    fn handle(&mut self, msg: Subscribe, _ctx: &mut Self::Context) {
+        self.subscribers.push(Subscriber {
+            subscription: msg.subscription,
+            sender: msg.sender,
+        });
+        debug!("Live trade subscriber added. {} subscribed.", self.subscribers.len());
+    }
+}
+
+impl Handler<Forward> for Broadcaster {
+    type Result = ();
+
+    fn handle(&mut self, msg: Forward, _ctx: &mut Self::Context) {
+        self.recipients.push(msg.recipient);
+    }
+}
+
+impl Handler<Stored> for Broadcaster {
+    type Result = ();
+
+    fn handle(&mut self, msg: Stored, _ctx: &mut Self::Context) {
+        if msg.trades.is_empty() {
+            return;
+        }
+
+        for recipient in self.recipients.iter() {
+            if let Err(e) = recipient.do_send(msg.clone()) {
+                error!("Can't forward stored trades: {}", &e);
+            }
+        }
+
+        if self.subscribers.is_empty() {
+            return;
+        }
+
+        let live = LiveTrades {
+            exchange: msg.exchange.as_str(),
+            assetpair: msg.asset_pair.to_string(),
+            trades: &msg.trades,
+        };
+        let json = match serde_json::to_string(&live) {
+            Ok(json) => json,
+            Err(e) => return error!("Can't serialize live trades: {}", &e),
+        };
+
+        self.publish(msg.exchange, msg.asset_pair, json);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{Future, Stream};
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect()
+    }
+
+    #[test]
+    fn subscriptions_filter_exchange_and_pair() {
+        let everything = Subscription::from_query(&query(&[])).unwrap();
+        assert!(everything.wants(exchange::Exchange::Bitstamp, asset::ETH_BTC));
+
+        let some = Subscription::from_query(&query(&[
+            ("exchange", "kraken,binance"), ("assetpair", "BTC/USD"),
+        ])).unwrap();
+        assert!(some.wants(exchange::Exchange::Kraken, asset::BTC_USD));
+        assert!(some.wants(exchange::Exchange::Binance, asset::BTC_USD));
+        assert!(!some.wants(exchange::Exchange::Kraken, asset::ETH_USD));
+        assert!(!some.wants(exchange::Exchange::Coinbase, asset::BTC_USD));
+
+        assert!(Subscription::from_query(&query(&[("exchange", "nowhere")])).is_err());
+    }
+
+    fn subscribe(
+        broadcaster: &mut Broadcaster, subscription: Subscription,
+    ) -> mpsc::Receiver<String> {
+        // No buffer. Only the one slot every sender gets.
+        let (sender, receiver) = mpsc::channel(0);
+        broadcaster.subscribers.push(Subscriber { subscription, sender });
+        receiver
+    }
+
+    #[test]
+    fn slow_and_gone_subscribers_are_dropped() {
+        let mut broadcaster = Broadcaster::new();
+        let coinbase = query(&[("exchange", "coinbase")]);
+        let coinbase = Subscription::from_query(&coinbase).unwrap();
+
+        let slow = subscribe(&mut broadcaster, Subscription::default());
+        let gone = subscribe(&mut broadcaster, Subscription::default());
+        let elsewhere = subscribe(&mut broadcaster, coinbase);
+        drop(gone);
+
+        broadcaster.publish(exchange::Exchange::Kraken, asset::BTC_USD, "1".to_owned());
+        assert_eq!(broadcaster.subscribers.len(), 2);
+
+        broadcaster.publish(exchange::Exchange::Kraken, asset::BTC_USD, "2".to_owned());
+        assert_eq!(broadcaster.subscribers.len(), 1);
+
+        // What was sent before the drop is still received. Then the feed ends.
+        assert_eq!(slow.collect().wait().unwrap(), vec!["1".to_owned()]);
+
+        broadcaster.publish(exchange::Exchange::Coinbase, asset::BTC_USD, "3".to_owned());
+        drop(broadcaster);
+        assert_eq!(elsewhere.collect().wait().unwrap(), vec!["3".to_owned()]);
+    }
+}
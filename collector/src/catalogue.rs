@@ -0,0 +1,235 @@
+//! Catalogue of the exchanges and asset pairs that have delivered trade history. Seeded
+//! from the database on startup and kept current as new trade history comes in.
+use std::collections::{BTreeMap, HashSet};
+
+use futures::Future;
+use actix::prelude::*;
+
+use common::{asset, exchange};
+
+use database::{TradeHistoryStorer, ReqDelivered};
+
+/// An asset pair along with the exchanges it is sourced from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PairSummary {
+    assetpair: String,
+    exchanges: Vec<String>,
+}
+
+/// Which exchanges have delivered which asset pairs.
+#[derive(Debug, Default)]
+struct Sources {
+    sources: BTreeMap<asset::Pair, HashSet<exchange::Exchange>>,
+}
+
+impl Sources {
+    fn insert(&mut self, exchange: exchange::Exchange, asset_pair: asset::Pair) {
+        self.sources
+            .entry(asset_pair)
+            .or_insert_with(HashSet::new)
+            .insert(exchange);
+    }
+
+    fn exchanges(&self, asset_pair: asset::Pair) -> Option<Vec<exchange::Exchange>> {
+        self.sources
+            .get(&asset_pair)
+            .map(|exchanges| {
+                let mut exchanges: Vec<exchange::Exchange> = exchanges
+                    .iter()
+                    .map(|e| *e)
+                    .collect();
+                exchanges.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+                exchanges
+            })
+    }
+
+    fn summary(&self) -> Vec<PairSummary> {
+        self.sources
+            .keys()
+            .filter_map(|ap| self.exchanges(*ap).map(|exchanges| PairSummary {
+                assetpair: ap.to_string(),
+                exchanges: exchanges.iter().map(|e| e.as_str().to_owned()).collect(),
+            }))
+            .collect()
+    }
+
+    /// The right side assets delivered for the `left` asset. `None` if there are none.
+    fn right_assets(&self, left: asset::Asset) -> Option<Vec<asset::Asset>> {
+        let rights: Vec<asset::Asset> = self.sources
+            .keys()
+            .filter(|ap| ap.left() == left)
+            .map(|ap| ap.right())
+            .collect();
+
+        if rights.is_empty() {
+            None
+        } else {
+            Some(rights)
+        }
+    }
+}
+
+/// Trade history has been received from the `exchange` for the `asset_pair`.
+#[derive(Debug, Copy, Clone, Message)]
+pub struct Delivered {
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+}
+
+impl Delivered {
+    pub fn new(exchange: exchange::Exchange, asset_pair: asset::Pair) -> Self {
+        Delivered {
+            exchange, asset_pair,
+        }
+    }
+}
+
+#[derive(Message)]
+struct Seed(Vec<(exchange::Exchange, asset::Pair)>);
+
+/// Request a summary of every asset pair and the exchanges delivering it.
+#[derive(Debug, Copy, Clone)]
+pub struct ReqSummary;
+
+impl Message for ReqSummary {
+    type Result = Vec<PairSummary>;
+}
+
+/// Request the right side assets delivered for a left side asset.
+#[derive(Debug, Copy, Clone)]
+pub struct ReqRightAssets(pub asset::Asset);
+
+impl Message for ReqRightAssets {
+    type Result = Option<Vec<asset::Asset>>;
+}
+
+/// Request the exchanges delivering an asset pair.
+#[derive(Debug, Copy, Clone)]
+pub struct ReqExchanges(pub asset::Pair);
+
+impl Message for ReqExchanges {
+    type Result = Option<Vec<exchange::Exchange>>;
+}
+
+pub struct Catalogue {
+    sources: Sources,
+    storer: Addr<TradeHistoryStorer>,
+}
+
+impl Catalogue {
+    pub fn new(storer: Addr<TradeHistoryStorer>) -> Self {
+        Catalogue {
+            sources: Sources::default(),
+            storer: storer,
+        }
+    }
+}
+
+impl Actor for Catalogue {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        let self_addr = ctx.address();
+
+        let seed_future = self.storer
+            .send(ReqDelivered)
+            .map_err(|e| error!("Can't get delivered sources from database actor: {}", &e))
+            .and_then(move |option| {
+                let delivered = option.unwrap_or_else(Vec::new);
+                self_addr
+                    .send(Seed(delivered))
+                    .map_err(|e| error!("Can't seed the catalogue: {}", &e))
+            });
+
+        Arbiter::spawn(seed_future);
+
+        debug!("Catalogue started.");
+    }
+
+    fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        debug!("Catalogue stopped.");
+    }
+}
+
+impl Handler<Seed> for Catalogue {
+    type Result = ();
+
+    fn handle(&mut self, msg: Seed, _ctx: &mut Self::Context) {
+        trace!("Seeding catalogue with {} source(s).", msg.0.len());
+        for (exchange, asset_pair) in msg.0 {
+            self.sources.insert(exchange, asset_pair);
+        }
+    }
+}
+
+impl Handler<Delivered> for Catalogue {
+    type Result = ();
+
+    fn handle(&mut self, msg: Delivered, _ctx: &mut Self::Context) {
+        self.sources.insert(msg.exchange, msg.asset_pair);
+    }
+}
+
+impl Handler<ReqSummary> for Catalogue {
+    type Result = Vec<PairSummary>;
+
+    fn handle(&mut self, _: ReqSummary, _: &mut Self::Context) -> Self::Result {
+        self.sources.summary()
+    }
+}
+
+impl Handler<ReqRightAssets> for Catalogue {
+    type Result = Option<Vec<asset::Asset>>;
+
+    fn handle(&mut self, msg: ReqRightAssets, _: &mut Self::Context) -> Self::Result {
+        self.sources.right_assets(msg.0)
+    }
+}
+
+impl Handler<ReqExchanges> for Catalogue {
+    type Result = Option<Vec<exchange::Exchange>>;
+
+    fn handle(&mut self, msg: ReqExchanges, _: &mut Self::Context) -> Self::Result {
+        self.sources.exchanges(msg.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    use common::exchange::Exchange;
+
+    #[test]
+    fn lookups_only_know_delivered_sources() {
+        let mut sources = Sources::default();
+        assert!(sources.summary().is_empty());
+        assert_eq!(sources.right_assets(asset::Asset::BTC), None);
+
+        sources.insert(Exchange::Kraken, asset::BTC_USD);
+        sources.insert(Exchange::Binance, asset::BTC_USD);
+        sources.insert(Exchange::Kraken, asset::BTC_USD);
+        sources.insert(Exchange::Binance, asset::ETH_BTC);
+        sources.insert(Exchange::Coinbase, asset::ETH_USD);
+
+        assert_eq!(
+            sources.exchanges(asset::BTC_USD),
+            Some(vec![Exchange::Binance, Exchange::Kraken]),
+        );
+        assert_eq!(sources.exchanges(asset::BNB_BTC), None);
+
+        assert_eq!(
+            sources.right_assets(asset::Asset::ETH),
+            Some(vec![asset::Asset::BTC, asset::Asset::USD]),
+        );
+        assert_eq!(sources.right_assets(asset::Asset::USD), None);
+
+        let summary = serde_json::to_string(&sources.summary()).unwrap();
+        assert_eq!(
+            summary,
+            r#"[{"assetpair":"BTC/USD","exchanges":["binance","kraken"]},{"assetpair":"ETH/BTC","exchanges":["binance"]},{"assetpair":"ETH/USD","exchanges":["coinbase"]}]"#,
+        );
+    }
+}
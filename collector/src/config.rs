@@ -3,15 +3,33 @@ use std::{env, net};
 
 use common::errors::ConfigError;
 
+use chrono::Duration;
+
+use lib::allow::AllowList;
+use lib::term::TermSettings;
+
 static LISTEN: &str = "COLLECTOR_LISTEN_ADDR";
 static DB_URL: &str = "DATABASE_URL";
 static DB_CONNS: &str = "DATABASE_CONNECTIONS";
+static ALLOW_LIST: &str = "COLLECTOR_ALLOW_LIST";
+static TICKER_DB_URL: &str = "TICKER_DATABASE_URL";
+static TICK_RESOLUTIONS: &str = "COLLECTOR_TICK_RESOLUTIONS";
+static TICK_GRACE: &str = "COLLECTOR_TICK_GRACE";
+
+/// One minute ticks if not set.
+static DEFAULT_TICK_RESOLUTIONS: &str = "60";
+
+/// Seconds to wait for late trades after a term ends if not set.
+const DEFAULT_TICK_GRACE: u32 = 10;
 
 #[derive(Debug)]
 pub struct Configuration {
     listen: net::SocketAddr,
     database_url: String,
     database_connections: u8,
+    allow_list: AllowList,
+    ticker_database_url: Option<String>,
+    term_settings: TermSettings,
 }
 
 impl Configuration {
@@ -26,16 +44,56 @@ impl Configuration {
     pub fn database_connections(&self) -> u8 {
         self.database_connections
     }
+
+    /// Which exchanges may send which asset pairs. Everything is allowed if not set.
+    pub fn allow_list(&self) -> &AllowList {
+        &self.allow_list
+    }
+
+    /// Where ticks are written. No ticks are calculated if not set.
+    pub fn ticker_database_url(&self) -> Option<&str> {
+        self.ticker_database_url.as_ref().map(|url| url.as_str())
+    }
+
+    pub fn term_settings(&self) -> &TermSettings {
+        &self.term_settings
+    }
 }
 
 pub fn config_from_environment() -> Result<Configuration, ConfigError> {
     let listen = env::var(LISTEN).map_err(|e| (LISTEN, e))?;
     let db_conns = env::var(DB_CONNS).map_err(|e| (DB_CONNS, e))?;
-    
+
+    let allow_list = match env::var(ALLOW_LIST) {
+        Ok(list) => list
+            .parse()
+            .map_err(|e| ConfigError::InvalidAllowList(ALLOW_LIST.to_owned(), e))?,
+        Err(_) => AllowList::everything(),
+    };
+
+    let resolutions = env::var(TICK_RESOLUTIONS)
+        .unwrap_or_else(|_| DEFAULT_TICK_RESOLUTIONS.to_owned());
+    let resolutions = resolutions
+        .split(',')
+        .map(|seconds| seconds.trim().parse::<u32>().map_err(|e| (TICK_RESOLUTIONS, e)))
+        .map(|seconds| seconds.map(|seconds| Duration::seconds(seconds as i64)))
+        .collect::<Result<Vec<Duration>, _>>()?;
+    if resolutions.iter().any(|resolution| resolution.num_seconds() == 0) {
+        return Err(ConfigError::ZeroDuration(TICK_RESOLUTIONS.to_owned()));
+    }
+
+    let grace = match env::var(TICK_GRACE) {
+        Ok(grace) => grace.parse().map_err(|e| (TICK_GRACE, e))?,
+        Err(_) => DEFAULT_TICK_GRACE,
+    };
+
     Ok(Configuration {
         listen: listen.parse().map_err(|e| (LISTEN, e))?,
         database_url: env::var(DB_URL).map_err(|e| (DB_URL, e))?,
         database_connections: db_conns.parse().map_err(|e|(DB_CONNS, e))?,
+        allow_list,
+        ticker_database_url: env::var(TICKER_DB_URL).ok(),
+        term_settings: TermSettings::new(resolutions, Duration::seconds(grace as i64)),
     })
 }
 
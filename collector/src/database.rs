@@ -1,11 +1,19 @@
 //! Database actor
 
+use chrono::{DateTime, Utc};
 use actix::prelude::*;
 
-use common::{exchange, trade, asset};
+use common::{exchange, trade, asset, candle, book};
 use trade_history::{crud, model};
+use trade_history::error::Error;
+use ticker_db::crud::Ticks;
+use ticker_db::model::FreshTick;
 
-#[derive(Debug, Clone, Message)]
+use broadcast::{Broadcaster, Stored};
+
+/// Trade history to be stored. Resolves to how many items were inserted once they have
+/// been committed. Items already stored count as stored but not as inserted.
+#[derive(Debug, Clone)]
 pub struct NewTradeHistory {
     exchange: exchange::Exchange,
     asset_pair: asset::Pair,
@@ -24,14 +32,67 @@ impl NewTradeHistory {
     }
 }
 
+impl Message for NewTradeHistory {
+    type Result = Result<u64, String>;
+}
+
+/// Stores and reads trade history. A lost connection is replaced on the next message
+/// instead of taking the actor down with it. Newly stored trade history is passed on to the
+/// `broadcaster`.
 pub struct TradeHistoryStorer  {
-    executor: crud::Trades,
+    db_url: String,
+    executor: Option<crud::Trades>,
+    broadcaster: Addr<Broadcaster>,
 }
 
 impl TradeHistoryStorer {
-    pub fn new(db_url: &str) -> Self {
+    pub fn new(db_url: &str, broadcaster: Addr<Broadcaster>) -> Self {
+        let executor = match crud::Trades::connect(db_url) {
+            Ok(executor) => Some(executor),
+            Err(e) => {
+                error!("Database connect failure. Will try again when needed: {}", &e);
+                None
+            },
+        };
+
         TradeHistoryStorer {
-            executor: crud::Trades::connect(db_url).expect("Database connect failure."),
+            db_url: db_url.to_owned(),
+            executor,
+            broadcaster,
+        }
+    }
+
+    fn connect(&mut self) -> Result<(), String> {
+        if self.executor.is_none() {
+            let executor = crud::Trades::connect(&self.db_url)
+                .map_err(|e| format!("Database connect failure: {}", &e))?;
+            debug!("Trade history storer connected to database.");
+            self.executor = Some(executor);
+        }
+        Ok(())
+    }
+
+    /// Run the `op` against the database. Should the connection have been lost, it is
+    /// dropped and the `op` is tried once more on a fresh one.
+    fn execute<T, F>(&mut self, op: F) -> Result<T, String>
+    where F: Fn(&crud::Trades) -> Result<T, Error> {
+        let mut retry = true;
+        loop {
+            self.connect()?;
+            let result = op(self.executor.as_ref().expect("Connected above."));
+
+            match result {
+                Ok(value) => return Ok(value),
+                Err(ref e) if e.is_connection() => {
+                    warn!("Lost the database connection: {}", e);
+                    self.executor = None;
+                    if !retry {
+                        return Err(e.to_string());
+                    }
+                    retry = false;
+                },
+                Err(e) => return Err(e.to_string()),
+            }
         }
     }
 }
@@ -48,58 +109,263 @@ impl Actor for TradeHistoryStorer {
     }
 }
 
+fn fresh_trade_items(
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+    items: &[trade::TradeHistoryItem],
+) -> Vec<model::FreshTradeItem> {
+    items
+        .iter()
+        .map(|item| model::FreshTradeItem::new(
+            exchange,
+            asset_pair,
+            item.timestamp(),
+            item.size(),
+            item.price(),
+            item.market(),
+            item.trade(),
+            item.match_id(),
+            item.buy_order_id(),
+            item.sell_order_id(),
+            item.match_timestamp(),
+        ))
+        .collect()
+}
+
 impl Handler<NewTradeHistory> for TradeHistoryStorer {
-    type Result = ();
+    type Result = Result<u64, String>;
 
-    fn handle(&mut self, msg: NewTradeHistory, _ctx: &mut Self::Context) {
+    fn handle(&mut self, msg: NewTradeHistory, _ctx: &mut Self::Context) -> Self::Result {
         let exchange = msg.exchange;
         let asset_pair = msg.asset_pair;
-        let ftis: Vec<model::FreshTradeItem> = msg.items
-            .iter()
-            .map(|item| model::FreshTradeItem::new(
-                exchange,
-                asset_pair,
-                item.timestamp(),
-                item.size(),
-                item.price(),
-                item.market(),
-                item.trade(),
-                item.match_id(),
-                item.buy_order_id(),
-                item.sell_order_id(),
-                item.match_timestamp(),
-            ))
-            .collect();
-
-        self.executor.create(&ftis).expect("Couldn't insert trade history.");
-    }
-}
-
-/// A request to fetch the last history item stored in the DB for the exchange/asset_pair.
+        let ftis = fresh_trade_items(exchange, asset_pair, &msg.items);
+
+        let created = self.execute(|executor| executor.create_bulk(&ftis))
+            .map_err(|e| format!("Couldn't insert trade history: {}", &e))?;
+
+        trace!(
+            "{} {} {} trade history item(s) inserted. {} duplicate(s) skipped.",
+            &exchange,
+            &asset_pair,
+            created.inserted().len(),
+            created.duplicates(),
+        );
+
+        let inserted = created.inserted().len() as u64;
+        if inserted > 0 {
+            let stored = Stored::new(exchange, asset_pair, created.inserted().clone());
+            self.broadcaster.do_send(stored);
+        }
+
+        Ok(inserted)
+    }
+}
+
+/// Candles computed by the exchange to be stored as is. Resolves to how many were inserted.
+#[derive(Debug, Clone)]
+pub struct NewCandles {
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+    candles: Vec<candle::Candle>,
+}
+
+impl NewCandles {
+    pub fn new(
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        candles: Vec<candle::Candle>,
+    ) -> Self {
+        NewCandles {
+            exchange, asset_pair, candles,
+        }
+    }
+}
+
+impl Message for NewCandles {
+    type Result = Result<u64, String>;
+}
+
+impl Handler<NewCandles> for TradeHistoryStorer {
+    type Result = Result<u64, String>;
+
+    fn handle(&mut self, msg: NewCandles, _ctx: &mut Self::Context) -> Self::Result {
+        let (exchange, asset_pair) = (msg.exchange, msg.asset_pair);
+        let candles = &msg.candles;
+        let inserted = self
+            .execute(|executor| executor.create_candles(exchange, asset_pair, candles))
+            .map_err(|e| format!("Couldn't insert candles: {}", &e))?;
+
+        trace!(
+            "{} {} candle(s) inserted out of {}.", &msg.asset_pair, inserted, msg.candles.len()
+        );
+
+        Ok(inserted)
+    }
+}
+
+/// Aggregate trades to be stored as is. Resolves to how many were inserted.
+#[derive(Debug, Clone)]
+pub struct NewAggregateTrades {
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+    aggregates: Vec<trade::AggregateTrade>,
+}
+
+impl NewAggregateTrades {
+    pub fn new(
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        aggregates: Vec<trade::AggregateTrade>,
+    ) -> Self {
+        NewAggregateTrades {
+            exchange, asset_pair, aggregates,
+        }
+    }
+}
+
+impl Message for NewAggregateTrades {
+    type Result = Result<u64, String>;
+}
+
+impl Handler<NewAggregateTrades> for TradeHistoryStorer {
+    type Result = Result<u64, String>;
+
+    fn handle(
+        &mut self, msg: NewAggregateTrades, _ctx: &mut Self::Context
+    ) -> Self::Result {
+        let (exchange, asset_pair) = (msg.exchange, msg.asset_pair);
+        let aggregates = &msg.aggregates;
+        let inserted = self
+            .execute(|executor| {
+                executor.create_aggregate_trades(exchange, asset_pair, aggregates)
+            })
+            .map_err(|e| format!("Couldn't insert aggregate trades: {}", &e))?;
+
+        trace!(
+            "{} {} aggregate trade(s) inserted out of {}.",
+            &msg.asset_pair,
+            inserted,
+            msg.aggregates.len(),
+        );
+
+        Ok(inserted)
+    }
+}
+
+/// Synchronised order book snapshots and deltas to be stored as is. Resolves to how many
+/// were inserted.
+#[derive(Debug, Clone)]
+pub struct NewBookEvents {
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+    events: Vec<book::BookEvent>,
+}
+
+impl NewBookEvents {
+    pub fn new(
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        events: Vec<book::BookEvent>,
+    ) -> Self {
+        NewBookEvents {
+            exchange, asset_pair, events,
+        }
+    }
+}
+
+impl Message for NewBookEvents {
+    type Result = Result<u64, String>;
+}
+
+impl Handler<NewBookEvents> for TradeHistoryStorer {
+    type Result = Result<u64, String>;
+
+    fn handle(&mut self, msg: NewBookEvents, _ctx: &mut Self::Context) -> Self::Result {
+        let (exchange, asset_pair) = (msg.exchange, msg.asset_pair);
+        let events = &msg.events;
+        let inserted = self
+            .execute(|executor| executor.create_book_events(exchange, asset_pair, events))
+            .map_err(|e| format!("Couldn't insert order book events: {}", &e))?;
+
+        trace!(
+            "{} {} order book event(s) inserted out of {}.",
+            &msg.asset_pair,
+            inserted,
+            msg.events.len(),
+        );
+
+        Ok(inserted)
+    }
+}
+
+/// A request to fetch the most recent history items stored in the DB for the
+/// exchange/asset_pair. No more than `limit` are returned, oldest first.
 #[derive(Debug, Copy, Clone)]
-pub struct ReqLastHistoryItem {
+pub struct ReqRecentHistoryItems {
     exchange: exchange::Exchange,
     asset_pair: asset::Pair,
+    limit: usize,
+}
+
+impl ReqRecentHistoryItems {
+    pub fn new(
+        exchange: exchange::Exchange, asset_pair: asset::Pair, limit: usize,
+    ) -> Self {
+        ReqRecentHistoryItems {
+            exchange, asset_pair, limit,
+        }
+    }
 }
 
-impl ReqLastHistoryItem {
-    pub fn new(exchange: exchange::Exchange, asset_pair: asset::Pair) -> Self {
-        ReqLastHistoryItem {
-            exchange, asset_pair,
+impl Message for ReqRecentHistoryItems {
+    type Result = Option<Vec<trade::TradeHistoryItem>>;
+}
+
+impl Handler<ReqRecentHistoryItems> for TradeHistoryStorer {
+    type Result = Option<Vec<trade::TradeHistoryItem>>;
+
+    fn handle(
+        &mut self, msg: ReqRecentHistoryItems, _: &mut Self::Context
+    ) -> Self::Result {
+        let (exchange, asset_pair) = (msg.exchange, msg.asset_pair);
+        let limit = msg.limit as i64;
+        let read = self.execute(|executor| {
+            executor.read_recent_items(exchange, asset_pair, limit)
+        });
+
+        match read {
+            Ok(items) => Some(items),
+            Err(e) => {
+                error!("Can't read recent {} trade history: {}", &msg.asset_pair, &e);
+                None
+            },
         }
     }
 }
 
-impl Message for ReqLastHistoryItem {
-    type Result = Option<model::TradeItem>;
+/// Request every stored trade that happened at or after `from`, oldest first.
+#[derive(Debug, Copy, Clone)]
+pub struct ReqTradesSince {
+    from: DateTime<Utc>,
+}
+
+impl ReqTradesSince {
+    pub fn new(from: DateTime<Utc>) -> Self {
+        ReqTradesSince { from }
+    }
+}
+
+impl Message for ReqTradesSince {
+    type Result = Result<Vec<model::TradeItem>, String>;
 }
 
-impl Handler<ReqLastHistoryItem> for TradeHistoryStorer {
-    type Result = Option<model::TradeItem>;
+impl Handler<ReqTradesSince> for TradeHistoryStorer {
+    type Result = Result<Vec<model::TradeItem>, String>;
 
-    fn handle(&mut self, msg: ReqLastHistoryItem, _: &mut Self::Context) -> Self::Result {
-        self.executor.read_last_item(msg.exchange, msg.asset_pair)
-            .expect("Couldn't read from DB.")
+    fn handle(&mut self, msg: ReqTradesSince, _: &mut Self::Context) -> Self::Result {
+        let from = msg.from;
+        self.execute(|executor| executor.read_since(from))
+            .map_err(|e| format!("Can't read trades since {}: {}", &from, &e))
     }
 }
 
@@ -115,6 +381,118 @@ impl Handler<ReqAllLloadAssetPairs> for TradeHistoryStorer {
     type Result = Option<Vec<asset::Pair>>;
 
     fn handle(&mut self, _: ReqAllLloadAssetPairs, _: &mut Self::Context) -> Self::Result {
-        Some(self.executor.asset_pairs())
+        match self.execute(|executor| Ok(executor.asset_pairs())) {
+            Ok(asset_pairs) => Some(asset_pairs),
+            Err(e) => {
+                error!("Can't read asset pairs from DB: {}", &e);
+                None
+            },
+        }
+    }
+}
+
+/// Request every exchange and asset pair combination that has trade history stored.
+#[derive(Debug, Copy, Clone)]
+pub struct ReqDelivered;
+
+impl Message for ReqDelivered {
+    type Result = Option<Vec<(exchange::Exchange, asset::Pair)>>;
+}
+
+impl Handler<ReqDelivered> for TradeHistoryStorer {
+    type Result = Option<Vec<(exchange::Exchange, asset::Pair)>>;
+
+    fn handle(&mut self, _: ReqDelivered, _: &mut Self::Context) -> Self::Result {
+        match self.execute(|executor| executor.delivered()) {
+            Ok(delivered) => Some(delivered),
+            Err(e) => {
+                error!("Can't read delivered trade history from DB: {}", &e);
+                None
+            },
+        }
+    }
+}
+
+/// A tick to be written to the ticker database.
+#[derive(Debug, Copy, Clone)]
+pub struct NewTick {
+    tick: FreshTick,
+}
+
+impl NewTick {
+    pub fn new(tick: FreshTick) -> Self {
+        NewTick { tick }
+    }
+}
+
+impl Message for NewTick {
+    type Result = Result<(), String>;
+}
+
+/// Writes ticks to the ticker database. Like the `TradeHistoryStorer` a failed connection
+/// is replaced on the next message.
+pub struct TickStorer {
+    db_url: String,
+    executor: Option<Ticks>,
+}
+
+impl TickStorer {
+    pub fn new(db_url: &str) -> Self {
+        TickStorer {
+            db_url: db_url.to_owned(),
+            executor: None,
+        }
+    }
+
+    fn connect(&mut self) -> Result<(), String> {
+        if self.executor.is_none() {
+            let executor = Ticks::connect(&self.db_url)
+                .map_err(|e| format!("Ticker database connect failure: {}", &e))?;
+            debug!("Tick storer connected to database.");
+            self.executor = Some(executor);
+        }
+        Ok(())
+    }
+}
+
+impl Actor for TickStorer {
+    type Context = SyncContext<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        debug!("Tick storer started.");
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        debug!("Tick storer stopped.");
+    }
+}
+
+impl Handler<NewTick> for TickStorer {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: NewTick, _: &mut Self::Context) -> Self::Result {
+        self.connect()?;
+        let created = self.executor
+            .as_ref()
+            .expect("Connected above.")
+            .create(&msg.tick);
+
+        match created {
+            Ok(tick) => {
+                trace!(
+                    "{} {} tick from {} to {} written.",
+                    tick.exchange(),
+                    tick.asset_pair(),
+                    tick.start_time(),
+                    tick.end_time(),
+                );
+                Ok(())
+            },
+            Err(e) => {
+                // Can't tell a lost connection apart. Start afresh either way.
+                self.executor = None;
+                Err(e.to_string())
+            },
+        }
     }
 }
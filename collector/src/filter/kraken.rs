@@ -1,92 +1,212 @@
 //! Filter for kraken
-use std::collections::HashMap;
+//!
+//! Kraken trades don't always carry an ID and several can share a timestamp. Polls overlap
+//! so the same trades come in more than once. A window of the most recently seen trades is
+//! kept for each asset pair and only exact repeats of those are dropped.
+//!
+//! Two real fills can be exactly alike, so the window counts how often it has seen each
+//! trade. A poll only has as many of a trade dropped as the window has already seen.
+use std::collections::{HashMap, VecDeque};
 
-use futures::Future;
-use futures::future::lazy;
-use chrono::{DateTime, Utc, TimeZone};
+use futures::{Future, future};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use actix::prelude::*;
 
 use common::{asset, trade, exchange};
 use super::UnfilteredTradeHistory;
 
 use database::{
-    NewTradeHistory, TradeHistoryStorer, ReqLastHistoryItem, ReqAllLloadAssetPairs
+    NewTradeHistory, TradeHistoryStorer, ReqRecentHistoryItems, ReqAllLloadAssetPairs
 };
 
-lazy_static! {
-    static ref YR2000: DateTime<Utc> = Utc.ymd(2000, 1, 1).and_hms(0, 0, 0);
+/// How many trades are remembered per asset pair. A kraken poll returns up to a thousand.
+const WINDOW_SIZE: usize = 5000;
+
+/// What tells one trade apart from another. The match ID if there is one. Otherwise
+/// everything else that is stored about the trade.
+///
+/// Timestamps are rounded to the microsecond and decimals have their trailing zeros
+/// removed. That's how the trade comes back out of the database.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+enum Fingerprint {
+    Match(i64),
+    Content {
+        micros: (i64, u32),
+        size: String,
+        price: String,
+        market: trade::Market,
+        trade: Option<trade::Type>,
+        buy_order_id: Option<i64>,
+        sell_order_id: Option<i64>,
+        match_micros: Option<(i64, u32)>,
+    },
 }
 
-#[derive(Message)]
-struct ToFilterTradeHistory {
-    asset_pair: asset::Pair,
-    history: Vec<trade::TradeHistoryItem>,
+/// Seconds and microseconds, rounded to the nearest.
+fn micros(timestamp: DateTime<Utc>) -> (i64, u32) {
+    let micros = (timestamp.timestamp_subsec_nanos() + 500) / 1000;
+    if micros >= 1_000_000 {
+        (timestamp.timestamp() + 1, micros - 1_000_000)
+    } else {
+        (timestamp.timestamp(), micros)
+    }
+}
+
+fn normalize(decimal: Decimal) -> String {
+    let text = decimal.to_string();
+    if text.contains('.') {
+        text.trim_right_matches('0').trim_right_matches('.').to_owned()
+    } else {
+        text
+    }
+}
+
+impl<'a> From<&'a trade::TradeHistoryItem> for Fingerprint {
+    fn from(item: &trade::TradeHistoryItem) -> Self {
+        if let Some(match_id) = item.match_id() {
+            return Fingerprint::Match(match_id);
+        }
+
+        Fingerprint::Content {
+            micros: micros(item.timestamp()),
+            size: normalize(item.size()),
+            price: normalize(item.price()),
+            market: item.market(),
+            trade: item.trade(),
+            buy_order_id: item.buy_order_id(),
+            sell_order_id: item.sell_order_id(),
+            match_micros: item.match_timestamp().map(micros),
+        }
+    }
 }
 
+/// The most recently seen trades of an asset pair and how often each was seen. The oldest
+/// are forgotten first.
+#[derive(Debug)]
+struct Window {
+    seen: HashMap<Fingerprint, usize>,
+    order: VecDeque<Fingerprint>,
+    size: usize,
+}
+
+impl Window {
+    fn new(size: usize) -> Self {
+        Window {
+            seen: HashMap::with_capacity(size),
+            order: VecDeque::with_capacity(size),
+            size,
+        }
+    }
+
+    /// How many times the trade is in the window.
+    fn count(&self, fingerprint: &Fingerprint) -> usize {
+        self.seen.get(fingerprint).cloned().unwrap_or(0)
+    }
+
+    /// Remember one more of the trade.
+    fn insert(&mut self, item: &trade::TradeHistoryItem) {
+        if self.order.len() == self.size {
+            if let Some(oldest) = self.order.pop_front() {
+                let gone = match self.seen.get_mut(&oldest) {
+                    Some(count) => {
+                        *count -= 1;
+                        *count == 0
+                    },
+                    None => false,
+                };
+                if gone {
+                    self.seen.remove(&oldest);
+                }
+            }
+        }
+
+        let fingerprint = Fingerprint::from(item);
+        *self.seen.entry(fingerprint.clone()).or_insert(0) += 1;
+        self.order.push_back(fingerprint);
+    }
+
+    /// Drop as many of each trade in the `history` as have already been seen. The rest are
+    /// new, even if exactly alike.
+    fn unseen(&self, history: &mut Vec<trade::TradeHistoryItem>) {
+        let mut polled: HashMap<Fingerprint, usize> = HashMap::new();
+        history.retain(|item| {
+            let fingerprint = Fingerprint::from(item);
+            let seen = self.count(&fingerprint);
+            let nth = polled.entry(fingerprint).or_insert(0);
+            *nth += 1;
+            *nth > seen
+        });
+    }
+}
+
+/// Trades that have been stored in the database.
 #[derive(Message)]
-struct UpdateTimestampMarker {
+struct Remember {
     asset_pair: asset::Pair,
-    timestamp: DateTime<Utc>,
+    history: Vec<trade::TradeHistoryItem>,
 }
 
 /// Filter optimized for kraken trade history. This will ensure that only new items are
 /// forwarded on through the system.
 pub struct KrakenTradeHistory {
-    timestamp_marker: HashMap<asset::Pair, DateTime<Utc>>,
+    windows: HashMap<asset::Pair, Window>,
     storer: Addr<TradeHistoryStorer>,
 }
 
 impl KrakenTradeHistory {
     pub fn new(storer: Addr<TradeHistoryStorer>) -> Self {
         KrakenTradeHistory {
-            timestamp_marker: HashMap::new(),
+            windows: HashMap::new(),
             storer: storer,
         }
     }
+
+    fn window(&mut self, asset_pair: asset::Pair) -> &mut Window {
+        self.windows
+            .entry(asset_pair)
+            .or_insert_with(|| Window::new(WINDOW_SIZE))
+    }
 }
 
 impl Actor for KrakenTradeHistory {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Context<Self>) {
-        // TODO: Setup future that will fill the timestamp_marker with timestamps for all
-        //       asset pairs in the database.
         let self_addr = ctx.address();
         let storer_addr = self.storer.clone();
         let kraken = exchange::Exchange::Kraken;
 
-        let update_future = self.storer
+        let seed_future = self.storer
             .send(ReqAllLloadAssetPairs)
             .map_err(|e| error!("Can't get asset pair list from database actor: {}", &e))
-            .map(|option| option.expect("Must always be `Some(Vec<asset::Pair>)`!"))
+            .map(|option| option.unwrap_or_else(|| {
+                warn!("No asset pairs to seed the kraken trade windows with.");
+                Vec::new()
+            }))
             .and_then(move |list| {
                 list.into_iter()
                     .for_each(|ap| {
-                        //println!("Asset Pair: {}", &ap);
                         let self_addr_clone = self_addr.clone();
-                        let request = ReqLastHistoryItem::new(kraken, ap);
-                        let ts_fut = storer_addr.send(request)
-                            //.map_err(|e| error!("Can't get last trade history item."))
-                            .map(move |maybie| maybie
-                                 .map(|item| (*item.timestamp(), ap))
-                                 .unwrap_or((*YR2000, ap)))
-                            .and_then(move |(ts, ap)| {
-                                let update = UpdateTimestampMarker {
+                        let request = ReqRecentHistoryItems::new(kraken, ap, WINDOW_SIZE);
+                        let seed_fut = storer_addr.send(request)
+                            .and_then(move |maybie| {
+                                let seed = Remember {
                                     asset_pair: ap,
-                                    timestamp: ts,
+                                    history: maybie.unwrap_or_else(Vec::new),
                                 };
-                                self_addr_clone.send(update)
+                                self_addr_clone.send(seed)
                             })
                             .map_err(|e| error!(
-                                "Can't update asset pair timestamp: {}", &e
+                                "Can't seed asset pair trade window: {}", &e
                             ));
 
-                        Arbiter::spawn(ts_fut);
+                        Arbiter::spawn(seed_fut);
                     });
                 Ok(())
             });
 
-        Arbiter::spawn(update_future);
+        Arbiter::spawn(seed_future);
 
         debug!("Kraken Trade History filter started.");
     }
@@ -97,69 +217,172 @@ impl Actor for KrakenTradeHistory {
 }
 
 impl Handler<UnfilteredTradeHistory> for KrakenTradeHistory {
-    type Result = ();
+    type Result = ResponseFuture<u64, String>;
+
+    fn handle(
+        &mut self, msg: UnfilteredTradeHistory, ctx: &mut Self::Context
+    ) -> Self::Result {
+        let asset_pair = msg.asset_pair;
+
+        let mut history = msg.history;
+        self.window(asset_pair).unseen(&mut history);
+
+        // Only process further if there's data.
+        if history.is_empty() {
+            return Box::new(future::ok(0));
+        }
+
+        trace!(
+            "{} new {} kraken trade history item(s). Sending to DB store.",
+            &asset_pair,
+            &history.len(),
+        );
 
-    fn handle(&mut self, msg: UnfilteredTradeHistory, ctx: &mut Self::Context) {
+        // Trades are only remembered once stored. A failed store can then be retried.
         let self_addr = ctx.address();
-        let message = ToFilterTradeHistory {
-            asset_pair: msg.asset_pair,
-            history: msg.history,
+        let stored = Remember {
+            asset_pair: asset_pair,
+            history: history.clone(),
         };
 
-        // Send to itself. This is to detach completion of this method from actual
-        // completion of the work. Therefore the method can return immediately whilst the
-        // work is now being handled elsewhere (still in the same actor though).
-        Arbiter::spawn(lazy(move || {
-            self_addr
-                .send(message)
-                .then(|result| {
-                    match result {
-                        Ok(()) => (),
-                        Err(e) => error!("Couldn't send internally: {}", &e),
-                    }
-                    Ok(())
-                })
-        }))
+        let new_trade_history = NewTradeHistory::new(
+            exchange::Exchange::Kraken, asset_pair, history
+        );
+
+        let store_future = self.storer.send(new_trade_history)
+            .map_err(|e| format!("Kraken filter can't send to storer! {}", &e))
+            .and_then(|result| result)
+            .map(move |inserted| {
+                self_addr.do_send(stored);
+                inserted
+            });
+
+        Box::new(store_future)
     }
 }
 
-impl Handler<ToFilterTradeHistory> for KrakenTradeHistory {
+impl Handler<Remember> for KrakenTradeHistory {
     type Result = ();
 
-    fn handle(&mut self, msg: ToFilterTradeHistory, _ctx: &mut Self::Context) {
-        let asset_pair = msg.asset_pair;
+    fn handle(&mut self, msg: Remember, _ctx: &mut Self::Context) {
+        trace!("Remembering {} {} trade(s).", msg.history.len(), &msg.asset_pair);
+        let window = self.window(msg.asset_pair);
+        for item in msg.history.iter() {
+            window.insert(item);
+        }
+    }
+}
 
-        let ts = *self.timestamp_marker.get(&asset_pair).unwrap_or(&YR2000);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let mut history = msg.history;
-        history.retain(move |item| item.timestamp() > ts);
+    use chrono::TimeZone;
 
-        // Only process further if there's data.
-        if let Some(item) = history.last().map(|i| *i) {
-            self.timestamp_marker.insert(asset_pair, item.timestamp());
-            trace!(
-                "{} new {} kraken trade history item(s). Sending to DB store.",
-                &asset_pair,
-                &history.len(),
-            );
-
-            // Send off to the tick generator
-            let new_trade_history = NewTradeHistory::new(
-                exchange::Exchange::Kraken, asset_pair, history
-            );
-
-            let send_future = self.storer.send(new_trade_history)
-                .map_err(|e| error!("Kraken filter can't send to storer! {}", &e));
-
-            Arbiter::spawn(send_future);
-        }
+    fn item(
+        timestamp: DateTime<Utc>, size: &str, match_id: Option<i64>,
+    ) -> trade::TradeHistoryItem {
+        trade::TradeHistoryItem::new(
+            timestamp,
+            size.parse().unwrap(),
+            "6450.1".parse().unwrap(),
+            trade::Market::Taker,
+            Some(trade::Type::Limit),
+            match_id,
+            None,
+            None,
+            None,
+        )
     }
-}
 
-impl Handler<UpdateTimestampMarker> for KrakenTradeHistory {
-    type Result = ();
+    fn unseen(
+        window: &Window, history: &[trade::TradeHistoryItem]
+    ) -> Vec<trade::TradeHistoryItem> {
+        let mut history = history.to_vec();
+        window.unseen(&mut history);
+        history
+    }
+
+    #[test]
+    fn shared_timestamps_and_out_of_order_trades_are_kept() {
+        let ts = Utc.ymd(2018, 10, 22).and_hms_milli(9, 15, 44, 562);
+        let earlier = Utc.ymd(2018, 10, 22).and_hms(9, 15, 40);
+        let mut window = Window::new(10);
+
+        window.insert(&item(ts, "0.25", None));
+        window.insert(&item(ts, "0.5", None));
+        assert!(unseen(&window, &[item(ts, "0.25", None)]).is_empty());
+
+        // Arrived late but hasn't been seen.
+        assert_eq!(unseen(&window, &[item(earlier, "1.0", None)]).len(), 1);
+
+        // The same trade read back out of the database.
+        assert!(unseen(&window, &[item(ts, "0.250000000000000", None)]).is_empty());
+
+        window.insert(&item(ts, "0.25", Some(61123)));
+        assert!(unseen(&window, &[item(earlier, "3", Some(61123))]).is_empty());
+
+        let history = vec![
+            item(ts, "0.5", None),
+            item(ts, "0.75", None),
+            item(earlier, "1", None),
+        ];
+        window.insert(&item(earlier, "1", None));
+        assert_eq!(unseen(&window, &history), vec![item(ts, "0.75", None)]);
+    }
+
+    #[test]
+    fn alike_fills_in_one_poll_are_all_kept() {
+        let ts = Utc.ymd(2018, 10, 22).and_hms_milli(9, 15, 44, 562);
+        let mut window = Window::new(10);
+
+        let poll = vec![item(ts, "0.75", None), item(ts, "0.75", None)];
+        assert_eq!(unseen(&window, &poll), poll);
+
+        // The next poll overlaps. Only a third fill alike the two is new.
+        window.insert(&poll[0]);
+        window.insert(&poll[1]);
+        assert!(unseen(&window, &poll).is_empty());
+
+        let overlap = vec![poll[0].clone(), poll[1].clone(), poll[0].clone()];
+        assert_eq!(unseen(&window, &overlap), vec![poll[0].clone()]);
+    }
+
+    #[test]
+    fn trade_types_and_order_ids_tell_fills_apart() {
+        let ts = Utc.ymd(2018, 10, 22).and_hms(9, 15, 44);
+        let limit = item(ts, "1", None);
+        let market = trade::TradeHistoryItem::new(
+            ts, limit.size(), limit.price(), limit.market(), Some(trade::Type::Market),
+            None, None, None, None,
+        );
+        let ordered = trade::TradeHistoryItem::new(
+            ts, limit.size(), limit.price(), limit.market(), limit.trade(),
+            None, Some(7), None, None,
+        );
+        let mut window = Window::new(10);
+        window.insert(&limit);
+
+        assert_eq!(unseen(&window, &[market.clone()]), vec![market]);
+        assert_eq!(unseen(&window, &[ordered.clone()]), vec![ordered]);
+    }
+
+    #[test]
+    fn oldest_are_forgotten() {
+        let ts = Utc.ymd(2018, 10, 22).and_hms(9, 15, 44);
+        let mut window = Window::new(2);
+
+        window.insert(&item(ts, "1", None));
+        window.insert(&item(ts, "2", None));
+        window.insert(&item(ts, "3", None));
+        assert!(unseen(&window, &[item(ts, "3", None)]).is_empty());
+        assert_eq!(unseen(&window, &[item(ts, "1", None)]).len(), 1);
 
-    fn handle(&mut self, msg: UpdateTimestampMarker, _ctx: &mut Self::Context) {
-        self.timestamp_marker.insert(msg.asset_pair, msg.timestamp);
+        // Counts go down one at a time.
+        window.insert(&item(ts, "3", None));
+        window.insert(&item(ts, "4", None));
+        assert!(unseen(&window, &[item(ts, "3", None)]).is_empty());
+        window.insert(&item(ts, "4", None));
+        assert_eq!(unseen(&window, &[item(ts, "3", None)]).len(), 1);
     }
 }
@@ -1,15 +1,17 @@
 //! Filter trade history actors.
 
+use actix::Message;
+
 use common::{asset, trade};
 
 mod kraken;
-mod binance;
+mod pass_through;
 
 pub use self::kraken::KrakenTradeHistory;
-pub use self::binance::BinanceTradeHistory;
+pub use self::pass_through::PassThroughTradeHistory;
 
-/// Common message for new data input for all filter actors.
-#[derive(Message)]
+/// Common message for new data input for all filter actors. Resolves once whatever got
+/// through the filter has been stored, to how many items were inserted.
 pub struct UnfilteredTradeHistory {
     asset_pair: asset::Pair,
     history: Vec<trade::TradeHistoryItem>,
@@ -23,3 +25,7 @@ impl UnfilteredTradeHistory {
         }
     }
 }
+
+impl Message for UnfilteredTradeHistory {
+    type Result = Result<u64, String>;
+}
@@ -0,0 +1,69 @@
+//! Filter for exchanges whose trade history is stored as it arrives
+
+use futures::{Future, future};
+use actix::prelude::*;
+
+use common::exchange;
+
+use database::{TradeHistoryStorer, NewTradeHistory};
+use super::UnfilteredTradeHistory;
+
+/// Filter for an exchange whose trade history needs no filtering. Everything received goes
+/// straight to the storer.
+pub struct PassThroughTradeHistory {
+    exchange: exchange::Exchange,
+    storer: Addr<TradeHistoryStorer>,
+}
+
+impl PassThroughTradeHistory {
+    pub fn new(exchange: exchange::Exchange, storer: Addr<TradeHistoryStorer>) -> Self {
+        PassThroughTradeHistory {
+            exchange,
+            storer,
+        }
+    }
+}
+
+impl Actor for PassThroughTradeHistory {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Context<Self>) {
+        debug!("{} Trade History filter started.", &self.exchange);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        debug!("{} Trade History filter stopped.", &self.exchange);
+    }
+}
+
+impl Handler<UnfilteredTradeHistory> for PassThroughTradeHistory {
+    type Result = ResponseFuture<u64, String>;
+
+    fn handle(
+        &mut self, msg: UnfilteredTradeHistory, _ctx: &mut Self::Context
+    ) -> Self::Result {
+        let asset_pair = msg.asset_pair;
+        let history = msg.history;
+        let exchange = self.exchange;
+
+        // Only process if there are items.
+        if history.is_empty() {
+            return Box::new(future::ok(0));
+        }
+
+        trace!(
+            "{} new {} {} trade history item(s). Sending to DB Store.",
+            &asset_pair,
+            &history.len(),
+            &exchange,
+        );
+
+        let new_trade_history = NewTradeHistory::new(exchange, asset_pair, history);
+
+        let store_future = self.storer.send(new_trade_history)
+            .map_err(move |e| format!("{} filter can't send to storer! {}", &exchange, &e))
+            .and_then(|stored| stored);
+
+        Box::new(store_future)
+    }
+}
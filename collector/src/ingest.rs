@@ -0,0 +1,240 @@
+//! Streamed trade history. A fetcher keeps a connection open and sends items as they come
+//! instead of PUTting arrays.
+//!
+//! Each line is one item along with a sequence number, `{"seq":1,"item":{...}}`. Sequence
+//! numbers must go up within a connection. Items are stored in batches and each stored
+//! batch is acknowledged with the sequence number of its last item,
+//! `{"ack":1,"received":1}`. Everything up to an acknowledged sequence number has been
+//! stored. Anything that can't be read or stored ends the stream with
+//! `{"error":"...","acked":1}`.
+use futures::Future;
+use actix::Addr;
+use serde_json;
+
+use common::{asset, exchange, trade};
+
+use database::{TradeHistoryStorer, NewTradeHistory};
+use catalogue::{Catalogue, Delivered};
+
+/// Most items stored at once.
+pub const MAX_BATCH: usize = 500;
+
+/// A line longer than this is refused rather than held onto.
+pub const MAX_LINE_BYTES: usize = 65536;
+
+#[derive(Debug, Clone, Deserialize)]
+struct Sequenced {
+    seq: u64,
+    item: trade::TradeHistoryItem,
+}
+
+/// Items to be stored together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    items: Vec<trade::TradeHistoryItem>,
+    last_seq: u64,
+}
+
+impl Batch {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+}
+
+/// What goes back to the fetcher.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Reply {
+    Ack { ack: u64, received: u64 },
+    Refused { error: String, acked: Option<u64> },
+}
+
+impl Reply {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Reply always serializes.")
+    }
+}
+
+/// Reads lines into batches. One per connection.
+#[derive(Debug, Default)]
+pub struct Ingest {
+    partial: Vec<u8>,
+    last_seq: Option<u64>,
+}
+
+impl Ingest {
+    /// Read the complete lines of a chunk of NDJSON. A line cut short is held until the
+    /// rest of it arrives.
+    pub fn chunk(&mut self, chunk: &[u8]) -> Result<Vec<Batch>, String> {
+        self.partial.extend_from_slice(chunk);
+        let complete = match self.partial.iter().rposition(|b| *b == b'\n') {
+            Some(end) => {
+                let rest = self.partial.split_off(end + 1);
+                ::std::mem::replace(&mut self.partial, rest)
+            },
+            None => Vec::new(),
+        };
+
+        if self.partial.len() > MAX_LINE_BYTES {
+            return Err(format!("Line longer than {} bytes.", MAX_LINE_BYTES));
+        }
+
+        self.lines(&complete)
+    }
+
+    /// The stream has ended. Whatever is left is the last line.
+    pub fn finish(&mut self) -> Result<Vec<Batch>, String> {
+        let rest = ::std::mem::replace(&mut self.partial, Vec::new());
+        self.lines(&rest)
+    }
+
+    /// Every line of a websocket frame is complete.
+    pub fn frame(&mut self, text: &str) -> Result<Vec<Batch>, String> {
+        let mut batches = self.chunk(text.as_bytes())?;
+        batches.extend(self.finish()?);
+        Ok(batches)
+    }
+
+    fn lines(&mut self, bytes: &[u8]) -> Result<Vec<Batch>, String> {
+        let mut batches: Vec<Batch> = Vec::new();
+        let lines = bytes
+            .split(|b| *b == b'\n')
+            .filter(|line| line.iter().any(|b| !b.is_ascii_whitespace()));
+
+        for line in lines {
+            let sequenced: Sequenced = serde_json::from_slice(line)
+                .map_err(|e| format!("Unreadable item: {}", &e))?;
+
+            if let Some(last_seq) = self.last_seq {
+                if sequenced.seq <= last_seq {
+                    return Err(format!(
+                        "Sequence number {} doesn't follow {}.", sequenced.seq, last_seq,
+                    ));
+                }
+            }
+            self.last_seq = Some(sequenced.seq);
+
+            let full = batches.last().map(|b| b.len() >= MAX_BATCH).unwrap_or(true);
+            if full {
+                batches.push(Batch { items: Vec::new(), last_seq: sequenced.seq });
+            }
+
+            let batch = batches.last_mut().expect("A batch was just pushed.");
+            batch.items.push(sequenced.item);
+            batch.last_seq = sequenced.seq;
+        }
+
+        Ok(batches)
+    }
+}
+
+/// Where the batches of a connection are stored.
+#[derive(Clone)]
+pub struct Store {
+    storer: Addr<TradeHistoryStorer>,
+    catalogue: Addr<Catalogue>,
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+}
+
+impl Store {
+    pub fn new(
+        storer: Addr<TradeHistoryStorer>,
+        catalogue: Addr<Catalogue>,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+    ) -> Self {
+        Store {
+            storer, catalogue, exchange, asset_pair,
+        }
+    }
+
+    /// Resolves to the acknowledgement once the batch is stored.
+    pub fn store(&self, batch: Batch) -> Box<Future<Item = Reply, Error = String>> {
+        let received = batch.len() as u64;
+        let last_seq = batch.last_seq();
+        let catalogue = self.catalogue.clone();
+        let delivered = Delivered::new(self.exchange, self.asset_pair);
+
+        let message = NewTradeHistory::new(self.exchange, self.asset_pair, batch.items);
+        let future = self.storer
+            .send(message)
+            .map_err(|e| e.to_string())
+            .and_then(|result| result)
+            .map(move |_inserted| {
+                catalogue.do_send(delivered);
+                Reply::Ack { ack: last_seq, received }
+            });
+
+        Box::new(future)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ITEM: &str = r#"{"timestamp":"2018-10-22T09:15:44.562Z","size":"0.25","price":"6458.5","market":"Taker"}"#;
+
+    fn line(seq: u64) -> String {
+        format!("{{\"seq\":{},\"item\":{}}}\n", seq, ITEM)
+    }
+
+    #[test]
+    fn lines_cut_across_chunks() {
+        let mut ingest = Ingest::default();
+        let text = format!("{}{}{}", line(1), line(2), line(3));
+        let (first, second) = text.as_bytes().split_at(text.len() / 2);
+
+        let batches = ingest.chunk(first).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 1);
+        assert_eq!(batches[0].last_seq(), 1);
+
+        let batches = ingest.chunk(second).unwrap();
+        assert_eq!(batches[0].len(), 2);
+        assert_eq!(batches[0].last_seq(), 3);
+
+        // No trailing newline on the last line.
+        let last = line(4);
+        assert!(ingest.chunk(last.trim_right().as_bytes()).unwrap().is_empty());
+        assert_eq!(ingest.finish().unwrap()[0].last_seq(), 4);
+    }
+
+    #[test]
+    fn large_frames_are_split_into_batches() {
+        let mut ingest = Ingest::default();
+        let text: String = (1..MAX_BATCH as u64 + 11).map(line).collect();
+
+        let batches = ingest.frame(&text).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), MAX_BATCH);
+        assert_eq!(batches[0].last_seq(), MAX_BATCH as u64);
+        assert_eq!(batches[1].len(), 10);
+        assert_eq!(batches[1].last_seq(), MAX_BATCH as u64 + 10);
+    }
+
+    #[test]
+    fn refusals() {
+        let mut ingest = Ingest::default();
+        ingest.frame(&line(5)).unwrap();
+        assert!(ingest.frame(&line(5)).is_err());
+        assert!(Ingest::default().frame("{\"seq\":1}").is_err());
+
+        let long = vec![b'x'; MAX_LINE_BYTES + 1];
+        assert!(Ingest::default().chunk(&long).is_err());
+
+        let ack = Reply::Ack { ack: 7, received: 2 };
+        assert_eq!(ack.to_json(), r#"{"ack":7,"received":2}"#);
+        let refused = Reply::Refused { error: "Nope".to_owned(), acked: None };
+        assert_eq!(refused.to_json(), r#"{"error":"Nope","acked":null}"#);
+    }
+}
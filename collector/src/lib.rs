@@ -2,7 +2,6 @@
 #[macro_use] extern crate log;
 #[macro_use] extern crate serde_derive;
 #[macro_use] extern crate actix;
-#[macro_use] extern crate lazy_static;
 extern crate serde;
 extern crate serde_json;
 extern crate actix_web;
@@ -14,7 +13,13 @@ extern crate tokio_timer;
 
 extern crate common;
 extern crate trade_history;
+extern crate ticker_db;
 
 pub mod restful;
 pub mod filter;
 pub mod database;
+pub mod catalogue;
+pub mod allow;
+pub mod ingest;
+pub mod broadcast;
+pub mod term;
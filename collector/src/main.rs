@@ -4,15 +4,20 @@ extern crate env_logger;
 extern crate dotenv;
 extern crate actix;
 extern crate actix_web;
+extern crate chrono;
 
 extern crate common;
 extern crate collector_lib as lib;
 
+use std::collections::HashMap;
+
 use actix::{Actor, SyncArbiter};
 use actix_web::{server::HttpServer, App, http::Method};
 use actix_web::middleware::Logger;
 
-use lib::{restful, filter, database};
+use common::exchange::Exchange;
+
+use lib::{restful, filter, database, catalogue, broadcast, term};
 
 mod config;
 
@@ -29,18 +34,52 @@ fn main() {
     let storer_addr = storer.start();
      */
 
+    let broadcaster_addr = broadcast::Broadcaster::new().start();
+
     let db_url = config.database_url().to_owned();
+    let storer_broadcaster = broadcaster_addr.clone();
     let storer_addr = SyncArbiter::start(config.database_connections() as usize, move || {
-        database::TradeHistoryStorer::new(db_url.as_str())
+        database::TradeHistoryStorer::new(db_url.as_str(), storer_broadcaster.clone())
     });
 
+    if let Some(ticker_db_url) = config.ticker_database_url() {
+        let ticker_db_url = ticker_db_url.to_owned();
+        let tick_storer_addr = SyncArbiter::start(1, move || {
+            database::TickStorer::new(ticker_db_url.as_str())
+        });
+
+        let term_calculator = term::TermCalculator::new(
+            config.term_settings().clone(),
+            broadcaster_addr.clone(),
+            storer_addr.clone(),
+            tick_storer_addr,
+        );
+        term_calculator.start();
+    } else {
+        info!("No ticker database set. Ticks won't be calculated.");
+    }
+
+    let mut filters = HashMap::new();
     let kraken_filter = filter::KrakenTradeHistory::new(storer_addr.clone());
-    let kf_addr = kraken_filter.start();
+    filters.insert(Exchange::Kraken, kraken_filter.start().recipient());
+
+    for &exchange in &[
+        Exchange::Binance, Exchange::Coinbase, Exchange::Bitstamp, Exchange::Bitfinex,
+    ] {
+        let filter = filter::PassThroughTradeHistory::new(exchange, storer_addr.clone());
+        filters.insert(exchange, filter.start().recipient());
+    }
 
-    let binance_filter = filter::BinanceTradeHistory::new(storer_addr);
-    let bf_addr = binance_filter.start();
+    let catalogue = catalogue::Catalogue::new(storer_addr.clone());
+    let catalogue_addr = catalogue.start();
 
-    let rest_state = lib::restful::State::new(kf_addr, bf_addr);
+    let rest_state = lib::restful::State::new(
+        filters,
+        storer_addr,
+        catalogue_addr,
+        broadcaster_addr,
+        config.allow_list().clone(),
+    );
 
     HttpServer::new(move || {
         App::with_state(rest_state.clone())
@@ -57,9 +96,31 @@ fn main() {
                         r.method(Method::GET).f(restful::trade_match_asset_pair)
                     })
                     .resource("/{left_asset}/{right_asset}/{exchange}", |r| {
-                        r.method(Method::PUT).f(restful::trade_match_put)
+                        r.method(Method::PUT).f(restful::trade_match_put);
+                        r.method(Method::POST).f(restful::trade_match_stream);
+                    })
+                    .resource("/{left_asset}/{right_asset}/{exchange}/ws", |r| {
+                        r.method(Method::GET).f(restful::trade_match_socket)
                     })
             })
+            .scope("/live", |scope| {
+                scope
+                    .resource("/trades", |r| {
+                        r.method(Method::GET).f(restful::live_trades)
+                    })
+                    .resource("/trades/ws", |r| {
+                        r.method(Method::GET).f(restful::live_trades_socket)
+                    })
+            })
+            .resource("/candles/{left_asset}/{right_asset}/{exchange}", |r| {
+                r.method(Method::PUT).f(restful::candle_put)
+            })
+            .resource("/aggregate_trades/{left_asset}/{right_asset}/{exchange}", |r| {
+                r.method(Method::PUT).f(restful::aggregate_trade_put)
+            })
+            .resource("/order_books/{left_asset}/{right_asset}/{exchange}", |r| {
+                r.method(Method::PUT).f(restful::order_book_put)
+            })
     })
         .bind(config.listen())
         .expect("Can't bind address.")
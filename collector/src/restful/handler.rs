@@ -1,19 +1,29 @@
 //! Handlers for the RESTful resources
-use futures::sync::oneshot;
-use futures::{Stream, Future, future};
-use actix::Arbiter;
+use futures::sync::{oneshot, mpsc};
+use futures::{Stream, Future, future, stream};
+use actix::{Arbiter, MailboxError};
 use actix_web::{
-    HttpRequest, HttpResponse, HttpMessage, Responder, error, AsyncResponder,
+    HttpRequest, HttpResponse, HttpMessage, Responder, error, AsyncResponder, ws,
 };
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use serde_json;
 
-use common::trade::TradeHistoryItem;
+use common::trade::{TradeHistoryItem, AggregateTrade};
+use common::candle::Candle;
+use common::book::BookEvent;
 use common::exchange::Exchange;
 use common::asset::{self, Asset};
 
 use super::State;
+use super::ingest::{Acks, IngestSocket};
+use super::live::LiveSocket;
 use filter::UnfilteredTradeHistory;
+use database::{NewCandles, NewAggregateTrades, NewBookEvents};
+use catalogue::{Delivered, ReqSummary, ReqRightAssets, ReqExchanges};
+use ingest::{Ingest, Store};
+use broadcast::{Subscription, Subscribe, LIVE_BUFFER};
 
 const PAYLOAD_4MB: usize = 4194304;
 
@@ -48,41 +58,108 @@ impl TradeHistoryResponse {
     }
 }
 
-pub fn trade_match_root(_req: &HttpRequest<State>) -> impl Responder {
-    // TODO
+/// An exchange sent an asset pair it isn't allowed to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Rejected {
+    exchange: String,
+    assetpair: String,
+}
+
+impl Rejected {
+    fn new(exchange: Exchange, asset_pair: asset::Pair) -> Self {
+        Rejected {
+            exchange: exchange.as_str().to_owned(),
+            assetpair: asset_pair.to_string(),
+        }
+    }
+}
 
-    // Return a static string for now.
-    let json = r##"[{"assetpair":"BTC/USD","exchanges":["kraken"]}]"##;
-    HttpResponse::Ok().body(json)
+/// Turn away what the exchange isn't allowed to send for the asset pair with a 422.
+fn permit(
+    state: &State, exchange: Exchange, asset_pair: asset::Pair, what: &str,
+) -> Result<(), HttpResponse> {
+    if state.allow_list().permits(exchange, asset_pair) {
+        return Ok(());
+    }
+
+    warn!("Rejected {} {} from {}.", &asset_pair, what, &exchange);
+    let rejected = Rejected::new(exchange, asset_pair);
+    Err(HttpResponse::UnprocessableEntity().json(rejected))
+}
+
+/// Unlike `parse_path_segment!`, an asset that can't be parsed is one we don't know about.
+macro_rules! parse_asset_segment {
+    ($segment:expr) => {
+        match $segment.parse() {
+            Ok(s) => s,
+            Err(_e) => return Box::new(future::ok(HttpResponse::NotFound().finish())),
+        };
+    };
 }
 
-pub fn trade_match_left_asset(req: &HttpRequest<State>) -> impl Responder {
-    let _lasset = req.match_info().get("left_asset")
+/// Answer with the JSON body of a catalogue lookup. Nothing in the catalogue is a 404.
+fn catalogue_response<T: Serialize>(
+    result: Result<Option<T>, MailboxError>
+) -> Result<HttpResponse, error::Error> {
+    match result {
+        Ok(Some(body)) => Ok(HttpResponse::Ok().json(body)),
+        Ok(None) => Ok(HttpResponse::NotFound().finish()),
+        Err(e) => {
+            error!("Can't query catalogue: {}", &e);
+            Ok(HttpResponse::InternalServerError().finish())
+        },
+    }
+}
+
+/// Every asset pair along with the exchanges that have delivered trade history for it.
+pub fn trade_match_root(
+    req: &HttpRequest<State>
+) -> Box<Future<Item = HttpResponse, Error = error::Error>> {
+    req.state().catalogue()
+        .send(ReqSummary)
+        .then(|result| catalogue_response(result.map(Some)))
+        .responder()
+}
+
+/// The right side assets that have trade history for the left asset.
+pub fn trade_match_left_asset(
+    req: &HttpRequest<State>
+) -> Box<Future<Item = HttpResponse, Error = error::Error>> {
+    let lasset = req.match_info().get("left_asset")
         .expect("Invalid use of function. Need to have {left_asset} on path.");
 
-    // TODO
-    // Match with state on valid asset or not
-    //println!("Left Asset: {}", &lasset);
+    let left_asset: Asset = parse_asset_segment!(lasset);
 
-    // Return a static string for now.
-    let json = r##"["USD"]"##;
-    HttpResponse::Ok().body(json)
+    req.state().catalogue()
+        .send(ReqRightAssets(left_asset))
+        .then(catalogue_response)
+        .responder()
 }
 
-pub fn trade_match_asset_pair(req: &HttpRequest<State>) -> impl Responder {
-    let _lasset = req.match_info().get("left_asset")
+/// The exchanges that have delivered trade history for the asset pair.
+pub fn trade_match_asset_pair(
+    req: &HttpRequest<State>
+) -> Box<Future<Item = HttpResponse, Error = error::Error>> {
+    let params = req.match_info();
+    let lasset = params.get("left_asset")
         .expect("Invalid use of function. Need to have {left_asset} on path.");
-    let _rasset = req.match_info().get("right_asset")
+    let rasset = params.get("right_asset")
         .expect("Invalid use of function. Need to have {right_asset} on path.");
 
-    // TODO
-    // Match with state on valid assets or not. Then check that the pair is valid.
-    //println!("Left Asset: {}", &lasset);
-    //println!("Right Asset: {}", &rasset);
+    let left_asset: Asset = parse_asset_segment!(lasset);
+    let right_asset: Asset = parse_asset_segment!(rasset);
+
+    let asset_pair = asset::Pair::new(left_asset, right_asset);
 
-    // Return a static string for now.
-    let json = r##"["kraken", "binance"]"##;
-    HttpResponse::Ok().body(json)
+    req.state().catalogue()
+        .send(ReqExchanges(asset_pair))
+        .then(|result| catalogue_response(result.map(|exchanges| {
+            exchanges.map(|exchanges| exchanges
+                .iter()
+                .map(|e| e.as_str().to_owned())
+                .collect::<Vec<String>>())
+        })))
+        .responder()
 }
 
 pub fn trade_match_put(
@@ -96,23 +173,23 @@ pub fn trade_match_put(
     let exchange = params.get("exchange")
         .expect("Invalid use of function. Need to have {exchange} on path.");
 
-    // TODO
-    // Match with state on valid assets or not. Then check that the pair is valid. Then
-    // check if the exchange is valid.
-    //println!("Left Asset: {}", &lasset);
-    //println!("Right Asset: {}", &rasset);
-    //println!("Exchange: {}", &exchange);
-    
     let left_asset: Asset = parse_path_segment!(lasset);
     let right_asset: Asset = parse_path_segment!(rasset);
     let exchange: Exchange = parse_path_segment!(exchange);
 
     let asset_pair = asset::Pair::new(left_asset, right_asset);
 
-    // TODO
-    // Validate that the exchange/asset_pair is valid.
+    if let Err(rejected) = permit(req.state(), exchange, asset_pair, "trade history") {
+        return Box::new(future::ok(rejected));
+    }
 
-    //let k_filter = req.state().kraken_filter().clone();
+    let filter = match req.state().filter(exchange) {
+        Some(filter) => filter.clone(),
+        None => {
+            error!("No trade history filter for {}.", &exchange);
+            return Box::new(future::ok(HttpResponse::InternalServerError().finish()));
+        },
+    };
     let state = req.state().clone();
 
     // Now grab the raw JSON data and deal with it.
@@ -136,35 +213,34 @@ pub fn trade_match_put(
         .and_then(move |history| {
             // Count the number of records (we'll return the count in the response).
             let count = history.len();
+            let catalogue = state.catalogue().clone();
 
             // Forward the deserialized data off to the filter.
             let message = UnfilteredTradeHistory::new(asset_pair, history);
             let (inform, callback) = oneshot::channel();
-            match exchange {
-                Exchange::Kraken => {
-                    let future = state.kraken_filter()
-                        .send(message)
-                        .then(move |result| inform.send(result))
-                        .map_err(|e| error!("Can't inform filter response: {:?}", &e));
-                    Arbiter::spawn(future);
-                },
-                Exchange::Binance => {
-                    let future = state.binance_filter()
-                        .send(message)
-                        .then(move |result| inform.send(result))
-                        .map_err(|e| error!("Can't inform filter response: {:?}", &e));
-                    Arbiter::spawn(future);
-                },
-            }
+            let future = filter
+                .send(message)
+                .then(move |result| inform.send(result))
+                .map_err(|e| error!("Can't inform filter response: {:?}", &e));
+            Arbiter::spawn(future);
 
             callback                
                 .then(move |result| match result {
                     Ok(result) => match result {
-                        Ok(()) => {
+                        // Only once the items are committed.
+                        Ok(Ok(_inserted)) => {
+                            if count > 0 {
+                                catalogue.do_send(Delivered::new(exchange, asset_pair));
+                            }
+
                             // Return the count of records received to the client.
                             let received = TradeHistoryResponse::new(count as u64);
                             Ok(HttpResponse::Ok().json(received))
                         },
+                        Ok(Err(e)) => {
+                            error!("Can't store {} trade history: {}", &asset_pair, &e);
+                            Ok(HttpResponse::InternalServerError().finish())
+                        },
                         Err(e) => {
                             error!("Actix error: {}", &e);
                             // TODO: Make the origin clearer.
@@ -180,3 +256,318 @@ pub fn trade_match_put(
         })
         .responder()
 }
+
+/// Work out where streamed trade history goes. Refuses pairs the exchange isn't allowed.
+fn stream_store(req: &HttpRequest<State>) -> Result<Store, HttpResponse> {
+    let params = req.match_info();
+    let lasset = params.get("left_asset")
+        .expect("Invalid use of function. Need to have {left_asset} on path.");
+    let rasset = params.get("right_asset")
+        .expect("Invalid use of function. Need to have {right_asset} on path.");
+    let exchange = params.get("exchange")
+        .expect("Invalid use of function. Need to have {exchange} on path.");
+
+    fn bad_request<E>(_e: E) -> HttpResponse {
+        HttpResponse::BadRequest().finish()
+    }
+
+    let left_asset: Asset = lasset.parse().map_err(bad_request)?;
+    let right_asset: Asset = rasset.parse().map_err(bad_request)?;
+    let exchange: Exchange = exchange.parse().map_err(bad_request)?;
+
+    let asset_pair = asset::Pair::new(left_asset, right_asset);
+
+    permit(req.state(), exchange, asset_pair, "trade history stream")?;
+
+    let state = req.state();
+    Ok(Store::new(state.storer().clone(), state.catalogue().clone(), exchange, asset_pair))
+}
+
+/// Trade history streamed in as chunked NDJSON. Acknowledgements are streamed back as they
+/// are stored.
+pub fn trade_match_stream(req: &HttpRequest<State>) -> HttpResponse {
+    let store = match stream_store(req) {
+        Ok(store) => store,
+        Err(response) => return response,
+    };
+
+    let mut ingest = Ingest::default();
+    let batches = req.payload()
+        .map(Some)
+        .chain(stream::once(Ok(None)))
+        .map_err(|e| e.to_string())
+        .and_then(move |chunk| match chunk {
+            Some(chunk) => ingest.chunk(&chunk),
+            None => ingest.finish(),
+        })
+        .map(stream::iter_ok::<_, String>)
+        .flatten();
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(Acks::new(batches, store))
+}
+
+/// Trade history streamed in over a websocket.
+pub fn trade_match_socket(req: &HttpRequest<State>) -> Result<HttpResponse, error::Error> {
+    match stream_store(req) {
+        Ok(store) => ws::start(req, IngestSocket::new(store)),
+        Err(response) => Ok(response),
+    }
+}
+
+/// Read the JSON body of the request up to the payload limit.
+fn json_body<T>(req: &HttpRequest<State>) -> impl Future<Item = T, Error = error::Error>
+where T: DeserializeOwned + 'static
+{
+    req.payload()
+        .from_err()
+        .fold(BytesMut::new(), move |mut body, chunk| {
+            if (body.len() + chunk.len()) > PAYLOAD_4MB {
+                Err(error::ErrorBadRequest("overflow"))
+            } else {
+                body.extend_from_slice(&chunk);
+                Ok(body)
+            }
+        })
+        .and_then(|body| {
+            let items: T = serde_json::from_slice(&body)?;
+            Ok(items)
+        })
+}
+
+/// Candles computed by the exchange. These are stored as is next to the trade history.
+pub fn candle_put(
+    req: &HttpRequest<State>
+) -> Box<Future<Item = HttpResponse, Error = error::Error>> {
+    let params = req.match_info();
+    let lasset = params.get("left_asset")
+        .expect("Invalid use of function. Need to have {left_asset} on path.");
+    let rasset = params.get("right_asset")
+        .expect("Invalid use of function. Need to have {right_asset} on path.");
+    let exchange = params.get("exchange")
+        .expect("Invalid use of function. Need to have {exchange} on path.");
+
+    let left_asset: Asset = parse_path_segment!(lasset);
+    let right_asset: Asset = parse_path_segment!(rasset);
+    let exchange: Exchange = parse_path_segment!(exchange);
+
+    let asset_pair = asset::Pair::new(left_asset, right_asset);
+    if let Err(rejected) = permit(req.state(), exchange, asset_pair, "candles") {
+        return Box::new(future::ok(rejected));
+    }
+
+    let state = req.state().clone();
+
+    json_body(req)
+        .and_then(move |candles: Vec<Candle>| {
+            let count = candles.len();
+            let message = NewCandles::new(exchange, asset_pair, candles);
+            state.storer()
+                .send(message)
+                .then(move |result| match result {
+                    Ok(Ok(_inserted)) => {
+                        let received = TradeHistoryResponse::new(count as u64);
+                        Ok(HttpResponse::Ok().json(received))
+                    },
+                    Ok(Err(e)) => {
+                        error!("Can't store {} candles: {}", &asset_pair, &e);
+                        Ok(HttpResponse::InternalServerError().finish())
+                    },
+                    Err(e) => {
+                        error!("Can't send candles to storer: {}", &e);
+                        Ok(HttpResponse::InternalServerError().finish())
+                    },
+                })
+        })
+        .responder()
+}
+
+/// Aggregate trades. Stored as is in their own table.
+pub fn aggregate_trade_put(
+    req: &HttpRequest<State>
+) -> Box<Future<Item = HttpResponse, Error = error::Error>> {
+    let params = req.match_info();
+    let lasset = params.get("left_asset")
+        .expect("Invalid use of function. Need to have {left_asset} on path.");
+    let rasset = params.get("right_asset")
+        .expect("Invalid use of function. Need to have {right_asset} on path.");
+    let exchange = params.get("exchange")
+        .expect("Invalid use of function. Need to have {exchange} on path.");
+
+    let left_asset: Asset = parse_path_segment!(lasset);
+    let right_asset: Asset = parse_path_segment!(rasset);
+    let exchange: Exchange = parse_path_segment!(exchange);
+
+    let asset_pair = asset::Pair::new(left_asset, right_asset);
+    if let Err(rejected) = permit(req.state(), exchange, asset_pair, "aggregate trades") {
+        return Box::new(future::ok(rejected));
+    }
+
+    let state = req.state().clone();
+
+    json_body(req)
+        .and_then(move |aggregates: Vec<AggregateTrade>| {
+            let count = aggregates.len();
+            let message = NewAggregateTrades::new(exchange, asset_pair, aggregates);
+            state.storer()
+                .send(message)
+                .then(move |result| match result {
+                    Ok(Ok(_inserted)) => {
+                        let received = TradeHistoryResponse::new(count as u64);
+                        Ok(HttpResponse::Ok().json(received))
+                    },
+                    Ok(Err(e)) => {
+                        error!("Can't store {} aggregate trades: {}", &asset_pair, &e);
+                        Ok(HttpResponse::InternalServerError().finish())
+                    },
+                    Err(e) => {
+                        error!("Can't send aggregate trades to storer: {}", &e);
+                        Ok(HttpResponse::InternalServerError().finish())
+                    },
+                })
+        })
+        .responder()
+}
+
+/// Synchronised order book snapshots and deltas. Stored as is in their own table.
+pub fn order_book_put(
+    req: &HttpRequest<State>
+) -> Box<Future<Item = HttpResponse, Error = error::Error>> {
+    let params = req.match_info();
+    let lasset = params.get("left_asset")
+        .expect("Invalid use of function. Need to have {left_asset} on path.");
+    let rasset = params.get("right_asset")
+        .expect("Invalid use of function. Need to have {right_asset} on path.");
+    let exchange = params.get("exchange")
+        .expect("Invalid use of function. Need to have {exchange} on path.");
+
+    let left_asset: Asset = parse_path_segment!(lasset);
+    let right_asset: Asset = parse_path_segment!(rasset);
+    let exchange: Exchange = parse_path_segment!(exchange);
+
+    let asset_pair = asset::Pair::new(left_asset, right_asset);
+    if let Err(rejected) = permit(req.state(), exchange, asset_pair, "order book events") {
+        return Box::new(future::ok(rejected));
+    }
+
+    let state = req.state().clone();
+
+    json_body(req)
+        .and_then(move |events: Vec<BookEvent>| {
+            let count = events.len();
+            let message = NewBookEvents::new(exchange, asset_pair, events);
+            state.storer()
+                .send(message)
+                .then(move |result| match result {
+                    Ok(Ok(_inserted)) => {
+                        let received = TradeHistoryResponse::new(count as u64);
+                        Ok(HttpResponse::Ok().json(received))
+                    },
+                    Ok(Err(e)) => {
+                        error!("Can't store {} order book events: {}", &asset_pair, &e);
+                        Ok(HttpResponse::InternalServerError().finish())
+                    },
+                    Err(e) => {
+                        error!("Can't send order book events to storer: {}", &e);
+                        Ok(HttpResponse::InternalServerError().finish())
+                    },
+                })
+        })
+        .responder()
+}
+
+/// Subscribe to the live trades picked out by the query parameters.
+fn subscribe(req: &HttpRequest<State>) -> Result<mpsc::Receiver<String>, HttpResponse> {
+    let subscription = Subscription::from_query(&req.query())
+        .map_err(|e| HttpResponse::BadRequest().body(e))?;
+
+    let (sender, receiver) = mpsc::channel(LIVE_BUFFER);
+    req.state().broadcaster().do_send(Subscribe::new(subscription, sender));
+    Ok(receiver)
+}
+
+/// Live trades as server sent events. One event per stored batch.
+pub fn live_trades(req: &HttpRequest<State>) -> HttpResponse {
+    let receiver = match subscribe(req) {
+        Ok(receiver) => receiver,
+        Err(response) => return response,
+    };
+
+    let events = receiver
+        .map(|json| Bytes::from(format!("data: {}\n\n", json)))
+        .map_err(|()| error::ErrorInternalServerError("Live trades failed."));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(events)
+}
+
+/// Live trades over a websocket. One text frame per stored batch.
+pub fn live_trades_socket(req: &HttpRequest<State>) -> Result<HttpResponse, error::Error> {
+    match subscribe(req) {
+        Ok(receiver) => ws::start(req, LiveSocket::new(receiver)),
+        Err(response) => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use actix::{Actor, SyncArbiter};
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::test::TestServer;
+
+    use allow::AllowList;
+    use broadcast::Broadcaster;
+    use catalogue::Catalogue;
+    use database::TradeHistoryStorer;
+    use super::*;
+
+    /// Nothing listens here. The storer only logs that it can't connect.
+    static UNREACHABLE_DB: &str = "postgres://127.0.0.1:1/trade_history";
+
+    fn state() -> State {
+        let broadcaster = Broadcaster::new().start();
+        let storer_broadcaster = broadcaster.clone();
+        let storer = SyncArbiter::start(1, move || {
+            TradeHistoryStorer::new(UNREACHABLE_DB, storer_broadcaster.clone())
+        });
+        let catalogue = Catalogue::new(storer.clone()).start();
+        let allow_list: AllowList = "kraken=BTC/USD".parse().unwrap();
+
+        State::new(HashMap::new(), storer, catalogue, broadcaster, allow_list)
+    }
+
+    #[test]
+    fn disallowed_pairs_are_unprocessable() {
+        let mut server = TestServer::build_with_state(state).start(|app| {
+            app.resource("/trade_history/{left_asset}/{right_asset}/{exchange}", |r| {
+                r.method(Method::PUT).f(trade_match_put)
+            });
+            app.resource("/candles/{left_asset}/{right_asset}/{exchange}", |r| {
+                r.method(Method::PUT).f(candle_put)
+            });
+            app.resource("/aggregate_trades/{left_asset}/{right_asset}/{exchange}", |r| {
+                r.method(Method::PUT).f(aggregate_trade_put)
+            });
+            app.resource("/order_books/{left_asset}/{right_asset}/{exchange}", |r| {
+                r.method(Method::PUT).f(order_book_put)
+            });
+        });
+
+        for resource in &["trade_history", "candles", "aggregate_trades", "order_books"] {
+            let path = format!("/{}/ETH/USD/kraken", resource);
+            let request = server.client(Method::PUT, &path).body("[]").unwrap();
+            let response = server.execute(request.send()).unwrap();
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY, "{}", &path);
+
+            let body = server.execute(response.body()).unwrap();
+            let rejected: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(rejected["exchange"], "kraken");
+            assert_eq!(rejected["assetpair"], "ETH/USD");
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Connections that stream trade history in. See `ingest` for what goes over them.
+use futures::{Future, Stream, Async, Poll, stream, future};
+use futures::future::Either;
+use actix::prelude::*;
+use actix_web::{ws, error};
+use bytes::Bytes;
+
+use ingest::{Batch, Ingest, Reply, Store};
+
+use super::State;
+
+/// Stores the batches as they are read and turns them into acknowledgement lines. Ends
+/// after the first refusal.
+pub struct Acks<S> {
+    batches: S,
+    store: Store,
+    storing: Option<Box<Future<Item = Reply, Error = String>>>,
+    acked: Option<u64>,
+    done: bool,
+}
+
+impl<S> Acks<S> {
+    pub fn new(batches: S, store: Store) -> Self {
+        Acks {
+            batches,
+            store,
+            storing: None,
+            acked: None,
+            done: false,
+        }
+    }
+
+    fn refuse(&mut self, error: String) -> Bytes {
+        warn!("Refusing streamed trade history: {}", &error);
+        self.done = true;
+        let refused = Reply::Refused { error, acked: self.acked };
+        Bytes::from(refused.to_json() + "\n")
+    }
+}
+
+impl<S> Stream for Acks<S> where S: Stream<Item = Batch, Error = String> {
+    type Item = Bytes;
+    type Error = error::Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, error::Error> {
+        loop {
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+
+            if let Some(mut storing) = self.storing.take() {
+                return match storing.poll() {
+                    Ok(Async::NotReady) => {
+                        self.storing = Some(storing);
+                        Ok(Async::NotReady)
+                    },
+                    Ok(Async::Ready(reply)) => {
+                        if let Reply::Ack { ack, .. } = reply {
+                            self.acked = Some(ack);
+                        }
+                        Ok(Async::Ready(Some(Bytes::from(reply.to_json() + "\n"))))
+                    },
+                    Err(e) => Ok(Async::Ready(Some(self.refuse(e)))),
+                };
+            }
+
+            match self.batches.poll() {
+                Ok(Async::Ready(Some(batch))) => {
+                    self.storing = Some(self.store.store(batch));
+                },
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => return Ok(Async::Ready(Some(self.refuse(e)))),
+            }
+        }
+    }
+}
+
+/// A websocket that streams trade history in. Each text frame holds one or more lines.
+pub struct IngestSocket {
+    ingest: Ingest,
+    store: Store,
+    acked: Option<u64>,
+}
+
+impl IngestSocket {
+    pub fn new(store: Store) -> Self {
+        IngestSocket {
+            ingest: Ingest::default(),
+            store,
+            acked: None,
+        }
+    }
+
+    fn refuse(&mut self, error: String, ctx: &mut ws::WebsocketContext<Self, State>) {
+        warn!("Refusing streamed trade history: {}", &error);
+        let refused = Reply::Refused { error, acked: self.acked };
+        ctx.text(refused.to_json());
+        ctx.close(Some(ws::CloseCode::Invalid.into()));
+        ctx.stop();
+    }
+
+    /// Batches of a frame are stored one after the other. No more incoming frames are
+    /// handled until they have been.
+    fn on_text(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self, State>) {
+        let batches = match self.ingest.frame(text) {
+            Ok(batches) => batches,
+            Err(e) => return self.refuse(e, ctx),
+        };
+
+        let store = self.store.clone();
+        let storing = stream::iter_ok::<_, ()>(batches)
+            .fold((Vec::new(), false), move |(mut replies, failed), batch| {
+                if failed {
+                    return Either::A(future::ok((replies, failed)));
+                }
+
+                Either::B(store.store(batch).then(move |result| {
+                    let failed = result.is_err();
+                    replies.push(result);
+                    Ok((replies, failed))
+                }))
+            });
+
+        let replying = storing
+            .into_actor(self)
+            .map(|(replies, _), act, ctx| {
+                for reply in replies {
+                    match reply {
+                        Ok(Reply::Ack { ack, received }) => {
+                            act.acked = Some(ack);
+                            ctx.text(Reply::Ack { ack, received }.to_json());
+                        },
+                        Ok(refused) => ctx.text(refused.to_json()),
+                        Err(e) => act.refuse(e, ctx),
+                    }
+                }
+            });
+
+        ctx.wait(replying);
+    }
+}
+
+impl Actor for IngestSocket {
+    type Context = ws::WebsocketContext<Self, State>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        debug!("Trade history socket opened.");
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        debug!("Trade history socket closed. Last acknowledged: {:?}", &self.acked);
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for IngestSocket {
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        match msg {
+            ws::Message::Text(text) => self.on_text(&text, ctx),
+            ws::Message::Ping(ping) => ctx.pong(&ping),
+            ws::Message::Close(_) => ctx.stop(),
+            _ => (),
+        }
+    }
+}
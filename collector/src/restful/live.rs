@@ -0,0 +1,61 @@
+//! Connections that live trades are sent out over. See `broadcast` for what goes over them.
+use futures::sync::mpsc;
+use actix::prelude::*;
+use actix_web::ws;
+
+use super::State;
+
+/// A websocket that live trades are sent down as text frames. Closed once the subscriber
+/// has been dropped by the broadcaster.
+pub struct LiveSocket {
+    receiver: Option<mpsc::Receiver<String>>,
+}
+
+impl LiveSocket {
+    pub fn new(receiver: mpsc::Receiver<String>) -> Self {
+        LiveSocket {
+            receiver: Some(receiver),
+        }
+    }
+}
+
+impl Actor for LiveSocket {
+    type Context = ws::WebsocketContext<Self, State>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(receiver) = self.receiver.take() {
+            ctx.add_stream(receiver);
+        }
+        debug!("Live trade socket opened.");
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        debug!("Live trade socket closed.");
+    }
+}
+
+impl StreamHandler<String, ()> for LiveSocket {
+    fn handle(&mut self, json: String, ctx: &mut Self::Context) {
+        ctx.text(json);
+    }
+
+    /// The broadcaster let go. Either it stopped or this socket couldn't keep up.
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        let reason = ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some("Live trades ended.".to_owned()),
+        };
+        ctx.close(Some(reason));
+        ctx.stop();
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for LiveSocket {
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        match msg {
+            ws::Message::Ping(ping) => ctx.pong(&ping),
+            ws::Message::Close(_) => ctx.stop(),
+            _ => (),
+        }
+    }
+}
@@ -2,6 +2,8 @@
 
 mod handler;
 mod state;
+mod ingest;
+mod live;
 
 pub use self::state::State;
 
@@ -10,4 +12,11 @@ pub use self::handler::{
     trade_match_left_asset,
     trade_match_asset_pair,
     trade_match_put,
+    trade_match_stream,
+    trade_match_socket,
+    candle_put,
+    aggregate_trade_put,
+    order_book_put,
+    live_trades,
+    live_trades_socket,
 };
@@ -1,30 +1,65 @@
 //! Shared state for all the handlers.
 
-use actix::Addr;
+use std::collections::HashMap;
 
-use filter;
+use actix::{Addr, Recipient};
+
+use common::exchange::Exchange;
+
+use filter::UnfilteredTradeHistory;
+use database;
+use catalogue;
+use broadcast;
+use allow::AllowList;
 
 #[derive(Clone)]
 pub struct State {
-    kraken_filter: Addr<filter::KrakenTradeHistory>,
-    binance_filter: Addr<filter::BinanceTradeHistory>,
+    filters: HashMap<Exchange, Recipient<UnfilteredTradeHistory>>,
+    storer: Addr<database::TradeHistoryStorer>,
+    catalogue: Addr<catalogue::Catalogue>,
+    broadcaster: Addr<broadcast::Broadcaster>,
+    allow_list: AllowList,
 }
 
 impl State {
     pub fn new(
-        kraken_filter: Addr<filter::KrakenTradeHistory>,
-        binance_filter: Addr<filter::BinanceTradeHistory>,
+        filters: HashMap<Exchange, Recipient<UnfilteredTradeHistory>>,
+        storer: Addr<database::TradeHistoryStorer>,
+        catalogue: Addr<catalogue::Catalogue>,
+        broadcaster: Addr<broadcast::Broadcaster>,
+        allow_list: AllowList,
     ) -> Self {
         State {
-            kraken_filter, binance_filter
+            filters,
+            storer,
+            catalogue,
+            broadcaster,
+            allow_list,
         }
     }
 
-    pub fn kraken_filter(&self) -> &Addr<filter::KrakenTradeHistory> {
-        &self.kraken_filter
+    /// Where trade history from the exchange goes to be filtered before it is stored.
+    pub fn filter(&self, exchange: Exchange) -> Option<&Recipient<UnfilteredTradeHistory>> {
+        self.filters.get(&exchange)
+    }
+
+    /// Data that doesn't need filtering goes straight to the storer.
+    pub fn storer(&self) -> &Addr<database::TradeHistoryStorer> {
+        &self.storer
+    }
+
+    /// What has been delivered by whom.
+    pub fn catalogue(&self) -> &Addr<catalogue::Catalogue> {
+        &self.catalogue
+    }
+
+    /// Where live trade subscribers sign up.
+    pub fn broadcaster(&self) -> &Addr<broadcast::Broadcaster> {
+        &self.broadcaster
     }
 
-    pub fn binance_filter(&self) -> &Addr<filter::BinanceTradeHistory> {
-        &self.binance_filter
+    /// Which exchanges may send which asset pairs.
+    pub fn allow_list(&self) -> &AllowList {
+        &self.allow_list
     }
 }
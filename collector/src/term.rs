@@ -0,0 +1,471 @@
+//! Term calculation. Newly stored trades are rolled up into ticks, one per exchange, asset
+//! pair and resolution. A tick is written once its term has ended and the grace period
+//! for late trades after it has passed.
+//!
+//! Trades that arrive after their term has been written are too late. They are counted and
+//! left out. Terms that ended before the calculator started count as written. Those still
+//! open are rebuilt from the stored trades on start so a restart doesn't cut them short.
+//!
+//! A tick that can't be written is kept and tried again on the next check.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc, TimeZone};
+use futures::Future;
+use rust_decimal::Decimal;
+use actix::prelude::*;
+
+use common::{asset, exchange};
+use ticker_db::model::FreshTick;
+use trade_history::model::TradeItem;
+
+use broadcast::{Broadcaster, Forward, Stored};
+use database::{TradeHistoryStorer, ReqTradesSince, TickStorer, NewTick};
+
+/// How often terms are checked for having ended.
+const CLOSE_INTERVAL: u64 = 1;
+
+/// Lengths of the terms and how long to wait for late trades after one ends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermSettings {
+    resolutions: Vec<Duration>,
+    grace: Duration,
+}
+
+impl TermSettings {
+    pub fn new(resolutions: Vec<Duration>, grace: Duration) -> Self {
+        TermSettings {
+            resolutions, grace,
+        }
+    }
+
+    pub fn resolutions(&self) -> &[Duration] {
+        &self.resolutions
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+}
+
+/// Trades of a single term so far.
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    first: (DateTime<Utc>, Decimal, Decimal),
+    last: (DateTime<Utc>, Decimal, Decimal),
+    highest: (Decimal, Decimal),
+    lowest: (Decimal, Decimal),
+    count: i32,
+}
+
+impl Term {
+    fn new(trade: &TradeItem) -> Self {
+        let (timestamp, price, size) = (*trade.timestamp(), *trade.price(), *trade.size());
+        Term {
+            first: (timestamp, price, size),
+            last: (timestamp, price, size),
+            highest: (price, size),
+            lowest: (price, size),
+            count: 1,
+        }
+    }
+
+    /// Trades can come in out of order. First and last go by the trade timestamps.
+    fn add(&mut self, trade: &TradeItem) {
+        let (timestamp, price, size) = (*trade.timestamp(), *trade.price(), *trade.size());
+        if timestamp < self.first.0 {
+            self.first = (timestamp, price, size);
+        }
+        if timestamp >= self.last.0 {
+            self.last = (timestamp, price, size);
+        }
+        if price > self.highest.0 {
+            self.highest = (price, size);
+        }
+        if price < self.lowest.0 {
+            self.lowest = (price, size);
+        }
+        self.count += 1;
+    }
+
+    fn tick(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> FreshTick {
+        FreshTick::new(
+            exchange,
+            asset_pair,
+            start,
+            end,
+            self.first.1,
+            self.first.2,
+            self.highest.0,
+            self.highest.1,
+            self.lowest.0,
+            self.lowest.1,
+            self.last.1,
+            self.last.2,
+            self.count,
+        )
+    }
+}
+
+/// The open terms of an exchange, asset pair and resolution.
+#[derive(Debug)]
+struct Terms {
+    /// Keyed by the start of the term in seconds.
+    open: BTreeMap<i64, Term>,
+    /// Terms starting before this have been written.
+    written_until: i64,
+}
+
+/// Start of the earliest term of `length` seconds that hasn't ended by `cutoff`.
+fn open_from(cutoff: i64, length: i64) -> i64 {
+    let due_before = cutoff - length;
+    due_before - due_before % length + length
+}
+
+/// Every open term. Kept apart from the actor so it can be driven by hand.
+#[derive(Debug)]
+struct Calculator {
+    settings: TermSettings,
+    started: DateTime<Utc>,
+    terms: HashMap<(exchange::Exchange, asset::Pair, i64), Terms>,
+    too_late: u64,
+    /// IDs of the trades the open terms were rebuilt from. They may come in again as
+    /// newly stored and mustn't be added twice.
+    rebuilt: HashSet<i64>,
+}
+
+impl Calculator {
+    fn new(settings: TermSettings, started: DateTime<Utc>) -> Self {
+        Calculator {
+            settings,
+            started,
+            terms: HashMap::new(),
+            too_late: 0,
+            rebuilt: HashSet::new(),
+        }
+    }
+
+    /// Where the stored trades of the terms still open at the start are to be read from.
+    fn rebuild_from(&self) -> DateTime<Utc> {
+        let cutoff = (self.started - self.settings.grace).timestamp();
+        let from = self.settings.resolutions
+            .iter()
+            .map(|resolution| open_from(cutoff, resolution.num_seconds()))
+            .min()
+            .unwrap_or(cutoff);
+        Utc.timestamp(from, 0)
+    }
+
+    /// Add stored trades to the terms still open. Returns how many hadn't been added yet.
+    fn rebuild(&mut self, trades: &[TradeItem]) -> usize {
+        let mut added = 0;
+        for trade in trades {
+            if self.rebuilt.insert(*trade.id()) {
+                self.add_to_terms(trade);
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Add a newly stored trade to its term of each resolution. Returns `false` if it was
+    /// too late for any of them.
+    fn add(&mut self, trade: &TradeItem) -> bool {
+        if self.rebuilt.remove(trade.id()) {
+            return true;
+        }
+
+        let in_time = self.add_to_terms(trade);
+        if !in_time {
+            self.too_late += 1;
+        }
+        in_time
+    }
+
+    fn add_to_terms(&mut self, trade: &TradeItem) -> bool {
+        let seconds = trade.timestamp().timestamp();
+        let started = (self.started - self.settings.grace).timestamp();
+        let mut in_time = true;
+
+        for resolution in self.settings.resolutions.iter() {
+            let length = resolution.num_seconds();
+            let start = seconds - seconds % length;
+            let key = (*trade.exchange(), *trade.asset_pair(), length);
+            let terms = self.terms.entry(key).or_insert_with(|| Terms {
+                open: BTreeMap::new(),
+                written_until: open_from(started, length),
+            });
+
+            if start < terms.written_until {
+                in_time = false;
+                continue;
+            }
+
+            let added = terms.open
+                .get_mut(&start)
+                .map(|term| term.add(trade))
+                .is_some();
+            if !added {
+                terms.open.insert(start, Term::new(trade));
+            }
+        }
+
+        in_time
+    }
+
+    /// Ticks of the terms that ended at least the grace period before `now`. They are
+    /// forgotten and later trades for them are too late.
+    fn close(&mut self, now: DateTime<Utc>) -> Vec<FreshTick> {
+        let cutoff = (now - self.settings.grace).timestamp();
+        let mut ticks = Vec::new();
+
+        for (&(exchange, asset_pair, length), terms) in self.terms.iter_mut() {
+            // The latest term that has ended by the cutoff.
+            let due_before = cutoff - length;
+            let due: Vec<i64> = terms.open
+                .range(..=due_before)
+                .map(|(start, _)| *start)
+                .collect();
+
+            for start in due {
+                let term = terms.open.remove(&start).expect("Start was just read.");
+                let start_time = Utc.timestamp(start, 0);
+                let end_time = Utc.timestamp(start + length, 0);
+                ticks.push(term.tick(exchange, asset_pair, start_time, end_time));
+            }
+
+            let written_until = open_from(cutoff, length);
+            if written_until > terms.written_until {
+                terms.written_until = written_until;
+            }
+        }
+
+        // Trades read for the rebuild are too late by now should they come in again.
+        let longest = self.settings.resolutions.iter().max().cloned();
+        if let Some(longest) = longest {
+            if cutoff >= (self.started + longest).timestamp() {
+                self.rebuilt.clear();
+            }
+        }
+
+        ticks
+    }
+}
+
+/// Keeps the open terms up to date with newly stored trades and writes their ticks once
+/// they have ended.
+pub struct TermCalculator {
+    calculator: Calculator,
+    broadcaster: Addr<Broadcaster>,
+    trades: Addr<TradeHistoryStorer>,
+    storer: Addr<TickStorer>,
+    /// Ticks that couldn't be written. Tried again on the next check.
+    unwritten: Vec<FreshTick>,
+}
+
+impl TermCalculator {
+    pub fn new(
+        settings: TermSettings,
+        broadcaster: Addr<Broadcaster>,
+        trades: Addr<TradeHistoryStorer>,
+        storer: Addr<TickStorer>,
+    ) -> Self {
+        TermCalculator {
+            calculator: Calculator::new(settings, Utc::now()),
+            broadcaster,
+            trades,
+            storer,
+            unwritten: Vec::new(),
+        }
+    }
+
+    /// Read the trades of the terms still open from the trade history and add them.
+    fn rebuild(&mut self, ctx: &mut Context<Self>) {
+        let from = self.calculator.rebuild_from();
+        let rebuilding = self.trades
+            .send(ReqTradesSince::new(from))
+            .into_actor(self)
+            .then(move |result, act, _ctx| {
+                match result {
+                    Ok(Ok(trades)) => {
+                        let added = act.calculator.rebuild(&trades);
+                        info!("Open terms rebuilt from {} trade(s) since {}.", added, from);
+                    },
+                    Ok(Err(e)) => error!("Can't rebuild the open terms: {}", &e),
+                    Err(e) => error!("Trade history storer failure: {}", &e),
+                }
+                actix::fut::ok(())
+            });
+
+        // Newly stored trades wait until the rebuild is done.
+        ctx.wait(rebuilding);
+    }
+
+    fn close(&mut self, ctx: &mut Context<Self>) {
+        let mut ticks = self.calculator.close(Utc::now());
+        ticks.extend(self.unwritten.drain(..));
+
+        for tick in ticks {
+            let write = self.storer
+                .send(NewTick::new(tick))
+                .map_err(|e| e.to_string())
+                .and_then(|result| result)
+                .into_actor(self)
+                .then(move |result, act, _ctx| {
+                    if let Err(e) = result {
+                        error!("Can't write tick {:?}. Trying again: {}", &tick, &e);
+                        act.unwritten.push(tick);
+                    }
+                    actix::fut::ok(())
+                });
+            ctx.spawn(write);
+        }
+    }
+}
+
+impl Actor for TermCalculator {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.broadcaster.do_send(Forward::new(ctx.address().recipient()));
+        self.rebuild(ctx);
+        ctx.run_interval(StdDuration::from_secs(CLOSE_INTERVAL), |act, ctx| act.close(ctx));
+        debug!("Term calculator started with {:?}.", &self.calculator.settings);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if !self.unwritten.is_empty() {
+            error!("Term calculator stopped. {} tick(s) unwritten.", self.unwritten.len());
+        }
+        debug!(
+            "Term calculator stopped. {} trade(s) were too late.", self.calculator.too_late
+        );
+    }
+}
+
+impl Handler<Stored> for TermCalculator {
+    type Result = ();
+
+    fn handle(&mut self, msg: Stored, _ctx: &mut Self::Context) {
+        let late = msg.trades()
+            .iter()
+            .filter(|trade| !self.calculator.add(trade))
+            .count();
+
+        if late > 0 {
+            warn!(
+                "{} {} {} trade(s) arrived after their term was written.",
+                late,
+                msg.exchange(),
+                msg.asset_pair(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common::trade;
+
+    fn trade(seconds: i64, price: &str, size: &str) -> TradeItem {
+        stored(0, seconds, price, size)
+    }
+
+    fn stored(id: i64, seconds: i64, price: &str, size: &str) -> TradeItem {
+        TradeItem::new(
+            id,
+            exchange::Exchange::Kraken,
+            asset::BTC_USD,
+            Utc.timestamp(seconds, 0),
+            size.parse().unwrap(),
+            price.parse().unwrap(),
+            trade::Market::Taker,
+        )
+    }
+
+    fn minutes() -> Calculator {
+        let settings = TermSettings::new(vec![Duration::minutes(1)], Duration::seconds(10));
+        Calculator::new(settings, Utc.timestamp(0, 0))
+    }
+
+    #[test]
+    fn ticks_are_written_after_the_grace_period() {
+        let mut calculator = minutes();
+        assert!(calculator.add(&trade(125, "101", "1")));
+        assert!(calculator.add(&trade(121, "100", "2")));
+        assert!(calculator.add(&trade(170, "99", "3")));
+        assert!(calculator.add(&trade(150, "105", "4")));
+        assert!(calculator.add(&trade(185, "110", "5")));
+
+        // The 120 term has ended but the grace period hasn't passed.
+        assert!(calculator.close(Utc.timestamp(185, 0)).is_empty());
+
+        let ticks = calculator.close(Utc.timestamp(190, 0));
+        assert_eq!(ticks.len(), 1);
+        let tick = &ticks[0];
+        assert_eq!(*tick.start_time(), Utc.timestamp(120, 0));
+        assert_eq!(*tick.end_time(), Utc.timestamp(180, 0));
+        assert_eq!(tick.first_price().to_string(), "100");
+        assert_eq!(tick.last_price().to_string(), "99");
+        assert_eq!(tick.highest_size().to_string(), "4");
+        assert_eq!(tick.lowest_size().to_string(), "3");
+        assert_eq!(*tick.count(), 4);
+
+        // The 120 term has been written.
+        assert!(!calculator.add(&trade(179, "1", "1")));
+        assert!(calculator.add(&trade(200, "111", "1")));
+        assert_eq!(calculator.too_late, 1);
+
+        let ticks = calculator.close(Utc.timestamp(250, 0));
+        assert_eq!(ticks.len(), 1);
+        assert_eq!(*ticks[0].count(), 2);
+    }
+
+    #[test]
+    fn every_resolution_gets_a_tick() {
+        let settings = TermSettings::new(
+            vec![Duration::minutes(1), Duration::minutes(5)], Duration::seconds(0),
+        );
+        let mut calculator = Calculator::new(settings, Utc.timestamp(0, 0));
+        for seconds in 0..10 {
+            calculator.add(&trade(seconds * 30, "100", "1"));
+        }
+
+        let ticks = calculator.close(Utc.timestamp(300, 0));
+        assert_eq!(ticks.len(), 6);
+        assert_eq!(ticks.iter().filter(|tick| *tick.count() == 10).count(), 1);
+    }
+
+    #[test]
+    fn open_terms_are_rebuilt_once() {
+        let settings = TermSettings::new(vec![Duration::minutes(1)], Duration::seconds(10));
+        let mut calculator = Calculator::new(settings, Utc.timestamp(1000, 0));
+
+        // The 900 term had ended by the start. The 960 term is still open.
+        assert_eq!(calculator.rebuild_from(), Utc.timestamp(960, 0));
+        assert_eq!(
+            calculator.rebuild(&[stored(1, 965, "100", "1"), stored(2, 1000, "101", "1")]),
+            2,
+        );
+
+        // Stored again whilst the rebuild read it. Only counted the once.
+        assert!(calculator.add(&stored(2, 1000, "101", "1")));
+        assert!(calculator.add(&stored(3, 1010, "102", "1")));
+        assert!(!calculator.add(&stored(4, 950, "99", "1")));
+
+        let ticks = calculator.close(Utc.timestamp(1030, 0));
+        assert_eq!(ticks.len(), 1);
+        assert_eq!(*ticks[0].start_time(), Utc.timestamp(960, 0));
+        assert_eq!(*ticks[0].count(), 3);
+        assert_eq!(ticks[0].first_price().to_string(), "100");
+        assert_eq!(ticks[0].last_price().to_string(), "102");
+    }
+}
@@ -0,0 +1,70 @@
+//! Order book models. Normalized so that books from any exchange can be stored the same way.
+use rust_decimal::Decimal;
+use chrono::{DateTime, Utc};
+
+/// A single price level. In a `BookDelta` a size of zero means the level was removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Getters)]
+pub struct Level {
+    price: Decimal,
+    size: Decimal,
+}
+
+impl Level {
+    pub fn new(price: Decimal, size: Decimal) -> Self {
+        Level { price, size }
+    }
+}
+
+/// The entire book at a point in time. Bids are ordered best (highest) first and asks are
+/// ordered best (lowest) first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Getters)]
+pub struct BookSnapshot {
+    timestamp: DateTime<Utc>,
+
+    /// Exchange update ID that the book is current up to.
+    sequence: u64,
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+impl BookSnapshot {
+    pub fn new(
+        timestamp: DateTime<Utc>, sequence: u64, bids: Vec<Level>, asks: Vec<Level>
+    ) -> Self {
+        BookSnapshot {
+            timestamp, sequence, bids, asks,
+        }
+    }
+}
+
+/// Levels that changed between two exchange update IDs (both inclusive).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Getters)]
+pub struct BookDelta {
+    timestamp: DateTime<Utc>,
+    first_sequence: u64,
+    last_sequence: u64,
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+impl BookDelta {
+    pub fn new(
+        timestamp: DateTime<Utc>,
+        first_sequence: u64,
+        last_sequence: u64,
+        bids: Vec<Level>,
+        asks: Vec<Level>,
+    ) -> Self {
+        BookDelta {
+            timestamp, first_sequence, last_sequence, bids, asks,
+        }
+    }
+}
+
+/// What a synchronised local book emits. A snapshot whenever it is (re)built, followed by
+/// deltas for as long as it stays in sync.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BookEvent {
+    Snapshot(BookSnapshot),
+    Delta(BookDelta),
+}
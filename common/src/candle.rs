@@ -0,0 +1,158 @@
+//! Candle models. Candles here are the ones computed by the exchange. Not the ticks we fold
+//! ourselves from the trade history.
+use std::{error, fmt, str};
+
+use rust_decimal::Decimal;
+use chrono::{DateTime, Utc};
+
+/// Length of time a candle covers. Serialized with the same labels as `Display`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Interval {
+    #[serde(rename = "1m")]
+    Minute1,
+    #[serde(rename = "3m")]
+    Minute3,
+    #[serde(rename = "5m")]
+    Minute5,
+    #[serde(rename = "15m")]
+    Minute15,
+    #[serde(rename = "30m")]
+    Minute30,
+    #[serde(rename = "1h")]
+    Hour1,
+    #[serde(rename = "2h")]
+    Hour2,
+    #[serde(rename = "4h")]
+    Hour4,
+    #[serde(rename = "6h")]
+    Hour6,
+    #[serde(rename = "8h")]
+    Hour8,
+    #[serde(rename = "12h")]
+    Hour12,
+    #[serde(rename = "1d")]
+    Day1,
+    #[serde(rename = "3d")]
+    Day3,
+    #[serde(rename = "1w")]
+    Week1,
+    #[serde(rename = "1M")]
+    Month1,
+}
+
+impl Interval {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Interval::Minute1 => "1m",
+            Interval::Minute3 => "3m",
+            Interval::Minute5 => "5m",
+            Interval::Minute15 => "15m",
+            Interval::Minute30 => "30m",
+            Interval::Hour1 => "1h",
+            Interval::Hour2 => "2h",
+            Interval::Hour4 => "4h",
+            Interval::Hour6 => "6h",
+            Interval::Hour8 => "8h",
+            Interval::Hour12 => "12h",
+            Interval::Day1 => "1d",
+            Interval::Day3 => "3d",
+            Interval::Week1 => "1w",
+            Interval::Month1 => "1M",
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl str::FromStr for Interval {
+    type Err = ParseIntervalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1m" => Ok(Interval::Minute1),
+            "3m" => Ok(Interval::Minute3),
+            "5m" => Ok(Interval::Minute5),
+            "15m" => Ok(Interval::Minute15),
+            "30m" => Ok(Interval::Minute30),
+            "1h" => Ok(Interval::Hour1),
+            "2h" => Ok(Interval::Hour2),
+            "4h" => Ok(Interval::Hour4),
+            "6h" => Ok(Interval::Hour6),
+            "8h" => Ok(Interval::Hour8),
+            "12h" => Ok(Interval::Hour12),
+            "1d" => Ok(Interval::Day1),
+            "3d" => Ok(Interval::Day3),
+            "1w" => Ok(Interval::Week1),
+            "1M" => Ok(Interval::Month1),
+            _ => Err(ParseIntervalError),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ParseIntervalError;
+
+impl fmt::Display for ParseIntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error parsing candle interval.")
+    }
+}
+
+impl error::Error for ParseIntervalError {
+    fn description(&self) -> &str {
+        "Error parsing candle interval."
+    }
+}
+
+/// A closed candle as computed by the exchange. The asset pair and exchange are not stored
+/// in the struct and are to be determined via the calling context.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Getters)]
+pub struct Candle {
+    interval: Interval,
+    open_time: DateTime<Utc>,
+    close_time: DateTime<Utc>,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+
+    /// Volume in the left side asset.
+    volume: Decimal,
+
+    /// Volume in the right side asset.
+    quote_volume: Decimal,
+    trades: u64,
+}
+
+impl Candle {
+    pub fn new(
+        interval: Interval,
+        open_time: DateTime<Utc>,
+        close_time: DateTime<Utc>,
+        open: Decimal,
+        high: Decimal,
+        low: Decimal,
+        close: Decimal,
+        volume: Decimal,
+        quote_volume: Decimal,
+        trades: u64,
+    ) -> Self {
+        Candle {
+            interval,
+            open_time,
+            close_time,
+            open,
+            high,
+            low,
+            close,
+            volume,
+            quote_volume,
+            trades,
+        }
+    }
+}
+
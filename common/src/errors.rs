@@ -4,14 +4,19 @@
 use std::{fmt, env, error, convert, num, net};
 
 use asset;
+use exchange;
 
 #[derive(Debug)]
 pub enum ConfigError {
     MissingEnv(String, env::VarError),
     InvalidAsset(String, asset::ParseAssetError),
     InvalidMode(String),
+    InvalidFormat(String),
     InvalidInt(String, num::ParseIntError),
     InvalidAddr(String, net::AddrParseError),
+    InvalidExchange(String, exchange::ParseExchangeError),
+    InvalidAllowList(String, String),
+    ZeroDuration(String),
 }
 
 impl fmt::Display for ConfigError {
@@ -20,8 +25,14 @@ impl fmt::Display for ConfigError {
             ConfigError::MissingEnv(var, err) => write!(f, "Missing {}:{}", &var, &err),
             ConfigError::InvalidAsset(var, err) => write!(f, "Invalid {}:{}", &var, &err),
             ConfigError::InvalidMode(var) => write!(f, "Invalid fetch mode: {}", &var),
+            ConfigError::InvalidFormat(var) => write!(f, "Invalid format: {}", &var),
             ConfigError::InvalidInt(var, err) => write!(f, "Invalid {}:{}", &var, &err),
             ConfigError::InvalidAddr(var, err) => write!(f, "Invalid {}:{}", &var, &err),
+            ConfigError::InvalidExchange(var, err) => write!(f, "Invalid {}:{}", &var, &err),
+            ConfigError::InvalidAllowList(var, err) => {
+                write!(f, "Invalid {}:{}", &var, &err)
+            },
+            ConfigError::ZeroDuration(var) => write!(f, "{} can't be zero", &var),
         }
     }
 }
@@ -55,3 +66,9 @@ impl<'a> convert::From<(&'a str, net::AddrParseError)> for ConfigError {
         ConfigError::InvalidAddr(e_tuple.0.to_owned(), e_tuple.1)
     }
 }
+
+impl<'a> convert::From<(&'a str, exchange::ParseExchangeError)> for ConfigError {
+    fn from(e_tuple: (&str, exchange::ParseExchangeError)) -> Self {
+        ConfigError::InvalidExchange(e_tuple.0.to_owned(), e_tuple.1)
+    }
+}
@@ -5,6 +5,9 @@ use std::{fmt, error, str};
 pub enum Exchange {
     Kraken,
     Binance,
+    Coinbase,
+    Bitstamp,
+    Bitfinex,
 }
 
 impl Exchange {
@@ -12,6 +15,9 @@ impl Exchange {
         match self {
             Exchange::Kraken => "kraken",
             Exchange::Binance => "binance",
+            Exchange::Coinbase => "coinbase",
+            Exchange::Bitstamp => "bitstamp",
+            Exchange::Bitfinex => "bitfinex",
         }
     }
 }
@@ -23,6 +29,9 @@ impl str::FromStr for Exchange {
         match s {
             "kraken" | "KRAKEN" => Ok(Exchange::Kraken),
             "binance" | "BINANCE" => Ok(Exchange::Binance),
+            "coinbase" | "COINBASE" => Ok(Exchange::Coinbase),
+            "bitstamp" | "BITSTAMP" => Ok(Exchange::Bitstamp),
+            "bitfinex" | "BITFINEX" => Ok(Exchange::Bitfinex),
             _ => Err(ParseExchangeError),
         }
     }
@@ -33,6 +42,9 @@ impl fmt::Display for Exchange {
         match self {
             Exchange::Kraken => write!(f, "kraken"),
             Exchange::Binance => write!(f, "binance"),
+            Exchange::Coinbase => write!(f, "coinbase"),
+            Exchange::Bitstamp => write!(f, "bitstamp"),
+            Exchange::Bitfinex => write!(f, "bitfinex"),
         }
     }
 }
@@ -9,5 +9,7 @@ pub mod trade;
 pub mod exchange;
 pub mod asset;
 pub mod tick;
+pub mod book;
+pub mod candle;
 pub mod errors;
 pub mod time_util;
@@ -173,3 +173,72 @@ impl TradeHistoryItem {
         self.match_timestamp
     }
 }
+
+/// Trades that filled at the same price from the same taker order, rolled into one by the
+/// exchange.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AggregateTrade {
+    timestamp: DateTime<Utc>,
+    size: Decimal,
+    price: Decimal,
+    market: Market,
+
+    /// Exchange ID of the aggregate.
+    aggregate_id: i64,
+
+    /// ID of the first trade rolled in to the aggregate.
+    first_trade_id: i64,
+
+    /// ID of the last trade rolled in to the aggregate.
+    last_trade_id: i64,
+}
+
+impl AggregateTrade {
+    pub fn new(
+        timestamp: DateTime<Utc>,
+        size: Decimal,
+        price: Decimal,
+        market: Market,
+        aggregate_id: i64,
+        first_trade_id: i64,
+        last_trade_id: i64,
+    ) -> Self {
+        AggregateTrade {
+            timestamp,
+            size,
+            price,
+            market,
+            aggregate_id,
+            first_trade_id,
+            last_trade_id,
+        }
+    }
+
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+
+    pub fn size(&self) -> Decimal {
+        self.size
+    }
+
+    pub fn price(&self) -> Decimal {
+        self.price
+    }
+
+    pub fn market(&self) -> Market {
+        self.market
+    }
+
+    pub fn aggregate_id(&self) -> i64 {
+        self.aggregate_id
+    }
+
+    pub fn first_trade_id(&self) -> i64 {
+        self.first_trade_id
+    }
+
+    pub fn last_trade_id(&self) -> i64 {
+        self.last_trade_id
+    }
+}
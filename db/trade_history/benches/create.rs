@@ -0,0 +1,90 @@
+//! Trade history inserts. Committing every item on its own and the per item `create`
+//! against the bulk inserts.
+//!
+//! Run with `cargo bench --features bench`. Needs a migrated database at `DATABASE_URL`.
+//! Every item inserted is new so the benchmarks fill the `trade_history_items` table.
+//! Point them at a throw away database.
+#[macro_use] extern crate criterion;
+extern crate chrono;
+extern crate dotenv;
+
+extern crate trade_history;
+extern crate common;
+
+use std::env::var;
+
+use chrono::{Utc, Duration};
+use criterion::Criterion;
+
+use common::{exchange, asset, trade};
+use trade_history::{model, crud};
+
+const BATCH: usize = 1000;
+
+/// A batch of items that haven't been stored before. Each is a microsecond after the last.
+fn fresh_batch(offset: &mut i64) -> Vec<model::FreshTradeItem> {
+    let start = Utc::now();
+    (0..BATCH)
+        .map(|_| {
+            *offset += 1;
+            model::FreshTradeItem::new(
+                exchange::Exchange::Kraken,
+                asset::BTC_USD,
+                start + Duration::microseconds(*offset),
+                10.into(),
+                100.into(),
+                trade::Market::Maker,
+                Some(trade::Type::Limit),
+                None,
+                None,
+                None,
+                None,
+            )
+        })
+        .collect()
+}
+
+fn connect() -> crud::Trades {
+    dotenv::dotenv().ok();
+    let db_url = var("DATABASE_URL").expect("DATABASE_URL env var not set.");
+    crud::Trades::connect(&db_url).expect("Can't connect to DB.")
+}
+
+fn autocommit(c: &mut Criterion) {
+    let trades = connect();
+    let mut offset = 0;
+    c.bench_function("create 1000 autocommit", move |b| b.iter_with_setup(
+        || fresh_batch(&mut offset),
+        |ftis| trades.create_autocommit(&ftis).expect("DB error."),
+    ));
+}
+
+fn per_item(c: &mut Criterion) {
+    let trades = connect();
+    let mut offset = 0;
+    c.bench_function("create 1000 per item", move |b| b.iter_with_setup(
+        || fresh_batch(&mut offset),
+        |ftis| trades.create(&ftis).expect("DB error."),
+    ));
+}
+
+fn bulk(c: &mut Criterion) {
+    let trades = connect();
+    let mut offset = 0;
+    c.bench_function("create_bulk 1000", move |b| b.iter_with_setup(
+        || fresh_batch(&mut offset),
+        |ftis| trades.create_bulk(&ftis).expect("DB error."),
+    ));
+}
+
+fn bulk_counted(c: &mut Criterion) {
+    let trades = connect();
+    let mut offset = 0;
+    c.bench_function("create_bulk_counted 1000", move |b| b.iter_with_setup(
+        || fresh_batch(&mut offset),
+        |ftis| trades.create_bulk_counted(&ftis).expect("DB error."),
+    ));
+}
+
+criterion_group!(benches, autocommit, per_item, bulk, bulk_counted);
+criterion_main!(benches);
@@ -1,12 +1,18 @@
 //! Create Read Update Delete executors using the `postgres` driver.
 use std::collections::HashMap;
 
-use postgres::{Connection, TlsMode};
+use postgres::{Connection, GenericConnection, TlsMode};
+use postgres::types::ToSql;
+use postgres::stmt::Statement;
+use postgres::transaction::Transaction;
 use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 
-use common::{asset, trade, exchange};
+use common::{asset, trade, exchange, candle, book};
 
-use model::{FreshTradeItem, TradeItem, TradeSetSummary};
+use model::{
+    FreshTradeItem, TradeItem, TradeSetSummary, CreatedTradeItems, Gap, GapKind, GapScan,
+};
 use error::Error;
 
 macro_rules! fetch_data_types {
@@ -52,6 +58,105 @@ macro_rules! db_row_to_trade_item {
     }
 }
 
+/// Most trade history items in one multi-row insert. Each takes eleven parameters and
+/// postgres allows no more than 65535 in a statement.
+pub const BULK_ROWS: usize = 1000;
+
+/// The IDs a fresh trade item is stored with.
+struct ItemIds {
+    exchange: i32,
+    asset_pair: i32,
+    market: i32,
+    trade: Option<i32>,
+}
+
+impl ItemIds {
+    fn params<'a>(&'a self, fti: &'a FreshTradeItem) -> [&'a ToSql; 11] {
+        [
+            &self.exchange,
+            &self.asset_pair,
+            fti.timestamp(),
+            fti.size(),
+            fti.price(),
+            &self.market,
+            &self.trade,
+            fti.match_id(),
+            fti.buy_order_id(),
+            fti.sell_order_id(),
+            fti.match_timestamp(),
+        ]
+    }
+}
+
+fn bulk_params<'a>(ftis: &'a [FreshTradeItem], ids: &'a [ItemIds]) -> Vec<&'a ToSql> {
+    ftis.iter()
+        .zip(ids.iter())
+        .flat_map(|(fti, ids)| ids.params(fti).to_vec())
+        .collect()
+}
+
+/// A multi-row insert of `rows` trade history items. The content hash must be worked out
+/// the same as in the migration that added it.
+fn bulk_insert(rows: usize, returning: bool) -> String {
+    let values: Vec<String> = (0..rows)
+        .map(|row| {
+            let p = row * 11;
+            format!(
+                "( ${}, ${}, ${ts}, ${size}, ${price}, ${market}, ${trade}, ${}, ${buy}, \
+                   ${sell}, ${match_ts}, \
+                 md5(concat_ws( \
+                   '|', \
+                   to_char(${ts}::TIMESTAMPTZ AT TIME ZONE 'UTC', \
+                           'YYYY-MM-DD HH24:MI:SS.US'), \
+                   ${size}::NUMERIC(30,15), \
+                   ${price}::NUMERIC(30,15), \
+                   ${market}::INTEGER, \
+                   coalesce(${trade}::INTEGER::TEXT, ''), \
+                   coalesce(${buy}::BIGINT::TEXT, ''), \
+                   coalesce(${sell}::BIGINT::TEXT, ''), \
+                   coalesce(to_char(${match_ts}::TIMESTAMPTZ AT TIME ZONE 'UTC', \
+                                    'YYYY-MM-DD HH24:MI:SS.US'), '') \
+                 )) )",
+                p + 1, p + 2, p + 8,
+                ts = p + 3, size = p + 4, price = p + 5, market = p + 6, trade = p + 7,
+                buy = p + 9, sell = p + 10, match_ts = p + 11,
+            )
+        })
+        .collect();
+
+    format!(
+        "INSERT INTO trade_history_items \
+         ( exchange, asset_pair, happened, match_size, match_price, market, trade, \
+           match_id, buy_order_id, sell_order_id, match_ts, content_hash ) \
+         VALUES {} \
+         ON CONFLICT DO NOTHING{}",
+        values.join(", "),
+        if returning { " RETURNING *" } else { "" },
+    )
+}
+
+/// Prepare the multi-row insert for `rows` items. Only full inserts are cached. The tail
+/// of a batch could be any length and each would be cached apart.
+fn prepare_bulk<'a>(
+    transaction: &'a Transaction, rows: usize, returning: bool,
+) -> Result<Statement<'a>, Error> {
+    let sql = bulk_insert(rows, returning);
+    let stmt = if rows == BULK_ROWS {
+        transaction.prepare_cached(&sql)?
+    } else {
+        transaction.prepare(&sql)?
+    };
+    Ok(stmt)
+}
+
+/// Order book levels as the parallel price and size arrays they are stored in.
+fn split_levels(levels: &[book::Level]) -> (Vec<Decimal>, Vec<Decimal>) {
+    levels
+        .iter()
+        .map(|level| (*level.price(), *level.size()))
+        .unzip()
+}
+
 pub struct Trades {
     connection: Connection,
     ex_ids: HashMap<exchange::Exchange, i32>,
@@ -107,67 +212,352 @@ impl Trades {
             .collect()
     }
 
+    /// Every exchange and asset pair combination that has trade history stored.
+    pub fn delivered(&self) -> Result<Vec<(exchange::Exchange, asset::Pair)>, Error> {
+        let rows = self.connection.query(
+            "SELECT DISTINCT exchange, asset_pair FROM trade_history_items", &[]
+        )?;
+
+        let mut delivered = Vec::with_capacity(rows.len());
+        for row in rows.iter() {
+            let ex_id: i32 = row.get(0);
+            let ap_id: i32 = row.get(1);
+            let exchange = *self.ids_ex.get(&ex_id).ok_or("Exchange in DB not in index.")?;
+            let pair = *self.ids_ap.get(&ap_id).ok_or("Asset Pair in DB not in index.")?;
+            delivered.push((exchange, pair));
+        }
+
+        Ok(delivered)
+    }
+
     /// Insert into the DB. The 'Create' part of CRUD. This one will check every
     /// item for it's exchange and asset pair.
     ///
+    /// Items already stored are skipped and counted as duplicates. An item is the same as
+    /// one stored if the exchange, asset pair and match ID are the same. Items without a
+    /// match ID are compared on a hash of their timestamp, size, price and market instead.
+    ///
     /// TODO:
     /// Create another method that let's you set the exchange and asset pair in
     /// advance to save a little time if this information is known in advance for large
     /// batch inserts.
-    pub fn create(&self, ftis: &[FreshTradeItem]) -> Result<Vec<TradeItem>, Error> {
-        //let transaction = self.connection.transaction()?;
-        let create_stmt = self.connection.prepare_cached(
+    pub fn create(&self, ftis: &[FreshTradeItem]) -> Result<CreatedTradeItems, Error> {
+        let transaction = self.connection.transaction()?;
+        let created = self.insert_each(&transaction, ftis)?;
+        transaction.commit()?;
+        Ok(created)
+    }
+
+    /// Same as `create` but every item is committed on its own, as `create` used to. Only
+    /// kept so the benchmarks can compare against it.
+    #[cfg(feature = "bench")]
+    pub fn create_autocommit(
+        &self, ftis: &[FreshTradeItem],
+    ) -> Result<CreatedTradeItems, Error> {
+        self.insert_each(&self.connection, ftis)
+    }
+
+    /// Insert the items one statement at a time over the `connection`.
+    fn insert_each<C: GenericConnection>(
+        &self, connection: &C, ftis: &[FreshTradeItem],
+    ) -> Result<CreatedTradeItems, Error> {
+        // The content hash must be worked out the same as in the migration that added it.
+        let create_stmt = connection.prepare_cached(
             "INSERT INTO trade_history_items \
              ( exchange, asset_pair, happened, match_size, match_price, market, trade, \
-               match_id, buy_order_id, sell_order_id, match_ts ) \
-             VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11 ) \
+               match_id, buy_order_id, sell_order_id, match_ts, content_hash ) \
+             VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, md5(concat_ws( \
+               '|', \
+               to_char($3::TIMESTAMPTZ AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS.US'), \
+               $4::NUMERIC(30,15), \
+               $5::NUMERIC(30,15), \
+               $6::INTEGER, \
+               coalesce($7::INTEGER::TEXT, ''), \
+               coalesce($9::BIGINT::TEXT, ''), \
+               coalesce($10::BIGINT::TEXT, ''), \
+               coalesce(to_char($11::TIMESTAMPTZ AT TIME ZONE 'UTC', \
+                                'YYYY-MM-DD HH24:MI:SS.US'), '') \
+             )) ) \
+             ON CONFLICT DO NOTHING \
              RETURNING *"
         )?;
 
-        let itis = ftis.iter()
-            .try_fold(vec![], |mut itis, fti| -> Result<Vec<TradeItem>, Error> {
-                let ex_id = self.ex_ids.get(fti.exchange()).ok_or("Invalid exchange")?;
-                let ap_id = self.ap_ids.get(fti.asset_pair()).ok_or("Invalid asset pair")?;
-                let tm_id = self.tm_ids.get(fti.market()).ok_or("Invalid market")?;
-                let tt_id = if let Some(trade) = fti.trade() {
-                    Some(self.tt_ids.get(&trade).ok_or("Invalid trade")?)
-                } else {
-                    None
-                };
-                
-                let rows = create_stmt.query(&[
-                    ex_id,
-                    ap_id,
-                    fti.timestamp(),
-                    fti.size(),
-                    fti.price(),
-                    tm_id,
-                    &tt_id,
-                    fti.match_id(),
-                    fti.buy_order_id(),
-                    fti.sell_order_id(),
-                    fti.match_timestamp(),
-                ])?;
-
-                // Only one row should be returned here.
-                if rows.len() != 1 {
-                    return Err(Error::InvalidRows(
+        let (itis, duplicates) = ftis.iter()
+            .try_fold((vec![], 0), |(mut itis, duplicates), fti| -> Result<_, Error> {
+                let ids = self.item_ids(fti)?;
+                let rows = create_stmt.query(&ids.params(fti))?;
+
+                // No row means it was already stored. Otherwise only one should be
+                // returned.
+                match rows.len() {
+                    0 => return Ok((itis, duplicates + 1)),
+                    1 => (),
+                    _ => return Err(Error::InvalidRows(
                         "Single trade item insert should only return one row.".to_owned(),
-                    ));
+                    )),
                 }
 
                 let row = rows.get(0);
-                //println!("ROW: {:?}", &row);
                 let iti = db_row_to_trade_item!(
                     row, self.ids_ex, self.ids_ap, self.ids_tm, self.ids_tt
                 );
-                
+
                 itis.push(iti);
-                Ok(itis)
+                Ok((itis, duplicates))
             })?;
 
-        //transaction.finish()?;
-        Ok(itis)
+        Ok(CreatedTradeItems::new(itis, duplicates))
+    }
+
+    /// Insert trade history with multi-row inserts of up to `BULK_ROWS` items each. All of
+    /// them go in one transaction. Either every item is stored or none are.
+    ///
+    /// Duplicates are skipped the same as with `create`. The inserted items come back in
+    /// no particular order.
+    pub fn create_bulk(&self, ftis: &[FreshTradeItem]) -> Result<CreatedTradeItems, Error> {
+        let transaction = self.connection.transaction()?;
+        let mut itis = Vec::with_capacity(ftis.len());
+
+        for chunk in ftis.chunks(BULK_ROWS) {
+            let ids = chunk.iter()
+                .map(|fti| self.item_ids(fti))
+                .collect::<Result<Vec<ItemIds>, Error>>()?;
+            let params = bulk_params(chunk, &ids);
+
+            let create_stmt = prepare_bulk(&transaction, chunk.len(), true)?;
+            for row in create_stmt.query(&params)?.iter() {
+                let iti = db_row_to_trade_item!(
+                    row, self.ids_ex, self.ids_ap, self.ids_tm, self.ids_tt
+                );
+                itis.push(iti);
+            }
+        }
+
+        transaction.commit()?;
+        let duplicates = (ftis.len() - itis.len()) as u64;
+        Ok(CreatedTradeItems::new(itis, duplicates))
+    }
+
+    /// Same as `create_bulk` but nothing is read back. Returns how many items were
+    /// inserted. For when knowing that the items are stored is enough.
+    pub fn create_bulk_counted(&self, ftis: &[FreshTradeItem]) -> Result<u64, Error> {
+        let transaction = self.connection.transaction()?;
+        let mut inserted = 0;
+
+        for chunk in ftis.chunks(BULK_ROWS) {
+            let ids = chunk.iter()
+                .map(|fti| self.item_ids(fti))
+                .collect::<Result<Vec<ItemIds>, Error>>()?;
+            let params = bulk_params(chunk, &ids);
+
+            let create_stmt = prepare_bulk(&transaction, chunk.len(), false)?;
+            inserted += create_stmt.execute(&params)?;
+        }
+
+        transaction.commit()?;
+        Ok(inserted)
+    }
+
+    /// Look up the IDs the item is stored with.
+    fn item_ids(&self, fti: &FreshTradeItem) -> Result<ItemIds, Error> {
+        let trade = if let Some(trade) = fti.trade() {
+            Some(*self.tt_ids.get(trade).ok_or("Invalid trade")?)
+        } else {
+            None
+        };
+
+        Ok(ItemIds {
+            exchange: *self.ex_ids.get(fti.exchange()).ok_or("Invalid exchange")?,
+            asset_pair: *self.ap_ids.get(fti.asset_pair()).ok_or("Invalid asset pair")?,
+            market: *self.tm_ids.get(fti.market()).ok_or("Invalid market")?,
+            trade,
+        })
+    }
+
+    /// Insert candles computed by the exchange. Candles already stored are skipped. Returns
+    /// how many were inserted.
+    pub fn create_candles(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        candles: &[candle::Candle],
+    ) -> Result<u64, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Invalid exchange")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Invalid asset pair")?;
+        let create_stmt = self.connection.prepare_cached(
+            "INSERT INTO candles \
+             ( exchange, asset_pair, candle_interval, open_time, close_time, open_price, \
+               high_price, low_price, close_price, volume, quote_volume, trades ) \
+             VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12 ) \
+             ON CONFLICT DO NOTHING"
+        )?;
+
+        candles
+            .iter()
+            .try_fold(0, |inserted, candle| -> Result<u64, Error> {
+                let count = create_stmt.execute(&[
+                    ex_id,
+                    ap_id,
+                    &candle.interval().as_str(),
+                    candle.open_time(),
+                    candle.close_time(),
+                    candle.open(),
+                    candle.high(),
+                    candle.low(),
+                    candle.close(),
+                    candle.volume(),
+                    candle.quote_volume(),
+                    &(*candle.trades() as i64),
+                ])?;
+
+                Ok(inserted + count)
+            })
+    }
+
+    /// Insert aggregate trades. Aggregates already stored are skipped. Returns how many were
+    /// inserted.
+    pub fn create_aggregate_trades(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        aggregates: &[trade::AggregateTrade],
+    ) -> Result<u64, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Invalid exchange")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Invalid asset pair")?;
+        let create_stmt = self.connection.prepare_cached(
+            "INSERT INTO aggregate_trades \
+             ( exchange, asset_pair, happened, match_size, match_price, market, \
+               aggregate_id, first_match_id, last_match_id ) \
+             VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9 ) \
+             ON CONFLICT DO NOTHING"
+        )?;
+
+        aggregates
+            .iter()
+            .try_fold(0, |inserted, aggregate| -> Result<u64, Error> {
+                let tm_id = self.tm_ids.get(&aggregate.market()).ok_or("Invalid market")?;
+                let count = create_stmt.execute(&[
+                    ex_id,
+                    ap_id,
+                    &aggregate.timestamp(),
+                    &aggregate.size(),
+                    &aggregate.price(),
+                    tm_id,
+                    &aggregate.aggregate_id(),
+                    &aggregate.first_trade_id(),
+                    &aggregate.last_trade_id(),
+                ])?;
+
+                Ok(inserted + count)
+            })
+    }
+
+    /// Insert order book snapshots and deltas. Events already stored are skipped. Returns
+    /// how many were inserted.
+    pub fn create_book_events(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        events: &[book::BookEvent],
+    ) -> Result<u64, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Invalid exchange")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Invalid asset pair")?;
+        let create_stmt = self.connection.prepare_cached(
+            "INSERT INTO order_book_events \
+             ( exchange, asset_pair, kind, happened, first_sequence, last_sequence, \
+               bid_prices, bid_sizes, ask_prices, ask_sizes ) \
+             VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10 ) \
+             ON CONFLICT DO NOTHING"
+        )?;
+
+        events
+            .iter()
+            .try_fold(0, |inserted, event| -> Result<u64, Error> {
+                let (kind, happened, first, last, bids, asks) = match event {
+                    book::BookEvent::Snapshot(snapshot) => (
+                        "snapshot",
+                        snapshot.timestamp(),
+                        *snapshot.sequence() as i64,
+                        *snapshot.sequence() as i64,
+                        snapshot.bids(),
+                        snapshot.asks(),
+                    ),
+                    book::BookEvent::Delta(delta) => (
+                        "delta",
+                        delta.timestamp(),
+                        *delta.first_sequence() as i64,
+                        *delta.last_sequence() as i64,
+                        delta.bids(),
+                        delta.asks(),
+                    ),
+                };
+                let (bid_prices, bid_sizes) = split_levels(bids);
+                let (ask_prices, ask_sizes) = split_levels(asks);
+                let count = create_stmt.execute(&[
+                    ex_id,
+                    ap_id,
+                    &kind,
+                    happened,
+                    &first,
+                    &last,
+                    &bid_prices,
+                    &bid_sizes,
+                    &ask_prices,
+                    &ask_sizes,
+                ])?;
+
+                Ok(inserted + count)
+            })
+    }
+
+    /// Reads up to the `limit` most recently stored trades for the `exchange` and
+    /// `asset_pair` supplied. Returned oldest first with all the detail that was stored.
+    pub fn read_recent_items(
+        &self, exchange: exchange::Exchange, asset_pair: asset::Pair, limit: i64,
+    ) -> Result<Vec<trade::TradeHistoryItem>, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Exchange DB not in index.")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Asset pair not in index.")?;
+
+        let rows = self.connection.query(
+            "SELECT \
+             happened, match_size, match_price, market, trade, \
+             match_id, buy_order_id, sell_order_id, match_ts \
+             FROM trade_history_items \
+             WHERE exchange = $1 AND asset_pair = $2 \
+             ORDER BY id DESC \
+             LIMIT $3",
+            &[ex_id, ap_id, &limit]
+        )?;
+
+        let mut items = rows
+            .iter()
+            .map(|row| -> Result<trade::TradeHistoryItem, Error> {
+                let tm_id: i32 = row.get(3);
+                let tt_id: Option<i32> = row.get(4);
+                let market = *self.ids_tm.get(&tm_id).ok_or("Market in DB not in index.")?;
+                let trade = match tt_id {
+                    Some(tt_id) => Some(
+                        *self.ids_tt.get(&tt_id).ok_or("Trade type in DB not in index.")?
+                    ),
+                    None => None,
+                };
+
+                Ok(trade::TradeHistoryItem::new(
+                    row.get(0),
+                    row.get(1),
+                    row.get(2),
+                    market,
+                    trade,
+                    row.get(5),
+                    row.get(6),
+                    row.get(7),
+                    row.get(8),
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        items.reverse();
+        Ok(items)
     }
 
     /// Reads the last trade item for the `exchange` and `asset_pair` supplied. This can
@@ -254,6 +644,31 @@ impl Trades {
         Ok(tis)
     }
 
+    /// Every trade of every exchange and asset pair that happened at or after `from`,
+    /// oldest first. Meant for short spans such as the terms still open after a restart.
+    pub fn read_since(&self, from: DateTime<Utc>) -> Result<Vec<TradeItem>, Error> {
+        let rows = self.connection.query(
+            "SELECT \
+             id, exchange, asset_pair, happened, match_size, match_price, \
+             market, trade \
+             FROM trade_history_items \
+             WHERE happened >= $1 \
+             ORDER BY happened ASC",
+            &[&from]
+        )?;
+
+        rows
+            .into_iter()
+            .try_fold(vec![], |mut tis, row| -> Result<Vec<TradeItem>, Error> {
+                let ti = db_row_to_trade_item!(
+                    row, self.ids_ex, self.ids_ap, self.ids_tm, self.ids_tt
+                );
+
+                tis.push(ti);
+                Ok(tis)
+            })
+    }
+
     /// Summarize the items in the trade history table.
     pub fn read_set_summary(
         &self,
@@ -363,4 +778,242 @@ impl Trades {
             },
         }
     }
+
+    /// The first and last trades between `from` and `to` along with how many there are.
+    /// `None` if there are none.
+    pub fn read_range_summary(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Option<TradeSetSummary>, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Exchange DB not in index.")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Asset pair not in index.")?;
+        let rows = self.connection.query(
+            "SELECT MIN(happened), MAX(happened), COUNT(*) \
+             FROM trade_history_items \
+             WHERE exchange = $1 AND asset_pair = $2 AND happened >= $3 AND happened < $4",
+            &[ex_id, ap_id, &from, &to]
+        )?;
+
+        let row = match rows.iter().next() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let first: Option<DateTime<Utc>> = row.get(0);
+        let last: Option<DateTime<Utc>> = row.get(1);
+
+        match (first, last) {
+            (Some(first), Some(last)) => {
+                Ok(Some(TradeSetSummary::new(first, last, row.get(2))))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Stretches between consecutive trades from `from` to `to` that are longer than
+    /// `longer_than` seconds. The last trade before `from` counts so that a silence
+    /// running into the range is found.
+    pub fn read_silences(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        longer_than: f64,
+    ) -> Result<Vec<Gap>, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Exchange DB not in index.")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Asset pair not in index.")?;
+        let rows = self.connection.query(
+            "WITH series AS ( \
+               SELECT happened, LAG(happened) OVER (ORDER BY happened) AS previous \
+               FROM trade_history_items \
+               WHERE exchange = $1 AND asset_pair = $2 AND happened < $4 \
+               AND happened >= COALESCE(( \
+                 SELECT MAX(happened) FROM trade_history_items \
+                 WHERE exchange = $1 AND asset_pair = $2 AND happened < $3 \
+               ), $3) \
+             ) \
+             SELECT previous, happened FROM series \
+             WHERE previous IS NOT NULL \
+             AND EXTRACT(EPOCH FROM happened - previous)::DOUBLE PRECISION \
+                 > $5::DOUBLE PRECISION \
+             ORDER BY previous ASC",
+            &[ex_id, ap_id, &from, &to, &longer_than]
+        )?;
+
+        let gaps = rows
+            .iter()
+            .map(|row| Gap::new(
+                exchange, asset_pair, GapKind::Silence, row.get(0), row.get(1), None,
+            ))
+            .collect();
+
+        Ok(gaps)
+    }
+
+    /// Places from `from` to `to` where the match IDs skip ahead. The last match before
+    /// `from` is included so a jump across it is found. Only makes sense for exchanges that
+    /// number the trades of each asset pair one after the other.
+    pub fn read_match_id_jumps(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Gap>, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Exchange DB not in index.")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Asset pair not in index.")?;
+        let rows = self.connection.query(
+            "WITH series AS ( \
+               SELECT match_id, happened, \
+               LAG(match_id) OVER (ORDER BY match_id) AS previous_id, \
+               LAG(happened) OVER (ORDER BY match_id) AS previous \
+               FROM trade_history_items \
+               WHERE exchange = $1 AND asset_pair = $2 AND match_id IS NOT NULL \
+               AND happened < $4 \
+               AND (happened >= $3 OR match_id = ( \
+                 SELECT MAX(match_id) FROM trade_history_items \
+                 WHERE exchange = $1 AND asset_pair = $2 AND happened < $3 \
+               )) \
+             ) \
+             SELECT LEAST(previous, happened), GREATEST(previous, happened), \
+             match_id - previous_id - 1 \
+             FROM series \
+             WHERE previous_id IS NOT NULL AND match_id - previous_id > 1 \
+             ORDER BY previous_id ASC",
+            &[ex_id, ap_id, &from, &to]
+        )?;
+
+        let gaps = rows
+            .iter()
+            .map(|row| Gap::new(
+                exchange, asset_pair, GapKind::MatchId, row.get(0), row.get(1), row.get(2),
+            ))
+            .collect();
+
+        Ok(gaps)
+    }
+
+    /// Store the gaps. Gaps already stored are skipped. Returns how many were inserted.
+    pub fn create_gaps(&self, gaps: &[Gap]) -> Result<u64, Error> {
+        let transaction = self.connection.transaction()?;
+        let inserted = self.insert_gaps(&transaction, gaps)?;
+        transaction.commit()?;
+        Ok(inserted)
+    }
+
+    /// Swap the `stored` gap for what is `found` in its place now. Nothing is found once
+    /// the gap has been filled in by a backfill. Returns how many were inserted.
+    pub fn replace_gap(&self, stored: &Gap, found: &[Gap]) -> Result<u64, Error> {
+        let ex_id = self.ex_ids.get(stored.exchange()).ok_or("Invalid exchange")?;
+        let ap_id = self.ap_ids.get(stored.asset_pair()).ok_or("Invalid asset pair")?;
+        let transaction = self.connection.transaction()?;
+
+        transaction.execute(
+            "DELETE FROM trade_history_gaps \
+             WHERE exchange = $1 AND asset_pair = $2 AND kind = $3 AND started = $4",
+            &[ex_id, ap_id, &stored.kind().as_str(), stored.started()]
+        )?;
+        let inserted = self.insert_gaps(&transaction, found)?;
+
+        transaction.commit()?;
+        Ok(inserted)
+    }
+
+    fn insert_gaps<C: GenericConnection>(
+        &self, connection: &C, gaps: &[Gap],
+    ) -> Result<u64, Error> {
+        let create_stmt = connection.prepare_cached(
+            "INSERT INTO trade_history_gaps \
+             ( exchange, asset_pair, kind, started, ended, missing_matches ) \
+             VALUES ( $1, $2, $3, $4, $5, $6 ) \
+             ON CONFLICT DO NOTHING"
+        )?;
+
+        let mut inserted = 0;
+        for gap in gaps.iter() {
+            let ex_id = self.ex_ids.get(gap.exchange()).ok_or("Invalid exchange")?;
+            let ap_id = self.ap_ids.get(gap.asset_pair()).ok_or("Invalid asset pair")?;
+            inserted += create_stmt.execute(&[
+                ex_id,
+                ap_id,
+                &gap.kind().as_str(),
+                gap.started(),
+                gap.ended(),
+                gap.missing_matches(),
+            ])?;
+        }
+
+        Ok(inserted)
+    }
+
+    /// How far the gap scans of the exchange and asset pair have got. `None` before its
+    /// first scan.
+    pub fn read_gap_scan(
+        &self, exchange: exchange::Exchange, asset_pair: asset::Pair,
+    ) -> Result<Option<GapScan>, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Exchange DB not in index.")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Asset pair not in index.")?;
+        let rows = self.connection.query(
+            "SELECT scanned_from, scanned_until FROM trade_history_gap_scans \
+             WHERE exchange = $1 AND asset_pair = $2",
+            &[ex_id, ap_id]
+        )?;
+
+        Ok(rows.iter().next().map(|row| GapScan::new(row.get(0), row.get(1))))
+    }
+
+    /// Record that everything of the exchange and asset pair up to `scanned_until` has been
+    /// scanned. The first scan also sets where scanning started from.
+    pub fn update_gap_scan(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        scanned_from: DateTime<Utc>,
+        scanned_until: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Exchange DB not in index.")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Asset pair not in index.")?;
+        self.connection.execute(
+            "INSERT INTO trade_history_gap_scans \
+             ( exchange, asset_pair, scanned_from, scanned_until ) \
+             VALUES ( $1, $2, $3, $4 ) \
+             ON CONFLICT ( exchange, asset_pair ) \
+             DO UPDATE SET scanned_until = EXCLUDED.scanned_until",
+            &[ex_id, ap_id, &scanned_from, &scanned_until]
+        )?;
+
+        Ok(())
+    }
+
+    /// Stored gaps that overlap `from` to `to`, earliest first.
+    pub fn read_gaps(
+        &self,
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Gap>, Error> {
+        let ex_id = self.ex_ids.get(&exchange).ok_or("Exchange DB not in index.")?;
+        let ap_id = self.ap_ids.get(&asset_pair).ok_or("Asset pair not in index.")?;
+        let rows = self.connection.query(
+            "SELECT kind, started, ended, missing_matches \
+             FROM trade_history_gaps \
+             WHERE exchange = $1 AND asset_pair = $2 AND started < $4 AND ended > $3 \
+             ORDER BY started ASC",
+            &[ex_id, ap_id, &from, &to]
+        )?;
+
+        let mut gaps = Vec::with_capacity(rows.len());
+        for row in rows.iter() {
+            let kind: String = row.get(0);
+            gaps.push(Gap::new(
+                exchange, asset_pair, kind.parse()?, row.get(1), row.get(2), row.get(3),
+            ));
+        }
+
+        Ok(gaps)
+    }
 }
@@ -22,6 +22,17 @@ pub enum Error {
     TradeType(trade::TradeTypeParseError),
 }
 
+impl Error {
+    /// The connection to the database failed rather than the operation. It should be
+    /// replaced. Errors reported by the database itself come with a SQL state code.
+    pub fn is_connection(&self) -> bool {
+        match self {
+            Error::Postgres(ref err) => err.code().is_none(),
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
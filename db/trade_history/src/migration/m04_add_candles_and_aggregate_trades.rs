@@ -0,0 +1,55 @@
+//! Tables for data that the exchange has already computed. Candles and aggregate trades.
+//!
+//! Both are unique on what the exchange uses to identify them so that resending the same
+//! data after a reconnect doesn't duplicate rows.
+use postgres::error::Error as PostgresError;
+use postgres::transaction::Transaction;
+use schemamama_postgres::PostgresMigration;
+
+pub struct AddCandlesAndAggregateTrades;
+
+migration!(AddCandlesAndAggregateTrades, 4, "Add candles and aggregate trades.");
+
+impl PostgresMigration for AddCandlesAndAggregateTrades {
+    fn up(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "CREATE TABLE IF NOT EXISTS candles ( \
+             id BIGSERIAL NOT NULL PRIMARY KEY, \
+             exchange INTEGER NOT NULL REFERENCES exchanges ( id ), \
+             asset_pair INTEGER NOT NULL REFERENCES asset_pairs ( id ), \
+             candle_interval VARCHAR(4) NOT NULL, \
+             open_time TIMESTAMP WITH TIME ZONE NOT NULL, \
+             close_time TIMESTAMP WITH TIME ZONE NOT NULL, \
+             open_price NUMERIC(30,15) NOT NULL, \
+             high_price NUMERIC(30,15) NOT NULL, \
+             low_price NUMERIC(30,15) NOT NULL, \
+             close_price NUMERIC(30,15) NOT NULL, \
+             volume NUMERIC(30,15) NOT NULL, \
+             quote_volume NUMERIC(30,15) NOT NULL, \
+             trades BIGINT NOT NULL, \
+             UNIQUE ( exchange, asset_pair, candle_interval, open_time ) \
+             ); \
+             \
+             CREATE TABLE IF NOT EXISTS aggregate_trades ( \
+             id BIGSERIAL NOT NULL PRIMARY KEY, \
+             exchange INTEGER NOT NULL REFERENCES exchanges ( id ), \
+             asset_pair INTEGER NOT NULL REFERENCES asset_pairs ( id ), \
+             happened TIMESTAMP WITH TIME ZONE NOT NULL, \
+             match_size NUMERIC(30,15) NOT NULL, \
+             match_price NUMERIC(30,15) NOT NULL, \
+             market INTEGER NOT NULL REFERENCES trade_markets ( id ), \
+             aggregate_id BIGINT NOT NULL, \
+             first_match_id BIGINT NOT NULL, \
+             last_match_id BIGINT NOT NULL, \
+             UNIQUE ( exchange, asset_pair, aggregate_id ) \
+             );"
+        )
+    }
+
+    fn down(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "DROP TABLE IF EXISTS aggregate_trades; \
+             DROP TABLE IF EXISTS candles;"
+        )
+    }
+}
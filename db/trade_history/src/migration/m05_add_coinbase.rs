@@ -0,0 +1,31 @@
+//! Adding Coinbase. It only trades asset pairs that are already present.
+use postgres::error::Error as PostgresError;
+use postgres::transaction::Transaction;
+use schemamama_postgres::PostgresMigration;
+
+pub struct AddCoinbase;
+
+migration!(AddCoinbase, 5, "Adding coinbase.");
+
+impl PostgresMigration for AddCoinbase {
+    fn up(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "INSERT INTO exchanges ( label ) VALUES ( 'coinbase' );"
+        )
+    }
+
+    fn down(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "DELETE FROM trade_history_items \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'coinbase'); \
+             \
+             DELETE FROM candles \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'coinbase'); \
+             \
+             DELETE FROM aggregate_trades \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'coinbase'); \
+             \
+             DELETE FROM exchanges WHERE label = 'coinbase';"
+        )
+    }
+}
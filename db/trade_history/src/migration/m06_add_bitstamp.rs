@@ -0,0 +1,31 @@
+//! Adding Bitstamp. It only trades asset pairs that are already present.
+use postgres::error::Error as PostgresError;
+use postgres::transaction::Transaction;
+use schemamama_postgres::PostgresMigration;
+
+pub struct AddBitstamp;
+
+migration!(AddBitstamp, 6, "Adding bitstamp.");
+
+impl PostgresMigration for AddBitstamp {
+    fn up(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "INSERT INTO exchanges ( label ) VALUES ( 'bitstamp' );"
+        )
+    }
+
+    fn down(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "DELETE FROM trade_history_items \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'bitstamp'); \
+             \
+             DELETE FROM candles \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'bitstamp'); \
+             \
+             DELETE FROM aggregate_trades \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'bitstamp'); \
+             \
+             DELETE FROM exchanges WHERE label = 'bitstamp';"
+        )
+    }
+}
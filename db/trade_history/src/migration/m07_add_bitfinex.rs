@@ -0,0 +1,31 @@
+//! Adding Bitfinex. It only trades asset pairs that are already present.
+use postgres::error::Error as PostgresError;
+use postgres::transaction::Transaction;
+use schemamama_postgres::PostgresMigration;
+
+pub struct AddBitfinex;
+
+migration!(AddBitfinex, 7, "Adding bitfinex.");
+
+impl PostgresMigration for AddBitfinex {
+    fn up(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "INSERT INTO exchanges ( label ) VALUES ( 'bitfinex' );"
+        )
+    }
+
+    fn down(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "DELETE FROM trade_history_items \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'bitfinex'); \
+             \
+             DELETE FROM candles \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'bitfinex'); \
+             \
+             DELETE FROM aggregate_trades \
+             WHERE exchange = (SELECT id FROM exchanges WHERE label = 'bitfinex'); \
+             \
+             DELETE FROM exchanges WHERE label = 'bitfinex';"
+        )
+    }
+}
@@ -0,0 +1,85 @@
+//! Make trade history items unique on their natural keys so that resending the same trades
+//! doesn't duplicate rows. Items with a match ID are unique on it. Items without one are
+//! unique on a hash of everything else stored about them.
+//!
+//! The inserts in `crud::Trades` work out the hash the same way as the backfill below. They
+//! have to change together.
+//!
+//! Items without a match ID that share their content with one stored before them may well
+//! be real fills of the same size at the same price and time. They are kept and reported
+//! but get no hash, which leaves them out of the unique index.
+//!
+//! ## Warning
+//! Items stored more than once under the same match ID are deleted before the indexes are
+//! made. Only the first of each is kept. Rolling back doesn't bring them back.
+use postgres::error::Error as PostgresError;
+use postgres::transaction::Transaction;
+use schemamama_postgres::PostgresMigration;
+
+pub struct UniqueTradeHistoryItems;
+
+migration!(UniqueTradeHistoryItems, 8, "Unique trade history items.");
+
+impl PostgresMigration for UniqueTradeHistoryItems {
+    fn up(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "ALTER TABLE IF EXISTS trade_history_items \
+             ADD COLUMN IF NOT EXISTS content_hash CHAR(32) DEFAULT NULL; \
+             \
+             UPDATE trade_history_items SET content_hash = md5(concat_ws( \
+             '|', \
+             to_char(happened AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS.US'), \
+             match_size, \
+             match_price, \
+             market, \
+             coalesce(trade::TEXT, ''), \
+             coalesce(buy_order_id::TEXT, ''), \
+             coalesce(sell_order_id::TEXT, ''), \
+             coalesce(to_char(match_ts AT TIME ZONE 'UTC', \
+                              'YYYY-MM-DD HH24:MI:SS.US'), '') \
+             )); \
+             \
+             DELETE FROM trade_history_items a USING trade_history_items b \
+             WHERE a.match_id IS NOT NULL \
+             AND a.exchange = b.exchange \
+             AND a.asset_pair = b.asset_pair \
+             AND a.match_id = b.match_id \
+             AND a.id > b.id; \
+             \
+             DO $$ \
+             DECLARE alike BIGINT; \
+             BEGIN \
+               UPDATE trade_history_items a SET content_hash = NULL \
+               FROM trade_history_items b \
+               WHERE a.match_id IS NULL AND b.match_id IS NULL \
+               AND a.exchange = b.exchange \
+               AND a.asset_pair = b.asset_pair \
+               AND a.content_hash = b.content_hash \
+               AND a.id > b.id; \
+               GET DIAGNOSTICS alike = ROW_COUNT; \
+               IF alike > 0 THEN \
+                 RAISE NOTICE '% trade history item(s) without a match ID are alike an \
+                   earlier one. Kept, but left out of the unique index.', alike; \
+               END IF; \
+             END $$; \
+             \
+             CREATE UNIQUE INDEX IF NOT EXISTS trade_history_items_match_id_key \
+             ON trade_history_items ( exchange, asset_pair, match_id ) \
+             WHERE match_id IS NOT NULL; \
+             \
+             CREATE UNIQUE INDEX IF NOT EXISTS trade_history_items_content_hash_key \
+             ON trade_history_items ( exchange, asset_pair, content_hash ) \
+             WHERE match_id IS NULL;"
+        )
+    }
+
+    fn down(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "DROP INDEX IF EXISTS trade_history_items_content_hash_key; \
+             DROP INDEX IF EXISTS trade_history_items_match_id_key; \
+             \
+             ALTER TABLE IF EXISTS trade_history_items \
+             DROP COLUMN IF EXISTS content_hash CASCADE;"
+        )
+    }
+}
@@ -0,0 +1,39 @@
+//! Holes in the trade history of an exchange and asset pair. Either a silence much longer
+//! than the usual time between trades or match IDs that skip ahead.
+//!
+//! A gap is unique on where it starts so that scanning the same stretch of trade history
+//! again doesn't duplicate it.
+use postgres::error::Error as PostgresError;
+use postgres::transaction::Transaction;
+use schemamama_postgres::PostgresMigration;
+
+pub struct AddTradeHistoryGaps;
+
+migration!(AddTradeHistoryGaps, 9, "Add trade history gaps.");
+
+impl PostgresMigration for AddTradeHistoryGaps {
+    fn up(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "CREATE TABLE IF NOT EXISTS trade_history_gaps ( \
+             id BIGSERIAL NOT NULL PRIMARY KEY, \
+             exchange INTEGER NOT NULL REFERENCES exchanges ( id ), \
+             asset_pair INTEGER NOT NULL REFERENCES asset_pairs ( id ), \
+             kind VARCHAR(16) NOT NULL, \
+             started TIMESTAMP WITH TIME ZONE NOT NULL, \
+             ended TIMESTAMP WITH TIME ZONE NOT NULL, \
+             missing_matches BIGINT DEFAULT NULL, \
+             detected TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(), \
+             UNIQUE ( exchange, asset_pair, kind, started ) \
+             ); \
+             \
+             CREATE INDEX IF NOT EXISTS trade_history_gaps_range_idx \
+             ON trade_history_gaps ( exchange, asset_pair, started, ended );"
+        )
+    }
+
+    fn down(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "DROP TABLE IF EXISTS trade_history_gaps;"
+        )
+    }
+}
@@ -0,0 +1,30 @@
+//! How far gap scans have got. A row for each exchange and asset pair so that any of its
+//! trade history outside of it is known to be unscanned. Pairs delivered later start their
+//! own scans.
+use postgres::error::Error as PostgresError;
+use postgres::transaction::Transaction;
+use schemamama_postgres::PostgresMigration;
+
+pub struct AddTradeHistoryGapScans;
+
+migration!(AddTradeHistoryGapScans, 10, "Add trade history gap scans.");
+
+impl PostgresMigration for AddTradeHistoryGapScans {
+    fn up(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "CREATE TABLE IF NOT EXISTS trade_history_gap_scans ( \
+             exchange INTEGER NOT NULL REFERENCES exchanges ( id ), \
+             asset_pair INTEGER NOT NULL REFERENCES asset_pairs ( id ), \
+             scanned_from TIMESTAMP WITH TIME ZONE NOT NULL, \
+             scanned_until TIMESTAMP WITH TIME ZONE NOT NULL, \
+             PRIMARY KEY ( exchange, asset_pair ) \
+             );"
+        )
+    }
+
+    fn down(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "DROP TABLE IF EXISTS trade_history_gap_scans;"
+        )
+    }
+}
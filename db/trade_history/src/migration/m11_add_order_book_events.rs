@@ -0,0 +1,38 @@
+//! Order book snapshots and deltas as synchronised by the fetchers.
+//!
+//! Levels are kept as parallel price and size arrays. Events are unique on the exchange
+//! update ID they end on so that resending them after a reconnect doesn't duplicate rows.
+use postgres::error::Error as PostgresError;
+use postgres::transaction::Transaction;
+use schemamama_postgres::PostgresMigration;
+
+pub struct AddOrderBookEvents;
+
+migration!(AddOrderBookEvents, 11, "Add order book events.");
+
+impl PostgresMigration for AddOrderBookEvents {
+    fn up(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "CREATE TABLE IF NOT EXISTS order_book_events ( \
+             id BIGSERIAL NOT NULL PRIMARY KEY, \
+             exchange INTEGER NOT NULL REFERENCES exchanges ( id ), \
+             asset_pair INTEGER NOT NULL REFERENCES asset_pairs ( id ), \
+             kind VARCHAR(8) NOT NULL CHECK ( kind IN ( 'snapshot', 'delta' ) ), \
+             happened TIMESTAMP WITH TIME ZONE NOT NULL, \
+             first_sequence BIGINT NOT NULL, \
+             last_sequence BIGINT NOT NULL, \
+             bid_prices NUMERIC(30,15)[] NOT NULL, \
+             bid_sizes NUMERIC(30,15)[] NOT NULL, \
+             ask_prices NUMERIC(30,15)[] NOT NULL, \
+             ask_sizes NUMERIC(30,15)[] NOT NULL, \
+             UNIQUE ( exchange, asset_pair, kind, last_sequence ) \
+             );"
+        )
+    }
+
+    fn down(&self, transaction: &Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(
+            "DROP TABLE IF EXISTS order_book_events;"
+        )
+    }
+}
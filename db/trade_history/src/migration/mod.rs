@@ -16,6 +16,14 @@ use error::Error;
 mod m01_initial;
 mod m02_add_binance;
 mod m03_extend_trade_history_items;
+mod m04_add_candles_and_aggregate_trades;
+mod m05_add_coinbase;
+mod m06_add_bitstamp;
+mod m07_add_bitfinex;
+mod m08_unique_trade_history_items;
+mod m09_add_trade_history_gaps;
+mod m10_add_trade_history_gap_scans;
+mod m11_add_order_book_events;
 
 /// Prepare all migrations to be run returning the migrator.
 pub fn setup<'a>(
@@ -31,6 +39,20 @@ pub fn setup<'a>(
     migrator.register(Box::new(m01_initial::CreateInitial));
     migrator.register(Box::new(m02_add_binance::MakeAdditions));
     migrator.register(Box::new(m03_extend_trade_history_items::ExtendTradeHistoryItems));
+    migrator.register(Box::new(
+        m04_add_candles_and_aggregate_trades::AddCandlesAndAggregateTrades
+    ));
+    migrator.register(Box::new(m05_add_coinbase::AddCoinbase));
+    migrator.register(Box::new(m06_add_bitstamp::AddBitstamp));
+    migrator.register(Box::new(m07_add_bitfinex::AddBitfinex));
+    migrator.register(Box::new(
+        m08_unique_trade_history_items::UniqueTradeHistoryItems
+    ));
+    migrator.register(Box::new(m09_add_trade_history_gaps::AddTradeHistoryGaps));
+    migrator.register(Box::new(
+        m10_add_trade_history_gap_scans::AddTradeHistoryGapScans
+    ));
+    migrator.register(Box::new(m11_add_order_book_events::AddOrderBookEvents));
 
     Ok(migrator)
 }
@@ -1,5 +1,7 @@
 //! DB models and their conversions
 
+use std::str;
+
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 
@@ -95,3 +97,94 @@ impl TradeSetSummary {
         }
     }
 }
+
+/// Outcome of inserting trade history. Items already stored are counted as duplicates and
+/// left out of `inserted`.
+#[derive(Debug, Clone, Serialize, Deserialize, Getters)]
+pub struct CreatedTradeItems {
+    inserted: Vec<TradeItem>,
+    duplicates: u64,
+}
+
+impl CreatedTradeItems {
+    pub fn new(inserted: Vec<TradeItem>, duplicates: u64) -> Self {
+        CreatedTradeItems {
+            inserted, duplicates
+        }
+    }
+}
+
+/// What gave a gap away.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GapKind {
+    /// No trades for much longer than usual.
+    #[serde(rename = "silence")]
+    Silence,
+    /// Match IDs that skip ahead.
+    #[serde(rename = "match_id")]
+    MatchId,
+}
+
+impl GapKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GapKind::Silence => "silence",
+            GapKind::MatchId => "match_id",
+        }
+    }
+}
+
+impl str::FromStr for GapKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "silence" => Ok(GapKind::Silence),
+            "match_id" => Ok(GapKind::MatchId),
+            _ => Err(format!("Unknown gap kind `{}`.", s)),
+        }
+    }
+}
+
+/// A hole in the trade history between two stored trades. `missing_matches` is how many
+/// match IDs were skipped, if known.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, Getters)]
+pub struct Gap {
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+    kind: GapKind,
+    started: DateTime<Utc>,
+    ended: DateTime<Utc>,
+    missing_matches: Option<i64>,
+}
+
+impl Gap {
+    pub fn new(
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        kind: GapKind,
+        started: DateTime<Utc>,
+        ended: DateTime<Utc>,
+        missing_matches: Option<i64>,
+    ) -> Self {
+        Gap {
+            exchange, asset_pair, kind, started, ended, missing_matches,
+        }
+    }
+}
+
+/// How far the gap scans of an exchange and asset pair have got. Its trade history outside
+/// of it hasn't been scanned.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Getters)]
+pub struct GapScan {
+    scanned_from: DateTime<Utc>,
+    scanned_until: DateTime<Utc>,
+}
+
+impl GapScan {
+    pub fn new(scanned_from: DateTime<Utc>, scanned_until: DateTime<Utc>) -> Self {
+        GapScan {
+            scanned_from, scanned_until,
+        }
+    }
+}
@@ -23,7 +23,11 @@ fn insert_fresh_trade_item() {
         10.into(),
         100.into(),
         trade::Market::Maker,
-        trade::Type::Limit,
+        Some(trade::Type::Limit),
+        None,
+        None,
+        None,
+        None,
     );
 
     let ftis = vec![fti_1];
@@ -39,7 +43,11 @@ fn gen_trade_item(ts: DateTime<Utc>) -> model::FreshTradeItem {
         10.into(),
         100.into(),
         trade::Market::Maker,
-        trade::Type::Limit,
+        Some(trade::Type::Limit),
+        None,
+        None,
+        None,
+        None,
     )
 }
 
@@ -74,3 +82,75 @@ fn search_date_range() {
     assert!(*fetched[1].timestamp() == ts3);
     assert!(*fetched[2].timestamp() == ts4);
 }
+
+#[test]
+fn bulk_insert_skips_stored_items() {
+    let trades = crud::Trades::connect(DATABASE_URL).expect("Can't connect to DB.");
+
+    let start = Utc::now();
+    let ftis: Vec<model::FreshTradeItem> = (0..(crud::BULK_ROWS as i64 + 10))
+        .map(|n| model::FreshTradeItem::new(
+            exchange::Exchange::Kraken,
+            asset::BTC_USD,
+            start + chrono::Duration::microseconds(n),
+            10.into(),
+            100.into(),
+            trade::Market::Maker,
+            Some(trade::Type::Limit),
+            None,
+            None,
+            None,
+            None,
+        ))
+        .collect();
+
+    let created = trades.create_bulk(&ftis[..10]).expect("DB error.");
+    assert_eq!(created.inserted().len(), 10);
+    assert_eq!(*created.duplicates(), 0);
+
+    // Spans more than one multi-row insert.
+    let inserted = trades.create_bulk_counted(&ftis).expect("DB error.");
+    assert_eq!(inserted, crud::BULK_ROWS as u64);
+}
+
+#[test]
+fn fills_apart_only_in_order_ids_are_both_stored() {
+    let trades = crud::Trades::connect(DATABASE_URL).expect("Can't connect to DB.");
+
+    let ts = Utc::now();
+    let fill = |buy_order_id| model::FreshTradeItem::new(
+        exchange::Exchange::Kraken,
+        asset::BTC_USD,
+        ts,
+        10.into(),
+        100.into(),
+        trade::Market::Maker,
+        Some(trade::Type::Limit),
+        None,
+        Some(buy_order_id),
+        None,
+        None,
+    );
+
+    let created = trades.create(&[fill(1), fill(2), fill(2)]).expect("DB error.");
+    assert_eq!(created.inserted().len(), 2);
+    assert_eq!(*created.duplicates(), 1);
+}
+
+#[test]
+fn gap_scans_are_kept_per_asset_pair() {
+    let trades = crud::Trades::connect(DATABASE_URL).expect("Can't connect to DB.");
+
+    let binance = exchange::Exchange::Binance;
+    let from = Utc.ymd(2014, 1, 1).and_hms(0, 0, 0);
+    let eth_until = Utc::now();
+    let bnb_until = eth_until - chrono::Duration::hours(1);
+
+    trades.update_gap_scan(binance, asset::ETH_BTC, from, eth_until).expect("DB error.");
+    trades.update_gap_scan(binance, asset::BNB_ETH, from, bnb_until).expect("DB error.");
+
+    let eth = trades.read_gap_scan(binance, asset::ETH_BTC).expect("DB error.").unwrap();
+    let bnb = trades.read_gap_scan(binance, asset::BNB_ETH).expect("DB error.").unwrap();
+    assert_eq!(eth.scanned_until().timestamp(), eth_until.timestamp());
+    assert_eq!(bnb.scanned_until().timestamp(), bnb_until.timestamp());
+}
@@ -0,0 +1,110 @@
+//! Small HTTP endpoint to change the streams of the running fetcher.
+//!
+//! * `GET /streams` lists what is subscribed and what is still waiting on binance.
+//! * `PUT /streams/{kind}/{left}/{right}` subscribes.
+//! * `DELETE /streams/{kind}/{left}/{right}` unsubscribes.
+//!
+//! `kind` is the binance stream name: `trade`, `depth`, `aggTrade` or `kline_1m` etc.
+use std::net::SocketAddr;
+
+use futures::Future;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::service::service_fn_ok;
+use serde::Serialize;
+use serde_json;
+
+use common::asset;
+
+use control::Subscriptions;
+use subscription::StreamType;
+
+#[derive(Debug, Serialize)]
+struct Accepted {
+    request_id: u64,
+}
+
+fn json<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    let body = serde_json::to_string(body).expect("Serializable body.");
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .expect("Valid response.")
+}
+
+fn empty(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .expect("Valid response.")
+}
+
+/// Read the stream out of `/streams/{kind}/{left}/{right}`.
+fn stream_from_path(path: &str) -> Result<StreamType, StatusCode> {
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    if parts.len() != 4 || parts[0] != "streams" {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let left: asset::Asset = parts[2].parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let right: asset::Asset = parts[3].parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    StreamType::from_kind(parts[1], asset::Pair::new(left, right))
+        .ok_or(StatusCode::BAD_REQUEST)
+}
+
+fn route(subscriptions: &Subscriptions, req: Request<Body>) -> Response<Body> {
+    let path = req.uri().path().to_owned();
+
+    if path.trim_matches('/') == "streams" {
+        return match req.method() {
+            &Method::GET => json(StatusCode::OK, &subscriptions.status()),
+            _ => empty(StatusCode::METHOD_NOT_ALLOWED),
+        };
+    }
+
+    let stream = match stream_from_path(&path) {
+        Ok(stream) => stream,
+        Err(status) => return empty(status),
+    };
+
+    let request_id = match req.method() {
+        &Method::PUT => subscriptions.subscribe(stream),
+        &Method::DELETE => subscriptions.unsubscribe(stream),
+        _ => return empty(StatusCode::METHOD_NOT_ALLOWED),
+    };
+
+    match request_id {
+        Some(request_id) => json(StatusCode::ACCEPTED, &Accepted { request_id }),
+        // Already subscribed, or never was. Nothing to do.
+        None => empty(StatusCode::NO_CONTENT),
+    }
+}
+
+/// Serve the admin endpoint on `addr`.
+pub fn serve(
+    addr: SocketAddr, subscriptions: Subscriptions,
+) -> impl Future<Item = (), Error = ()> {
+    info!("Admin endpoint listening on {}", &addr);
+    Server::bind(&addr)
+        .serve(move || {
+            let subscriptions = subscriptions.clone();
+            service_fn_ok(move |req| route(&subscriptions, req))
+        })
+        .map_err(|e| error!("Admin endpoint failure: {}", &e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stream_path() {
+        assert_eq!(
+            stream_from_path("/streams/trade/BTC/USD"),
+            Ok(StreamType::TradeHistoryItems(asset::BTC_USD)),
+        );
+        assert_eq!(stream_from_path("/streams/bogus/BTC/USD"), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(stream_from_path("/streams/trade/BTC"), Err(StatusCode::NOT_FOUND));
+    }
+}
@@ -0,0 +1,371 @@
+//! Local order books kept in sync with the binance depth diff streams.
+//!
+//! Follows the procedure binance documents for managing a local book;
+//! 1. Buffer the diffs received on the stream.
+//! 2. Fetch a depth snapshot over REST.
+//! 3. Drop any buffered diff where `u` is <= `lastUpdateId` of the snapshot.
+//! 4. The first diff applied must have `U` <= `lastUpdateId` + 1 and `u` >= `lastUpdateId` + 1.
+//! 5. Every diff after that must have `U` equal to the previous `u` + 1.
+//!
+//! Should a diff ever skip ahead the book is thrown away and rebuilt from a fresh snapshot.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::mem;
+
+use futures::{Future, Stream, Poll, Async};
+use hyper::Uri;
+use rust_decimal::Decimal;
+use chrono::{DateTime, Utc};
+use serde_json;
+
+use fetch_lib::https_client::{HttpsClient, FetchError};
+use common::asset;
+use common::book::{Level, BookSnapshot, BookDelta, BookEvent};
+
+use payload::{self, DepthUpdate, DepthSnapshot};
+use subscription::symbol;
+
+/// How many levels to ask for in a REST snapshot. The most binance will give.
+const SNAPSHOT_DEPTH: u32 = 1000;
+
+/// Diffs kept whilst waiting for a snapshot. Protects us if snapshots keep failing.
+const MAX_BUFFERED: usize = 10_000;
+
+/// Outcome of applying a diff to a local book.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Apply {
+    Applied(BookDelta),
+
+    /// The diff is older than the book. Nothing to do.
+    Stale,
+
+    /// The diff skips ahead of the book. Updates have been missed.
+    Gap,
+}
+
+fn into_levels(levels: &[payload::Level]) -> Vec<Level> {
+    levels
+        .iter()
+        .map(|&(price, size)| Level::new(price, size))
+        .collect()
+}
+
+/// Apply levels to one side of the book. Zero quantity levels are removed.
+fn update_side(side: &mut BTreeMap<Decimal, Decimal>, levels: &[payload::Level]) {
+    let zero: Decimal = 0.into();
+    for &(price, size) in levels.iter() {
+        if size == zero {
+            side.remove(&price);
+        } else {
+            side.insert(price, size);
+        }
+    }
+}
+
+/// Order book for a single asset pair.
+#[derive(Debug, Clone)]
+pub struct LocalBook {
+    sequence: u64,
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl LocalBook {
+    pub fn from_snapshot(snapshot: &DepthSnapshot) -> Self {
+        let mut book = LocalBook {
+            sequence: snapshot.last_update_id(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        update_side(&mut book.bids, snapshot.bids());
+        update_side(&mut book.asks, snapshot.asks());
+        book
+    }
+
+    /// Update ID the book is current up to.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn apply(&mut self, update: &DepthUpdate) -> Apply {
+        if update.final_update_id() <= self.sequence {
+            return Apply::Stale;
+        }
+
+        if update.first_update_id() > self.sequence + 1 {
+            return Apply::Gap;
+        }
+
+        // A diff with no time can't be stored. Resync as though it was missed.
+        let timestamp = match update.timestamp() {
+            Some(timestamp) => timestamp,
+            None => return Apply::Gap,
+        };
+
+        update_side(&mut self.bids, update.bids());
+        update_side(&mut self.asks, update.asks());
+        let first = self.sequence + 1;
+        self.sequence = update.final_update_id();
+
+        Apply::Applied(BookDelta::new(
+            timestamp,
+            first,
+            self.sequence,
+            into_levels(update.bids()),
+            into_levels(update.asks()),
+        ))
+    }
+
+    pub fn snapshot(&self, timestamp: DateTime<Utc>) -> BookSnapshot {
+        BookSnapshot::new(
+            timestamp,
+            self.sequence,
+            self.bids.iter().rev().map(|(p, s)| Level::new(*p, *s)).collect(),
+            self.asks.iter().map(|(p, s)| Level::new(*p, *s)).collect(),
+        )
+    }
+}
+
+/// Request a depth snapshot for the asset pair from the REST API at `rest`.
+fn fetch_snapshot(
+    client: &HttpsClient, rest: &str, pair: asset::Pair,
+) -> Box<Future<Item = DepthSnapshot, Error = FetchError> + Send> {
+    let uri: Uri = format!(
+        "{}/api/v3/depth?symbol={}&limit={}", rest, symbol(pair), SNAPSHOT_DEPTH
+    )
+        .parse()
+        .expect("Invalid REST URI constructed. Check the configured base.");
+
+    let fut = client
+        .get(uri)
+        .from_err::<FetchError>()
+        .and_then(|res| {
+            let status = res.status().as_u16();
+            res.into_body()
+                .concat2()
+                .from_err::<FetchError>()
+                .and_then(move |body| {
+                    if status != 200 {
+                        return Err(FetchError::Status(status));
+                    }
+                    let snapshot: DepthSnapshot = serde_json::from_slice(&body)?;
+                    Ok(snapshot)
+                })
+        });
+
+    Box::new(fut)
+}
+
+/// Sync state of a single asset pair.
+struct PairSync {
+    book: Option<LocalBook>,
+    buffer: VecDeque<DepthUpdate>,
+    snapshot: Option<Box<Future<Item = DepthSnapshot, Error = FetchError> + Send>>,
+}
+
+impl PairSync {
+    fn new() -> Self {
+        PairSync {
+            book: None,
+            buffer: VecDeque::new(),
+            snapshot: None,
+        }
+    }
+
+    fn hold(&mut self, update: DepthUpdate) {
+        if self.buffer.len() >= MAX_BUFFERED {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(update);
+    }
+}
+
+/// Stream that takes in the depth diffs of any number of asset pairs and yields the
+/// snapshots and deltas of their local books.
+pub struct BookSync<S> {
+    client: HttpsClient,
+    rest: String,
+    diffs: S,
+    pairs: HashMap<asset::Pair, PairSync>,
+    ready: VecDeque<(asset::Pair, BookEvent)>,
+}
+
+impl<S> BookSync<S>
+where S: Stream<Item = (asset::Pair, DepthUpdate), Error = ()>
+{
+    pub fn new(client: HttpsClient, rest: &str, diffs: S) -> Self {
+        BookSync {
+            client: client,
+            rest: rest.to_owned(),
+            diffs: diffs,
+            pairs: HashMap::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    fn receive(&mut self, pair: asset::Pair, update: DepthUpdate) {
+        let sync = self.pairs.entry(pair).or_insert_with(PairSync::new);
+
+        let outcome = sync.book.as_mut().map(|book| book.apply(&update));
+        match outcome {
+            Some(Apply::Applied(delta)) => {
+                self.ready.push_back((pair, BookEvent::Delta(delta)));
+                return;
+            },
+            Some(Apply::Stale) => return,
+            Some(Apply::Gap) => {
+                warn!("{} order book missed updates. Rebuilding.", &pair);
+                sync.book = None;
+            },
+            None => (),
+        }
+
+        sync.hold(update);
+        if sync.snapshot.is_none() {
+            sync.snapshot = Some(fetch_snapshot(&self.client, &self.rest, pair));
+        }
+    }
+
+    fn synchronise(&mut self, pair: asset::Pair, result: Result<DepthSnapshot, FetchError>) {
+        let sync = self.pairs.get_mut(&pair).expect("Snapshot for an unknown pair.");
+
+        let snapshot = match result {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                // The next diff to arrive will ask for another.
+                error!("{} order book snapshot failed: {}", &pair, &e);
+                return;
+            },
+        };
+
+        let mut book = LocalBook::from_snapshot(&snapshot);
+        let mut events = vec![BookEvent::Snapshot(book.snapshot(Utc::now()))];
+        let buffered = mem::replace(&mut sync.buffer, VecDeque::new());
+
+        let mut gap = false;
+        for update in buffered.iter() {
+            match book.apply(update) {
+                Apply::Applied(delta) => events.push(BookEvent::Delta(delta)),
+                Apply::Stale => (),
+                Apply::Gap => {
+                    gap = true;
+                    break;
+                },
+            }
+        }
+
+        if gap {
+            // Snapshot is older than what we've buffered. Try again.
+            debug!("{} snapshot {} too old. Refetching.", &pair, book.sequence());
+            sync.buffer = buffered;
+            sync.snapshot = Some(fetch_snapshot(&self.client, &self.rest, pair));
+            return;
+        }
+
+        debug!("{} order book synchronised at {}.", &pair, book.sequence());
+        sync.book = Some(book);
+        self.ready.extend(events.into_iter().map(|event| (pair, event)));
+    }
+}
+
+impl<S> Stream for BookSync<S>
+where S: Stream<Item = (asset::Pair, DepthUpdate), Error = ()>
+{
+    type Item = (asset::Pair, BookEvent);
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Ok(Async::Ready(Some(event)));
+            }
+
+            match self.diffs.poll()? {
+                Async::Ready(Some((pair, update))) => {
+                    self.receive(pair, update);
+                    continue;
+                },
+                Async::Ready(None) => return Ok(Async::Ready(None)),
+                Async::NotReady => (),
+            }
+
+            let mut arrived = Vec::new();
+            for (pair, sync) in self.pairs.iter_mut() {
+                let polled = match sync.snapshot.as_mut().map(|fut| fut.poll()) {
+                    Some(Ok(Async::Ready(snapshot))) => Some(Ok(snapshot)),
+                    Some(Err(e)) => Some(Err(e)),
+                    Some(Ok(Async::NotReady)) | None => None,
+                };
+
+                if let Some(result) = polled {
+                    sync.snapshot = None;
+                    arrived.push((*pair, result));
+                }
+            }
+
+            if arrived.is_empty() {
+                return Ok(Async::NotReady);
+            }
+
+            for (pair, result) in arrived.into_iter() {
+                self.synchronise(pair, result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(json: &str) -> DepthSnapshot {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn update(json: &str) -> DepthUpdate {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn applies_diffs_in_sequence() {
+        let mut book = LocalBook::from_snapshot(&snapshot(
+            r##"{"lastUpdateId":100,"bids":[["10","1"],["9","2"]],"asks":[["11","1"]]}"##
+        ));
+
+        // Entirely before the snapshot.
+        let stale = update(r##"{"E":1,"s":"BNBBTC","U":90,"u":100,"b":[],"a":[]}"##);
+        assert_eq!(book.apply(&stale), Apply::Stale);
+
+        // Straddles the snapshot. Allowed as the first diff.
+        let first = update(
+            r##"{"E":1,"s":"BNBBTC","U":98,"u":102,"b":[["10","0"]],"a":[["12","3"]]}"##
+        );
+        match book.apply(&first) {
+            Apply::Applied(delta) => {
+                assert_eq!(*delta.first_sequence(), 101);
+                assert_eq!(*delta.last_sequence(), 102);
+            },
+            other => panic!("Expected applied. Got {:?}", other),
+        }
+
+        let next = update(r##"{"E":1,"s":"BNBBTC","U":103,"u":103,"b":[],"a":[]}"##);
+        assert!(if let Apply::Applied(_) = book.apply(&next) { true } else { false });
+
+        let snap = book.snapshot(Utc::now());
+        assert_eq!(*snap.sequence(), 103);
+        assert_eq!(snap.bids().len(), 1);
+        assert_eq!(*snap.bids()[0].price(), 9.into());
+        assert_eq!(snap.asks().len(), 2);
+        assert_eq!(*snap.asks()[0].price(), 11.into());
+    }
+
+    #[test]
+    fn detects_gaps() {
+        let mut book = LocalBook::from_snapshot(&snapshot(
+            r##"{"lastUpdateId":100,"bids":[],"asks":[]}"##
+        ));
+
+        let skipped = update(r##"{"E":1,"s":"BNBBTC","U":102,"u":105,"b":[],"a":[]}"##);
+        assert_eq!(book.apply(&skipped), Apply::Gap);
+        assert_eq!(book.sequence(), 100);
+    }
+}
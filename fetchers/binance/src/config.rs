@@ -1,19 +1,52 @@
 //! Configuration
 use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 use common::asset;
+use common::candle::Interval;
 use common::errors::ConfigError;
 
-use lib::StreamRequest;
+use lib::{StreamRequest, DEFAULT_ROTATE_AFTER_SECS, DEFAULT_STALE_AFTER_SECS};
 
 static COLLECTOR: &str = "SAMMY_COLLECTOR";
 static BINANCE_BASE_URI: &str = "BINANCE_BASE_URI";
+static BINANCE_REST_URI: &str = "BINANCE_REST_URI";
+static BINANCE_API_KEY: &str = "BINANCE_API_KEY";
 static TRADE_HISTORY: &str = "TRADE_HISTORY_STREAMS";
+static ORDER_BOOK: &str = "ORDER_BOOK_STREAMS";
+static KLINE: &str = "KLINE_STREAMS";
+static KLINE_INTERVALS: &str = "KLINE_INTERVALS";
+static AGGREGATE_TRADE: &str = "AGGREGATE_TRADE_STREAMS";
+static ROTATE_AFTER: &str = "BINANCE_ROTATE_AFTER_SECS";
+static STALE_AFTER: &str = "BINANCE_STALE_AFTER_SECS";
+static ADMIN_LISTEN: &str = "BINANCE_ADMIN_LISTEN";
+
+static DEFAULT_REST_URI: &str = "https://api.binance.com";
+static DEFAULT_KLINE_INTERVALS: &str = "1m";
+
+/// Colon separated asset pairs in the env var. Empty if it isn't set.
+fn optional_asset_pairs(var: &str) -> Vec<asset::Pair> {
+    env::var(var)
+        .ok()
+        .map(|streams| {
+            streams
+                .split(':')
+                .map(|ap_str| ap_str.parse().expect("Invalid asset pair code."))
+                .collect()
+        })
+        .unwrap_or_default()
+}
 
 #[derive(Debug, Clone)]
 pub struct Configuration {
     collector: String,
-    subscribe: StreamRequest,    
+    rest_uri: String,
+    api_key: Option<String>,
+    subscribe: StreamRequest,
+    rotate_after: Duration,
+    stale_after: Duration,
+    admin_listen: Option<SocketAddr>,
 }
 
 impl Configuration {
@@ -21,14 +54,41 @@ impl Configuration {
         self.collector.as_str()
     }
 
+    /// Base of the REST API. Used to fetch order book snapshots and missed trades.
+    pub fn rest_uri(&self) -> &str {
+        self.rest_uri.as_str()
+    }
+
+    /// Lets missed trades be fetched exactly. Otherwise aggregates are used.
+    pub fn api_key(&self) -> Option<String> {
+        self.api_key.clone()
+    }
+
     pub fn subscribe(&self) -> StreamRequest {
         self.subscribe.clone()
     }
+
+    /// How long a connection is kept before it is replaced by a fresh one.
+    pub fn rotate_after(&self) -> Duration {
+        self.rotate_after
+    }
+
+    /// How long a connection can go without receiving anything before it is replaced.
+    pub fn stale_after(&self) -> Duration {
+        self.stale_after
+    }
+
+    /// Where the admin endpoint listens. It isn't started if this isn't set.
+    pub fn admin_listen(&self) -> Option<SocketAddr> {
+        self.admin_listen
+    }
 }
 
 pub fn config_from_environment() -> Result<Configuration, ConfigError> {
     let collector = env::var(COLLECTOR).map_err(|e| (COLLECTOR, e))?;
     let base_uri = env::var(BINANCE_BASE_URI).ok();
+    let rest_uri = env::var(BINANCE_REST_URI).unwrap_or(DEFAULT_REST_URI.to_owned());
+    let api_key = env::var(BINANCE_API_KEY).ok();
     let trade_history_streams = env::var(TRADE_HISTORY).map_err(|e| (TRADE_HISTORY, e))?;
 
     let th_asset_pairs: Vec<asset::Pair> = trade_history_streams
@@ -36,6 +96,16 @@ pub fn config_from_environment() -> Result<Configuration, ConfigError> {
         .map(|ap_str| ap_str.parse().expect("Invalid asset pair code."))
         .collect();
 
+    let ob_asset_pairs = optional_asset_pairs(ORDER_BOOK);
+    let kl_asset_pairs = optional_asset_pairs(KLINE);
+    let at_asset_pairs = optional_asset_pairs(AGGREGATE_TRADE);
+
+    let kl_intervals: Vec<Interval> = env::var(KLINE_INTERVALS)
+        .unwrap_or(DEFAULT_KLINE_INTERVALS.to_owned())
+        .split(':')
+        .map(|i_str| i_str.parse().expect("Invalid kline interval."))
+        .collect();
+
     let mut subscribe = StreamRequest::new();
 
     if let Some(uri) = base_uri {
@@ -46,7 +116,42 @@ pub fn config_from_environment() -> Result<Configuration, ConfigError> {
         subscribe = subscribe.add_trade_history_item_stream(ap);
     }
 
+    for ap in ob_asset_pairs.into_iter() {
+        subscribe = subscribe.add_order_book_stream(ap);
+    }
+
+    for ap in kl_asset_pairs.into_iter() {
+        for interval in kl_intervals.iter() {
+            subscribe = subscribe.add_kline_stream(ap, *interval);
+        }
+    }
+
+    for ap in at_asset_pairs.into_iter() {
+        subscribe = subscribe.add_aggregate_trade_stream(ap);
+    }
+
+    let rotate_after = match env::var(ROTATE_AFTER) {
+        Ok(secs) => secs.parse().map_err(|e| (ROTATE_AFTER, e))?,
+        Err(_) => DEFAULT_ROTATE_AFTER_SECS,
+    };
+
+    let stale_after = match env::var(STALE_AFTER) {
+        Ok(secs) => secs.parse().map_err(|e| (STALE_AFTER, e))?,
+        Err(_) => DEFAULT_STALE_AFTER_SECS,
+    };
+
+    let admin_listen = match env::var(ADMIN_LISTEN) {
+        Ok(addr) => Some(addr.parse().map_err(|e| (ADMIN_LISTEN, e))?),
+        Err(_) => None,
+    };
+
     Ok(Configuration {
-        collector, subscribe        
+        collector,
+        rest_uri,
+        api_key,
+        subscribe,
+        rotate_after: Duration::from_secs(rotate_after),
+        stale_after: Duration::from_secs(stale_after),
+        admin_listen,
     })
 }
@@ -0,0 +1,365 @@
+//! Change the subscribed streams whilst connected.
+//!
+//! Binance takes `SUBSCRIBE` and `UNSUBSCRIBE` frames on a live connection. Each carries an
+//! ID which binance replies to once it has acted on it. The streams wanted are kept in a
+//! shared `StreamRequest` so that any new connection, be it a rotation or a reconnect,
+//! starts with the current set.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde_json;
+use futures::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::tungstenite::Message;
+
+use subscription::{StreamRequest, StreamType};
+
+static SUBSCRIBE: &str = "SUBSCRIBE";
+static UNSUBSCRIBE: &str = "UNSUBSCRIBE";
+
+#[derive(Debug, Serialize)]
+struct Frame<'a> {
+    method: &'a str,
+    params: Vec<String>,
+    id: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplyError {
+    code: i64,
+    msg: String,
+}
+
+/// Binance's reply to a frame.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reply {
+    id: u64,
+    #[serde(default)]
+    error: Option<ReplyError>,
+}
+
+/// A frame sent that hasn't been replied to on every connection yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct Pending {
+    id: u64,
+    method: &'static str,
+    streams: Vec<String>,
+    sent: DateTime<Utc>,
+
+    #[serde(skip)]
+    awaiting: usize,
+    #[serde(skip)]
+    acknowledged: usize,
+    #[serde(skip)]
+    refused: Vec<usize>,
+    #[serde(skip)]
+    retry: bool,
+}
+
+/// What is subscribed and what is still waiting on binance.
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    streams: Vec<String>,
+    pending: Vec<Pending>,
+}
+
+/// Writes frames to a connection.
+type Sender = UnboundedSender<Message>;
+
+struct Inner {
+    request: StreamRequest,
+    connections: HashMap<usize, Sender>,
+    pending: BTreeMap<u64, Pending>,
+    next_id: u64,
+}
+
+impl Inner {
+    /// Send a frame on every live connection. Returns the frame ID.
+    fn send(&mut self, method: &'static str, streams: Vec<StreamType>) -> u64 {
+        let targets: Vec<(usize, Sender)> = self.connections
+            .iter()
+            .map(|(conn, sender)| (*conn, sender.clone()))
+            .collect();
+        self.send_to(&targets, method, streams)
+    }
+
+    /// Send a frame on the connections given. Returns the frame ID.
+    fn send_to(
+        &mut self, targets: &[(usize, Sender)], method: &'static str, streams: Vec<StreamType>,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let params: Vec<String> = streams.iter().map(|st| st.to_string()).collect();
+        let frame = serde_json::to_string(&Frame { method, params: params.clone(), id })
+            .expect("Control frame always serializes.");
+
+        let awaiting = targets
+            .iter()
+            .filter(|(conn, sender)| match sender.unbounded_send(Message::Text(frame.clone())) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("Can't send {} to connection {}: {}", method, conn, &e);
+                    false
+                },
+            })
+            .count();
+
+        if awaiting > 0 {
+            self.pending.insert(id, Pending {
+                id, method, streams: params, sent: Utc::now(), awaiting,
+                acknowledged: 0, refused: Vec::new(), retry: false,
+            });
+        }
+
+        id
+    }
+}
+
+/// Shared handle on the subscribed streams and the live connections.
+#[derive(Clone)]
+pub struct Subscriptions {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Subscriptions {
+    pub fn new(request: StreamRequest) -> Self {
+        let inner = Inner {
+            request: request,
+            connections: HashMap::new(),
+            pending: BTreeMap::new(),
+            next_id: 1,
+        };
+
+        Subscriptions {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<Inner> {
+        self.inner.lock().expect("Subscriptions lock poisoned.")
+    }
+
+    /// The request for a new connection. Pass the streams back in `register` once open.
+    pub fn request(&self) -> StreamRequest {
+        self.lock().request.clone()
+    }
+
+    /// Add an open connection. Anything that changed since it was requested is caught up.
+    pub fn register(&self, conn: usize, sender: Sender, connected_with: &[StreamType]) {
+        let mut inner = self.lock();
+        let current = inner.request.streams();
+
+        let missing: Vec<StreamType> = current
+            .iter()
+            .filter(|st| !connected_with.contains(st))
+            .cloned()
+            .collect();
+        let extra: Vec<StreamType> = connected_with
+            .iter()
+            .filter(|st| !current.contains(st))
+            .cloned()
+            .collect();
+
+        let target = [(conn, sender.clone())];
+        if !missing.is_empty() {
+            inner.send_to(&target, SUBSCRIBE, missing);
+        }
+        if !extra.is_empty() {
+            inner.send_to(&target, UNSUBSCRIBE, extra);
+        }
+
+        inner.connections.insert(conn, sender);
+    }
+
+    /// Remove a connection once closed. It won't be replying anymore.
+    pub fn unregister(&self, conn: usize) {
+        let mut inner = self.lock();
+        inner.connections.remove(&conn);
+        if inner.connections.is_empty() {
+            inner.pending.clear();
+        }
+    }
+
+    /// Subscribe to the stream on all connections. Returns the frame ID or `None` if it's
+    /// already subscribed.
+    pub fn subscribe(&self, stream: StreamType) -> Option<u64> {
+        let mut inner = self.lock();
+        if inner.request.add_stream(stream) {
+            info!("Subscribing to {}.", &stream);
+            Some(inner.send(SUBSCRIBE, vec![stream]))
+        } else {
+            None
+        }
+    }
+
+    /// Unsubscribe from the stream on all connections. Returns the frame ID or `None` if it
+    /// wasn't subscribed.
+    pub fn unsubscribe(&self, stream: StreamType) -> Option<u64> {
+        let mut inner = self.lock();
+        if inner.request.remove_stream(&stream) {
+            info!("Unsubscribing from {}.", &stream);
+            Some(inner.send(UNSUBSCRIBE, vec![stream]))
+        } else {
+            None
+        }
+    }
+
+    /// Record binance's reply to a frame. Once every connection has replied, a subscribe
+    /// some of them refused is sent once more to just those. A subscribe refused by every
+    /// connection is taken back out of the request so the status reflects what binance is
+    /// actually sending.
+    pub fn acknowledge(&self, conn: usize, reply: Reply) {
+        let mut inner = self.lock();
+
+        let done = match inner.pending.get_mut(&reply.id) {
+            Some(pending) => {
+                pending.awaiting = pending.awaiting.saturating_sub(1);
+                match reply.error {
+                    Some(error) => {
+                        error!(
+                            "Binance refused {} {:?} on connection {}: {} {}",
+                            pending.method, &pending.streams, conn, error.code, &error.msg,
+                        );
+                        pending.refused.push(conn);
+                    },
+                    None => pending.acknowledged += 1,
+                }
+                pending.awaiting == 0
+            },
+            None => {
+                warn!("Connection {} replied to unknown frame {}.", conn, reply.id);
+                return;
+            },
+        };
+
+        if !done {
+            return;
+        }
+
+        let pending = inner.pending.remove(&reply.id).expect("Checked above.");
+        let streams: Vec<StreamType> = inner.request
+            .streams()
+            .into_iter()
+            .filter(|st| pending.streams.contains(&st.to_string()))
+            .collect();
+
+        if pending.refused.is_empty() {
+            debug!("Binance acknowledged {} {:?}.", pending.method, &pending.streams);
+        } else if pending.retry {
+            warn!(
+                "Binance refused {} {:?} again on connections {:?}. Leaving them as is.",
+                pending.method, &pending.streams, &pending.refused,
+            );
+        } else if pending.method == SUBSCRIBE && pending.acknowledged == 0 {
+            for stream in streams.iter() {
+                inner.request.remove_stream(stream);
+            }
+        } else if pending.method == SUBSCRIBE && !streams.is_empty() {
+            let targets: Vec<(usize, Sender)> = pending.refused
+                .iter()
+                .filter_map(|conn| {
+                    inner.connections.get(conn).map(|sender| (*conn, sender.clone()))
+                })
+                .collect();
+
+            if !targets.is_empty() {
+                info!(
+                    "Retrying {} {:?} on connections {:?}.",
+                    SUBSCRIBE, &pending.streams, &pending.refused,
+                );
+                let id = inner.send_to(&targets, SUBSCRIBE, streams);
+                if let Some(retry) = inner.pending.get_mut(&id) {
+                    retry.retry = true;
+                }
+            }
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        let inner = self.lock();
+        Status {
+            streams: inner.request.streams().iter().map(|st| st.to_string()).collect(),
+            pending: inner.pending.values().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::Stream;
+    use futures::sync::mpsc::unbounded;
+    use common::asset;
+
+    #[test]
+    fn reply_deserialize() {
+        let ok: Reply = serde_json::from_str(r##"{"result":null,"id":3}"##).unwrap();
+        assert_eq!(ok.id, 3);
+        assert!(ok.error.is_none());
+
+        let refused: Reply = serde_json::from_str(
+            r##"{"error":{"code":2,"msg":"Invalid request"},"id":4}"##
+        ).unwrap();
+        assert_eq!(refused.error.unwrap().code, 2);
+    }
+
+    #[test]
+    fn subscribe_without_connections() {
+        let subscriptions = Subscriptions::new(StreamRequest::new());
+        let stream = StreamType::TradeHistoryItems(asset::BNB_BTC);
+
+        assert_eq!(subscriptions.subscribe(stream), Some(1));
+        assert_eq!(subscriptions.subscribe(stream), None);
+        assert_eq!(subscriptions.request().streams(), vec![stream]);
+        assert!(subscriptions.status().pending.is_empty());
+
+        assert_eq!(subscriptions.unsubscribe(stream), Some(2));
+        assert!(subscriptions.request().streams().is_empty());
+    }
+
+    #[test]
+    fn partially_refused_subscribe_is_retried() {
+        let subscriptions = Subscriptions::new(StreamRequest::new());
+        let stream = StreamType::TradeHistoryItems(asset::BNB_BTC);
+        let (first, _first_rx) = unbounded();
+        let (second, second_rx) = unbounded();
+        subscriptions.register(1, first, &[]);
+        subscriptions.register(2, second, &[]);
+
+        let id = subscriptions.subscribe(stream).unwrap();
+        subscriptions.acknowledge(1, Reply { id, error: None });
+        subscriptions.acknowledge(2, Reply {
+            id,
+            error: Some(ReplyError { code: 2, msg: "Invalid request".to_owned() }),
+        });
+
+        assert_eq!(subscriptions.request().streams(), vec![stream]);
+        let status = subscriptions.status();
+        assert_eq!(status.pending.len(), 1);
+        assert_eq!(status.pending[0].refused.len(), 0);
+        assert_eq!(status.pending[0].awaiting, 1);
+        assert!(status.pending[0].retry);
+
+        let sent: Vec<Message> = second_rx.take(2).wait().map(|m| m.unwrap()).collect();
+        assert_eq!(sent.len(), 2);
+    }
+
+    #[test]
+    fn subscribe_refused_everywhere_is_dropped() {
+        let subscriptions = Subscriptions::new(StreamRequest::new());
+        let stream = StreamType::TradeHistoryItems(asset::BNB_BTC);
+        let (sender, _rx) = unbounded();
+        subscriptions.register(1, sender, &[]);
+
+        let id = subscriptions.subscribe(stream).unwrap();
+        subscriptions.acknowledge(1, Reply {
+            id,
+            error: Some(ReplyError { code: 2, msg: "Invalid request".to_owned() }),
+        });
+
+        assert!(subscriptions.request().streams().is_empty());
+        assert!(subscriptions.status().pending.is_empty());
+    }
+}
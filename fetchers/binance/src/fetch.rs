@@ -1,126 +1,680 @@
-//! Code that manages the fetching of the data from binance. Will run a websocket connection
-//! inside an infinite loop (binance disconnects you automatically every 24 hours) that will
-//! forward payload items to the rest of the system.
+//! Code that manages the fetching of the data from binance. Runs the websocket connection
+//! on the tokio runtime inside a reconnect loop (binance disconnects you automatically every
+//! 24 hours) that will forward payload items to the rest of the system.
 //!
 //! Binance websocket API allows us to fetch multiple streams within a single connection.
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+//!
+//! To avoid losing trades when binance drops the connection at the 24 hour mark, a
+//! replacement connection is opened shortly before that. Once it is up the old one is
+//! closed. Trades received on both during the overlap are only forwarded once thanks to the
+//! sequential trade ID.
+//!
+//! Connections that go quiet without closing are caught by pinging binance and watching for
+//! traffic. One silent for longer than the stale threshold is dropped and replaced.
+//!
+//! Everything winds down once the shutdown future resolves. Connections are closed and the
+//! forward channels dropped so whatever is downstream can finish with what it already has.
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use futures::{Async, Future, Poll, Sink, Stream};
+use futures::future;
+use futures::stream;
+use futures::sync::{mpsc, oneshot};
 use futures::sync::mpsc::UnboundedSender;
 use serde_json;
-use ws::{self, Sender, Handler, Message, Handshake, util::Token, CloseCode};
+use tokio;
+use tokio::timer::{Delay, Interval};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use url::Url;
+
+use fetch_lib::backoff::Backoff;
+use fetch_lib::deadletter::DeadLetters;
+use common::{asset, trade, candle};
+
+use subscription::StreamType;
+use control::{Subscriptions, Reply};
+use liveness::{Liveness, PING_INTERVAL_SECS};
+use payload::{self, DepthUpdate};
+
+const CHECK_INTERVAL_MILLIS: u64 = 2000; // Two second checks.
+
+const MIN_RECONNECT_DELAY_SECS: u64 = 1;
+const MAX_RECONNECT_DELAY_SECS: u64 = 120;
+
+/// Binance closes connections after 24 hours. Rotate an hour before.
+pub const DEFAULT_ROTATE_AFTER_SECS: u64 = 23 * 60 * 60;
+
+/// Last trade ID forwarded for each asset pair. Shared by all connections so that trades
+/// received twice whilst two connections overlap are only forwarded once.
+#[derive(Debug, Clone, Default)]
+struct TradeIdDedupe {
+    last: Arc<Mutex<HashMap<asset::Pair, u64>>>,
+}
+
+impl TradeIdDedupe {
+    /// Whether the trade hasn't been seen before. Records it if so.
+    fn is_new(&self, pair: asset::Pair, trade_id: u64) -> bool {
+        let mut last = self.last.lock().expect("Trade ID dedupe lock poisoned.");
+        match last.get(&pair) {
+            Some(last_id) if trade_id <= *last_id => false,
+            _ => {
+                last.insert(pair, trade_id);
+                true
+            },
+        }
+    }
+}
+
+/// Channels the client forwards received payloads on. One for each kind of payload. Those
+/// that can't be used go to the dead letters.
+#[derive(Clone)]
+pub struct Forward {
+    trades: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
+    depth: UnboundedSender<(asset::Pair, DepthUpdate)>,
+    candles: UnboundedSender<(asset::Pair, candle::Candle)>,
+    aggregate_trades: UnboundedSender<(asset::Pair, trade::AggregateTrade)>,
+    dead_letters: DeadLetters,
+}
+
+impl Forward {
+    pub fn new(
+        trades: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
+        depth: UnboundedSender<(asset::Pair, DepthUpdate)>,
+        candles: UnboundedSender<(asset::Pair, candle::Candle)>,
+        aggregate_trades: UnboundedSender<(asset::Pair, trade::AggregateTrade)>,
+        dead_letters: DeadLetters,
+    ) -> Self {
+        Forward {
+            trades, depth, candles, aggregate_trades, dead_letters,
+        }
+    }
+}
+
+/// Why a connection ended.
+#[derive(Debug)]
+enum End {
+    /// Replaced or shut down by the supervisor.
+    Retired,
+
+    /// Binance closed it.
+    Closed,
+
+    /// Nothing arrived for too long.
+    Stale(Duration),
+
+    /// Nothing is receiving forwarded items anymore. Time to stop altogether.
+    Halted,
+
+    Failed(String),
+}
 
-use common::{asset, trade};
+impl fmt::Display for End {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            End::Retired => write!(f, "Retired"),
+            End::Closed => write!(f, "Closed by binance"),
+            End::Stale(silent) => write!(f, "Silent for {:?}", silent),
+            End::Halted => write!(f, "Forward channels closed"),
+            End::Failed(err) => write!(f, "{}", err),
+        }
+    }
+}
 
-use super::StreamRequest;
-use payload;
+/// What connections report back to the supervisor.
+enum Event {
+    Opened(usize),
+    RotationDue(usize),
+    Closed(usize, Result<(), End>),
+}
 
-const CHECK_FOR_STOP: Token = Token(1);
-const TIMEOUT_MILLIS: u64 = 2000; // Two second timeout.
+/// What a connection reacts to.
+enum Input {
+    Message(Message),
 
+    /// The websocket stream ended.
+    Ended,
+    Check,
+    Ping,
+    Rotate,
+    Retire,
+}
+
+/// State of a single open connection.
 struct Client {
-    /// Websocket sender. Link to `mio` event loop backend. Also used for initiating close.
-    ws: Sender,
+    /// Identifies the connection to the supervisor.
+    id: usize,
+
+    /// Frames to write to binance.
+    outgoing: UnboundedSender<Message>,
+
+    /// Report lifecycle events to the supervisor.
+    events: UnboundedSender<Event>,
 
-    /// To permit graceful shutdown (and trapping ctlr-c plus other signals).
-    stop: Arc<AtomicBool>,
+    dedupe: TradeIdDedupe,
 
     /// Forward received items on.
-    th_sender: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
+    forward: Forward,
+
+    /// Streams can be changed on the open connection through here.
+    subscriptions: Subscriptions,
+
+    /// Connection is dropped if nothing arrives for this long.
+    stale_after: Duration,
+
+    liveness: Liveness,
 }
 
 impl Client {
-    fn new(
-        ws: Sender,
-        stop: Arc<AtomicBool>,
-        th_sender: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
-    ) -> Self {
-        Client {
-            ws, stop, th_sender,
+    fn handle(mut self, input: Input) -> Result<Self, End> {
+        match input {
+            Input::Message(msg) => self.on_message(msg)?,
+            Input::Ended => return Err(End::Closed),
+            Input::Check => self.check_stale()?,
+            Input::Ping => {
+                self.log_symbol_ages();
+                let payload = self.liveness.ping(Instant::now());
+                self.outgoing
+                    .unbounded_send(Message::Ping(payload))
+                    .map_err(|e| End::Failed(e.to_string()))?;
+            },
+            Input::Rotate => {
+                self.events.unbounded_send(Event::RotationDue(self.id)).ok();
+            },
+            Input::Retire => {
+                // Best effort. The connection is dropped either way.
+                self.outgoing.unbounded_send(Message::Close(None)).ok();
+                return Err(End::Retired);
+            },
         }
+
+        Ok(self)
     }
-}
 
-impl Handler for Client {
-    fn on_open(&mut self, _: Handshake) -> Result<(), ws::Error> {
-        //println!("WS TOKEN: {:?}", self.ws.token());
+    /// End the connection if it has gone quiet. It may be dead without us being told.
+    fn check_stale(&self) -> Result<(), End> {
+        let now = Instant::now();
+        if !self.liveness.is_stale(self.stale_after, now) {
+            return Ok(());
+        }
 
-        // Setup stop check timeout every second
-        self.ws.timeout(TIMEOUT_MILLIS, CHECK_FOR_STOP)
+        warn!(
+            "Connection {} silent. Last ping round trip {:?}. Forcing a reconnect.",
+            self.id,
+            self.liveness.round_trip(),
+        );
+        Err(End::Stale(self.liveness.silent_for(now)))
     }
 
-    fn on_timeout(&mut self, event: ws::util::Token) -> Result<(), ws::Error> {
-        if event == CHECK_FOR_STOP {
-            // We check if stop is true
-            if self.stop.load(Ordering::Relaxed) {
-                self.ws.close(CloseCode::Normal)?;
-            }
-            
-            // Reschedule the timeout
-            self.ws.timeout(TIMEOUT_MILLIS, CHECK_FOR_STOP)
-        } else {
-            Err(ws::Error::new(
-                ws::ErrorKind::Internal, "Invalid timeout token encountered!"
-            ))
+    /// Log how long since each subscribed symbol last had a message.
+    fn log_symbol_ages(&self) {
+        let pairs: Vec<asset::Pair> = self.subscriptions
+            .request()
+            .streams()
+            .iter()
+            .map(|stream| stream.asset_pair())
+            .collect();
+
+        self.liveness
+            .symbol_ages(&pairs, Instant::now())
+            .into_iter()
+            .for_each(|(pair, age)| {
+                if age > self.stale_after {
+                    info!("Connection {}: nothing for {} in {:?}.", self.id, &pair, &age);
+                } else {
+                    trace!("Connection {}: {} last message {:?} ago.", self.id, &pair, &age);
+                }
+            });
+    }
+
+    fn on_message(&mut self, msg: Message) -> Result<(), End> {
+        let now = Instant::now();
+        self.liveness.received(now);
+
+        match msg {
+            Message::Text(json) => self.on_text(&json),
+            Message::Pong(payload) => {
+                if let Some(round_trip) = self.liveness.pong(&payload, now) {
+                    debug!("Connection {} ping round trip {:?}.", self.id, &round_trip);
+                }
+                Ok(())
+            },
+            // Answered by tungstenite itself.
+            Message::Ping(_) => Ok(()),
+            Message::Close(_) => Err(End::Closed),
+            Message::Binary(_) => {
+                warn!("Connection {} got an unexpected binary message.", self.id);
+                Ok(())
+            },
         }
     }
 
-    /// Inspects each message and depending on the type, will forward it on through the
-    /// appropriate `UnboundedSender` channel.
-    fn on_message(&mut self, msg: Message) -> Result<(), ws::Error> {
-        let json = msg.as_text()?;
-        match serde_json::from_str::<payload::StreamItem>(json) {
-            Ok(stream_item) => {
-                trace!("Received stream item: {:?}", &stream_item);
-                match stream_item.data().asset_pair() {
-                    Ok(ap) => {
-                        // Check if the payload is a trade history item.
-                        if let Some(thi) = stream_item.data().as_trade_history_item() {
-                            self.th_sender
-                                .unbounded_send((ap, thi))
-                                .unwrap_or_else(|_| {
-                                    self.stop.store(true, Ordering::Relaxed)
-                                });
-                        }
+    /// Inspects each payload and depending on the type, will forward it on through the
+    /// appropriate `UnboundedSender` channel. Payloads that can't be used are quarantined
+    /// in the dead letters.
+    fn on_text(&mut self, json: &str) -> Result<(), End> {
+        let stream_item = match serde_json::from_str::<payload::StreamItem>(json) {
+            Ok(stream_item) => stream_item,
+            Err(e) => {
+                // Could be a reply to a subscription change instead.
+                if let Ok(reply) = serde_json::from_str::<Reply>(json) {
+                    self.subscriptions.acknowledge(self.id, reply);
+                    return Ok(());
+                }
+
+                error!("Payload deserialization failed: {}", &e);
+                self.forward.dead_letters.record(json, &e.to_string());
+                return Ok(());
+            },
+        };
+
+        trace!("Received stream item: {:?}", &stream_item);
+        let ap = match stream_item.data().asset_pair() {
+            Ok(ap) => {
+                self.liveness.received_for(ap, Instant::now());
+                ap
+            },
+            Err(e) => {
+                error!("Invalid asset pair symbol: {}", &e);
+                self.forward.dead_letters.record(json, &e.to_string());
+                return Ok(());
+            },
+        };
+
+        match stream_item.data() {
+            payload::Payload::Trade { .. } => {
+                let thi = match stream_item.data().as_trade_history_item() {
+                    Some(thi) => thi,
+                    None => {
+                        error!("Trade with a zero timestamp.");
+                        self.forward.dead_letters.record(json, "Zero timestamp.");
+                        return Ok(());
                     },
+                };
+
+                let fresh = stream_item.data()
+                    .trade_id()
+                    .map(|trade_id| self.dedupe.is_new(ap, trade_id))
+                    .unwrap_or(true);
+
+                if fresh {
+                    self.forward.trades
+                        .unbounded_send((ap, thi))
+                        .map_err(|_| End::Halted)?;
+                } else {
+                    trace!("Connection {} dropped a duplicate trade.", self.id);
+                }
+            },
+            payload::Payload::DepthUpdate(update) => {
+                // Left out, the book sees a gap and resyncs from a fresh snapshot.
+                if update.timestamp().is_none() {
+                    error!("Depth update with a zero timestamp.");
+                    self.forward.dead_letters.record(json, "Zero timestamp.");
+                    return Ok(());
+                }
+
+                self.forward.depth
+                    .unbounded_send((ap, update.clone()))
+                    .map_err(|_| End::Halted)?;
+            },
+            payload::Payload::Kline { .. } => {
+                // Open candles are skipped. Only the closing one is kept.
+                match stream_item.data().as_candle() {
+                    Ok(Some(candle)) => {
+                        self.forward.candles
+                            .unbounded_send((ap, candle))
+                            .map_err(|_| End::Halted)?;
+                    },
+                    Ok(None) => (),
                     Err(e) => {
-                        error!("Invalid asset pair symbol: {}", &e);
-                        // TODO: Do something with this error. Currently will ignore and
-                        //       keep going with the next item. Maybie should keep an error
-                        //       count in here and notify an external system?
+                        error!("Kline not converted: {}", &e);
+                        self.forward.dead_letters.record(json, &e.to_string());
+                        return Ok(());
                     },
                 }
             },
-            Err(e) => {
-                error!("Payload deserialization failed: {}", &e);
-                // TODO: Do Something with the error. If this starts happening consecutively
-                //       for example it could indicate an API change. Thus some form of
-                //       notification is essential for these kinds of errors.
+            payload::Payload::AggregateTrade { .. } => {
+                match stream_item.data().as_aggregate_trade() {
+                    Ok(Some(aggregate)) => {
+                        self.forward.aggregate_trades
+                            .unbounded_send((ap, aggregate))
+                            .map_err(|_| End::Halted)?;
+                    },
+                    Ok(None) => (),
+                    Err(e) => {
+                        error!("Aggregate trade not converted: {}", &e);
+                        self.forward.dead_letters.record(json, &e.to_string());
+                        return Ok(());
+                    },
+                }
             },
         }
+
+        self.forward.dead_letters.success();
         Ok(())
     }
 }
 
+/// Everything a connection needs. Cloned for each new connection.
+#[derive(Clone)]
+struct Connector {
+    subscriptions: Subscriptions,
+    rotate_after: Duration,
+    stale_after: Duration,
+    events: UnboundedSender<Event>,
+    dedupe: TradeIdDedupe,
+    forward: Forward,
+}
 
+impl Connector {
+    /// Open a connection as its own task. Returns the means to retire it.
+    fn connect(&self, id: usize) -> oneshot::Sender<()> {
+        let (retire_tx, retire_rx) = oneshot::channel();
+        let connector = self.clone();
+        let events = self.events.clone();
+        let request = self.subscriptions.request();
+        let connected_with = request.streams();
 
-/// Starts websocket connection within reconnect loop. Blocks calling thread.
-pub fn stream(
-    subscription: StreamRequest,
-    stop: Arc<AtomicBool>,
-    th_sender: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
-) {
-    while !stop.load(Ordering::Relaxed) {
-        let url = subscription.url();
-        match ws::connect(
-            url, |sender| Client::new(sender, stop.clone(), th_sender.clone())
-        ) {
-            Ok(()) => (), // Stopped normally.
-            Err(e) => {
-                error!("Encountered error: {}", &e);
-                // TODO: Do something with the error.
-                // TODO: Back off on reconnecting. Add a delay.
+        let connection = future::result(Url::parse(&request.url()))
+            .map_err(|e| End::Failed(e.to_string()))
+            .and_then(|url| connect_async(url).map_err(|e| End::Failed(e.to_string())))
+            .and_then(move |(ws, _)| connector.run(id, ws, connected_with, retire_rx))
+            .then(move |result| {
+                events.unbounded_send(Event::Closed(id, result)).ok();
+                Ok(())
+            });
+
+        tokio::spawn(connection);
+        retire_tx
+    }
+
+    /// Drive an open connection until it ends.
+    fn run<S>(
+        self,
+        id: usize,
+        ws: S,
+        connected_with: Vec<StreamType>,
+        retire: oneshot::Receiver<()>,
+    ) -> impl Future<Item = (), Error = End>
+    where S: Stream<Item = Message> + Sink<SinkItem = Message> + Send + 'static,
+          S::Error: fmt::Display,
+          S::SinkError: fmt::Display,
+    {
+        debug!("Connection {} open.", id);
+        let (sink, incoming) = ws.split();
+        let (outgoing, outgoing_rx) = mpsc::unbounded();
+
+        // Writes whatever the client and subscriptions queue. Ends once both let go.
+        let writer = sink
+            .sink_map_err(|e| e.to_string())
+            .send_all(outgoing_rx.map_err(|()| "Outgoing channel failed.".to_owned()))
+            .map(|_| ())
+            .map_err(move |e| warn!("Connection {} write failed: {}", id, &e));
+        tokio::spawn(writer);
+
+        let subscriptions = self.subscriptions.clone();
+        subscriptions.register(id, outgoing.clone(), &connected_with);
+        self.events.unbounded_send(Event::Opened(id)).ok();
+
+        let now = Instant::now();
+        let check = Duration::from_millis(CHECK_INTERVAL_MILLIS);
+        let ping = Duration::from_secs(PING_INTERVAL_SECS);
+        let timer_failed = |e: tokio::timer::Error| End::Failed(e.to_string());
+
+        let inputs = incoming
+            .map(Input::Message)
+            .map_err(|e| End::Failed(e.to_string()))
+            .chain(stream::once(Ok(Input::Ended)))
+            .select(
+                Interval::new(now + check, check)
+                    .map(|_| Input::Check)
+                    .map_err(timer_failed)
+            )
+            .select(
+                Interval::new(now + ping, ping)
+                    .map(|_| Input::Ping)
+                    .map_err(timer_failed)
+            )
+            .select(
+                Delay::new(now + self.rotate_after)
+                    .into_stream()
+                    .map(|()| Input::Rotate)
+                    .map_err(timer_failed)
+            )
+            // Dropping the sender retires the connection too.
+            .select(retire.then(|_| Ok(Input::Retire)).into_stream());
+
+        let client = Client {
+            id,
+            outgoing,
+            events: self.events,
+            dedupe: self.dedupe,
+            forward: self.forward,
+            subscriptions: self.subscriptions,
+            stale_after: self.stale_after,
+            liveness: Liveness::new(now),
+        };
+
+        inputs
+            .fold(client, |client, input| client.handle(input))
+            .then(move |result| {
+                subscriptions.unregister(id);
+                match result {
+                    Ok(_) | Err(End::Retired) => Ok(()),
+                    Err(end) => Err(end),
+                }
+            })
+    }
+}
+
+/// Keeps a connection open. Replaces it when it is due for rotation and reconnects with a
+/// backoff when it is lost.
+struct Supervisor {
+    connector: Connector,
+    events: mpsc::UnboundedReceiver<Event>,
+
+    /// Connections not yet closed. Those already retired no longer hold a sender.
+    live: HashMap<usize, Option<oneshot::Sender<()>>>,
+
+    /// Connections that have asked to be replaced.
+    due: HashSet<usize>,
+    next_id: usize,
+    backoff: Backoff,
+    reconnect: Option<Delay>,
+
+    /// Taken once it resolves.
+    shutdown: Option<Box<Future<Item = (), Error = ()> + Send>>,
+    stopping: bool,
+}
+
+impl Supervisor {
+    fn connect(&mut self) {
+        let retire = self.connector.connect(self.next_id);
+        self.live.insert(self.next_id, Some(retire));
+        self.next_id += 1;
+    }
+
+    fn retire(&mut self, id: usize) {
+        if let Some(retire) = self.live.get_mut(&id).and_then(|retire| retire.take()) {
+            retire.send(()).ok();
+        }
+    }
+
+    /// Retire every connection and don't open any more.
+    fn stop(&mut self) {
+        self.stopping = true;
+        self.reconnect = None;
+        let ids: Vec<usize> = self.live.keys().cloned().collect();
+        ids.into_iter().for_each(|id| self.retire(id));
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Opened(id) => {
+                self.backoff.reset();
+
+                // Make before break. The newest connection is up so retire the others.
+                let others: Vec<usize> = self.live
+                    .iter()
+                    .filter(|(other, retire)| **other != id && retire.is_some())
+                    .map(|(other, _)| *other)
+                    .collect();
+                others.into_iter().for_each(|other| {
+                    info!("Connection {} replaced by {}.", other, id);
+                    self.retire(other);
+                });
+            },
+            Event::RotationDue(id) => {
+                self.due.insert(id);
+                let unretired = self.live.values().filter(|retire| retire.is_some()).count();
+                if !self.stopping && unretired == 1 {
+                    info!("Connection {} due for rotation. Opening replacement.", id);
+                    self.connect();
+                }
+            },
+            Event::Closed(id, result) => {
+                self.live.remove(&id);
+                self.due.remove(&id);
+                match result {
+                    Ok(()) => debug!("Connection {} closed.", id), // Retired normally.
+                    Err(End::Halted) => {
+                        error!("Connection {}: {}. Stopping.", id, End::Halted);
+                        self.stop();
+                    },
+                    Err(e) => error!("Connection {} ended: {}", id, &e),
+                }
+
+                if self.stopping {
+                    return;
+                }
+
+                if self.live.is_empty() {
+                    let delay = self.backoff.next_delay();
+                    warn!("No live connection. Reconnecting in {:?}.", &delay);
+                    self.reconnect = Some(Delay::new(Instant::now() + delay));
+                    return;
+                }
+
+                // The replacement failed while those it was to replace are still open. Try
+                // again before binance drops them.
+                let replaced = self.live.keys().all(|live| self.due.contains(live));
+                if replaced && self.reconnect.is_none() {
+                    let delay = self.backoff.next_delay();
+                    warn!("Replacement connection failed. Trying again in {:?}.", &delay);
+                    self.reconnect = Some(Delay::new(Instant::now() + delay));
+                }
             },
         }
     }
 }
+
+impl Future for Supervisor {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let shutdown = match self.shutdown.as_mut().map(|shutdown| shutdown.poll()) {
+            Some(Ok(Async::NotReady)) | None => false,
+            Some(_) => true,
+        };
+        if shutdown {
+            info!("Shutting down binance connections.");
+            self.shutdown = None;
+            self.stop();
+        }
+
+        // The connector holds a sender so this never ends by itself.
+        while let Async::Ready(Some(event)) = self.events.poll()? {
+            self.handle(event);
+        }
+
+        let reconnect = match self.reconnect.as_mut().map(|delay| delay.poll()) {
+            Some(Ok(Async::NotReady)) | None => false,
+            Some(Ok(Async::Ready(()))) => true,
+            Some(Err(e)) => {
+                error!("Reconnect timer failed: {}. Reconnecting now.", &e);
+                true
+            },
+        };
+        if reconnect {
+            self.reconnect = None;
+            self.connect();
+        }
+
+        if self.stopping && self.live.is_empty() {
+            // Dropping the supervisor lets go of the forward channels.
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// Returns the future that keeps the websocket connection up. Must be run on the tokio
+/// runtime.
+///
+/// Failed connections are retried with an exponential backoff. Each connection asks to be
+/// replaced after `rotate_after` and is only closed once its replacement is open. Streams
+/// can be changed at any time through the `subscriptions`. Connections silent for longer
+/// than `stale_after` are dropped and replaced.
+///
+/// Resolves once `shutdown` does and every connection has closed. The `forward` channels
+/// are dropped with it.
+pub fn stream<F>(
+    subscriptions: Subscriptions,
+    shutdown: F,
+    rotate_after: Duration,
+    stale_after: Duration,
+    forward: Forward,
+) -> impl Future<Item = (), Error = ()>
+where F: Future<Item = (), Error = ()> + Send + 'static
+{
+    let (events_tx, events_rx) = mpsc::unbounded();
+    let connector = Connector {
+        subscriptions: subscriptions,
+        rotate_after: rotate_after,
+        stale_after: stale_after,
+        events: events_tx,
+        dedupe: TradeIdDedupe::default(),
+        forward: forward,
+    };
+
+    let mut supervisor = Supervisor {
+        connector: connector,
+        events: events_rx,
+        live: HashMap::new(),
+        due: HashSet::new(),
+        next_id: 0,
+        backoff: Backoff::new(
+            Duration::from_secs(MIN_RECONNECT_DELAY_SECS),
+            Duration::from_secs(MAX_RECONNECT_DELAY_SECS),
+        ),
+        reconnect: None,
+        shutdown: Some(Box::new(shutdown)),
+        stopping: false,
+    };
+
+    future::lazy(move || {
+        supervisor.connect();
+        supervisor
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedupe_drops_repeated_and_older_trades() {
+        let dedupe = TradeIdDedupe::default();
+
+        assert!(dedupe.is_new(asset::BTC_USD, 10));
+        assert!(dedupe.is_new(asset::BTC_USD, 11));
+        assert!(!dedupe.is_new(asset::BTC_USD, 11));
+        assert!(!dedupe.is_new(asset::BTC_USD, 9));
+        assert!(dedupe.is_new(asset::ETH_USD, 9));
+    }
+}
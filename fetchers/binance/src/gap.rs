@@ -0,0 +1,472 @@
+//! Fill in the trades missed whilst the websocket was down.
+//!
+//! Binance trade IDs are sequential for each symbol. The last ID placed for each asset pair
+//! is remembered. When a live trade skips ahead, which is what the first trade after a
+//! reconnect will do, the missing range is fetched over REST and placed ahead of it.
+//!
+//! The `historicalTrades` endpoint gives back the exact trades but needs an API key. Without
+//! a key the `aggTrades` endpoint is used instead. An aggregate of a single trade is that
+//! trade and is placed with the rest. One of several trades can't be split back up and may
+//! hold trades placed before the gap, so it's placed with the aggregate trades instead.
+//! Those are stored once for each aggregate ID.
+//!
+//! Either way the requests are spaced apart and only the latest part of a long gap is
+//! fetched. Live trades wait on the fill, so it mustn't run on.
+use std::cmp;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use futures::{future, Future, Stream};
+use futures::future::Loop;
+use futures::sync::mpsc::UnboundedSender;
+use hyper::{Body, Request, Uri};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde_json;
+use tokio::timer::Delay;
+
+use fetch_lib::backoff::Backoff;
+use fetch_lib::https_client::{HttpsClient, FetchError};
+use common::{asset, trade};
+
+use payload::{HistoricalTrade, HistoricalAggregate};
+use subscription::symbol;
+
+/// Most binance will return in one request.
+const PAGE_LIMIT: usize = 1000;
+
+/// Most trades fetched for a single gap. When more are missing only the latest are fetched.
+const MAX_GAP: u64 = 100_000;
+
+/// Longest time window the `aggTrades` endpoint takes in one request.
+const MAX_WINDOW_MILLIS: u64 = 60 * 60 * 1000 - 1;
+
+/// Most windows of `aggTrades` fetched for a single gap. When the gap is longer only the
+/// latest windows are fetched, like `MAX_GAP` for trades.
+const MAX_WINDOWS: u64 = 24;
+
+/// Time between gap fill requests. Doubles up to the ceiling whilst binance throttles us.
+const REQUEST_DELAY_MILLIS: u64 = 250;
+const MAX_REQUEST_DELAY_SECS: u64 = 60;
+
+/// Times a throttled request is tried again before the fill gives up.
+const MAX_THROTTLED: usize = 5;
+
+/// Binance answers too many requests with 429 and bans the IP with 418 if we carry on.
+const TOO_MANY_REQUESTS: u16 = 429;
+const BANNED: u16 = 418;
+
+static API_KEY_HEADER: &str = "X-MBX-APIKEY";
+
+/// Missed trades along with the aggregates that couldn't be split up into trades.
+type Filled = (Vec<trade::TradeHistoryItem>, Vec<trade::AggregateTrade>);
+
+type Filling = Box<Future<Item = Filled, Error = FetchError> + Send>;
+
+/// Range of missing trades. IDs are the first missing and the first that isn't. Times are
+/// of the trades either side of the gap in milliseconds.
+#[derive(Debug, Copy, Clone)]
+struct Gap {
+    pair: asset::Pair,
+    from_id: u64,
+    to_id: u64,
+    from_time: u64,
+    to_time: u64,
+}
+
+fn millis(timestamp: DateTime<Utc>) -> u64 {
+    cmp::max(timestamp.timestamp_millis(), 0) as u64
+}
+
+fn get<T>(
+    client: &HttpsClient, uri: Uri, api_key: Option<&str>,
+) -> Box<Future<Item = Vec<T>, Error = FetchError> + Send>
+where T: DeserializeOwned + Send + 'static
+{
+    let mut req = Request::get(uri);
+    if let Some(key) = api_key {
+        req.header(API_KEY_HEADER, key);
+    }
+    let req = req.body(Body::empty()).expect("Invalid gap fill request.");
+
+    let fut = client
+        .request(req)
+        .from_err::<FetchError>()
+        .and_then(|res| {
+            let status = res.status().as_u16();
+            res.into_body()
+                .concat2()
+                .from_err::<FetchError>()
+                .and_then(move |body| {
+                    if status != 200 {
+                        return Err(FetchError::Status(status));
+                    }
+                    let items: Vec<T> = serde_json::from_slice(&body)?;
+                    Ok(items)
+                })
+        });
+
+    Box::new(fut)
+}
+
+fn pacing() -> Backoff {
+    Backoff::new(
+        Duration::from_millis(REQUEST_DELAY_MILLIS),
+        Duration::from_secs(MAX_REQUEST_DELAY_SECS),
+    )
+}
+
+/// `get` once the backoff delay is up. Throttled requests are tried again after a longer
+/// delay, up to `MAX_THROTTLED` times. A ban isn't, as carrying on only makes it longer.
+fn get_paced<T>(
+    client: HttpsClient, uri: Uri, api_key: Option<String>, backoff: Backoff,
+) -> Box<Future<Item = (Vec<T>, Backoff), Error = FetchError> + Send>
+where T: DeserializeOwned + Send + 'static
+{
+    let fut = future::loop_fn((backoff, 0), move |(mut backoff, throttled)| {
+        let client = client.clone();
+        let uri = uri.clone();
+        let api_key = api_key.clone();
+
+        Delay::new(Instant::now() + backoff.delay())
+            .from_err::<FetchError>()
+            .and_then(move |()| {
+                get::<T>(&client, uri, api_key.as_ref().map(|key| key.as_str()))
+            })
+            .then(move |result| match result {
+                Ok(page) => {
+                    backoff.reset();
+                    Ok(Loop::Break((page, backoff)))
+                },
+                Err(FetchError::Status(TOO_MANY_REQUESTS)) if throttled < MAX_THROTTLED => {
+                    backoff.increase();
+                    warn!("Gap fill throttled. Waiting {:?}.", backoff.delay());
+                    Ok(Loop::Continue((backoff, throttled + 1)))
+                },
+                Err(FetchError::Status(BANNED)) => {
+                    error!("Binance has banned us from the REST API. Gap fill stopped.");
+                    Err(FetchError::Status(BANNED))
+                },
+                Err(e) => Err(e),
+            })
+    });
+
+    Box::new(fut)
+}
+
+/// Page through `historicalTrades` from the first missing ID.
+fn historical_trades(
+    client: HttpsClient, rest: String, api_key: String, gap: Gap,
+) -> Box<Future<Item = Vec<trade::TradeHistoryItem>, Error = FetchError> + Send> {
+    let start = (gap.from_id, Vec::new(), pacing());
+    let fut = future::loop_fn(start, move |(next, mut items, backoff)| {
+        let uri: Uri = format!(
+            "{}/api/v3/historicalTrades?symbol={}&fromId={}&limit={}",
+            &rest, symbol(gap.pair), next, PAGE_LIMIT,
+        )
+            .parse()
+            .expect("Invalid REST URI constructed. Check the configured base.");
+
+        get_paced::<HistoricalTrade>(client.clone(), uri, Some(api_key.clone()), backoff)
+            .map(move |(page, backoff)| {
+                let last = page.last().map(|t| t.id());
+                for missed in page.iter().filter(|t| t.id() < gap.to_id) {
+                    match missed.as_trade_history_item() {
+                        Some(item) => items.push(item),
+                        None => {
+                            warn!("{} trade {} has no time. Dropped.", &gap.pair, missed.id());
+                        },
+                    }
+                }
+
+                match last {
+                    Some(id) if page.len() >= PAGE_LIMIT && id + 1 < gap.to_id => {
+                        Loop::Continue((id + 1, items, backoff))
+                    },
+                    _ => Loop::Break(items),
+                }
+            })
+    });
+
+    Box::new(fut)
+}
+
+/// Sort the aggregates of a page that overlap the gap into trades and aggregates.
+fn sort_aggregates(gap: &Gap, page: &[HistoricalAggregate], filled: &mut Filled) {
+    let overlapping = page
+        .iter()
+        .filter(|a| a.last_trade_id() >= gap.from_id)
+        .filter(|a| a.first_trade_id() < gap.to_id);
+
+    for aggregate in overlapping {
+        match (aggregate.as_trade_history_item(), aggregate.as_aggregate_trade()) {
+            (Some(item), _) => filled.0.push(item),
+            (None, Some(unsplit)) => filled.1.push(unsplit),
+            (None, None) => {
+                warn!("{} aggregate {} has no time. Dropped.", &gap.pair, aggregate.id());
+            },
+        }
+    }
+}
+
+/// Where to start paging `aggTrades` for the gap. No more than `MAX_WINDOWS` before the
+/// end of it.
+fn aggregate_start(gap: &Gap) -> u64 {
+    let latest = gap.to_time.saturating_sub(MAX_WINDOWS * (MAX_WINDOW_MILLIS + 1));
+    if latest > gap.from_time {
+        warn!(
+            "{} gap is over {} hour(s) long. Only fetching the last of it.",
+            &gap.pair, MAX_WINDOWS,
+        );
+    }
+    cmp::max(gap.from_time, latest)
+}
+
+/// Page through `aggTrades` over the time between the trades either side of the gap.
+fn aggregate_trades(
+    client: HttpsClient, rest: String, gap: Gap,
+) -> Filling {
+    let start = (aggregate_start(&gap), (Vec::new(), Vec::new()), pacing());
+    let fut = future::loop_fn(start, move |(start, mut filled, backoff)| {
+        let end = cmp::min(start + MAX_WINDOW_MILLIS, gap.to_time);
+        let uri: Uri = format!(
+            "{}/api/v3/aggTrades?symbol={}&startTime={}&endTime={}&limit={}",
+            &rest, symbol(gap.pair), start, end, PAGE_LIMIT,
+        )
+            .parse()
+            .expect("Invalid REST URI constructed. Check the configured base.");
+
+        get_paced::<HistoricalAggregate>(client.clone(), uri, None, backoff)
+            .map(move |(page, backoff)| {
+                // A full page may have left some out. Carry on from the last one returned.
+                let next = match page.last() {
+                    Some(last) if page.len() >= PAGE_LIMIT => last.trade_time() + 1,
+                    _ => end + 1,
+                };
+
+                sort_aggregates(&gap, &page, &mut filled);
+
+                if next > gap.to_time {
+                    Loop::Break(filled)
+                } else {
+                    Loop::Continue((next, filled, backoff))
+                }
+            })
+    });
+
+    Box::new(fut)
+}
+
+/// Remembers the last trade of each asset pair and fetches what was skipped.
+pub struct GapFill {
+    client: HttpsClient,
+    rest: String,
+    api_key: Option<String>,
+    aggregates: UnboundedSender<(asset::Pair, trade::AggregateTrade)>,
+    last: HashMap<asset::Pair, (u64, DateTime<Utc>)>,
+}
+
+impl GapFill {
+    /// Aggregates fetched that can't be placed as trades are sent to `aggregates`.
+    pub fn new(
+        client: HttpsClient,
+        rest: &str,
+        api_key: Option<String>,
+        aggregates: UnboundedSender<(asset::Pair, trade::AggregateTrade)>,
+    ) -> Self {
+        GapFill {
+            client: client,
+            rest: rest.to_owned(),
+            api_key: api_key,
+            aggregates: aggregates,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Trades to place for the live trade. Any missed trades come first. If fetching them
+    /// fails only the live trade is returned.
+    pub fn fill(
+        &mut self, pair: asset::Pair, item: trade::TradeHistoryItem,
+    ) -> Box<Future<Item = (asset::Pair, Vec<trade::TradeHistoryItem>), Error = ()> + Send> {
+        let id = match item.match_id() {
+            Some(id) if id >= 0 => id as u64,
+            _ => return Box::new(future::ok((pair, vec![item]))),
+        };
+
+        let previous = self.last.get(&pair).cloned();
+        if let Some((last_id, _)) = previous {
+            if id <= last_id {
+                return Box::new(future::ok((pair, vec![item])));
+            }
+        }
+        self.last.insert(pair, (id, item.timestamp()));
+
+        let (last_id, last_time) = match previous {
+            Some((last_id, last_time)) if id > last_id + 1 => (last_id, last_time),
+            _ => return Box::new(future::ok((pair, vec![item]))),
+        };
+
+        let missing = id - last_id - 1;
+        let from_id = if missing > MAX_GAP {
+            warn!("{} missed {} trades. Only fetching the last {}.", &pair, missing, MAX_GAP);
+            id - MAX_GAP
+        } else {
+            info!("{} missed {} trade(s). Fetching them.", &pair, missing);
+            last_id + 1
+        };
+
+        let gap = Gap {
+            pair: pair,
+            from_id: from_id,
+            to_id: id,
+            from_time: millis(last_time),
+            to_time: millis(item.timestamp()),
+        };
+
+        let fetch: Filling = match self.api_key {
+            Some(ref key) => Box::new(
+                historical_trades(self.client.clone(), self.rest.clone(), key.clone(), gap)
+                    .map(|items| (items, Vec::new()))
+            ),
+            None => aggregate_trades(self.client.clone(), self.rest.clone(), gap),
+        };
+
+        let aggregates = self.aggregates.clone();
+        let fut = fetch.then(move |result| {
+            let (mut items, unsplit) = match result {
+                Ok((items, unsplit)) => {
+                    debug!(
+                        "{} gap filled with {} trade(s) and {} aggregate(s).",
+                        &pair,
+                        items.len(),
+                        unsplit.len(),
+                    );
+                    (items, unsplit)
+                },
+                Err(e) => {
+                    error!("{} gap fill failed: {}", &pair, &e);
+                    (Vec::new(), Vec::new())
+                },
+            };
+
+            for aggregate in unsplit {
+                if aggregates.unbounded_send((pair, aggregate)).is_err() {
+                    warn!("{} gap fill aggregates dropped. Nothing places them.", &pair);
+                    break;
+                }
+            }
+
+            items.push(item);
+            Ok((pair, items))
+        });
+
+        Box::new(fut)
+    }
+}
+
+/// Pass the live trades through a `GapFill`. Trades come out in batches with the missed
+/// trades ahead of the live one. The next live trade waits until a fill is done so that
+/// the order is kept.
+pub fn fill_gaps<S>(
+    mut gap_fill: GapFill, trades: S,
+) -> impl Stream<Item = (asset::Pair, Vec<trade::TradeHistoryItem>), Error = ()>
+where S: Stream<Item = (asset::Pair, trade::TradeHistoryItem), Error = ()>
+{
+    trades.and_then(move |(pair, item)| gap_fill.fill(pair, item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use futures::sync::mpsc;
+    use fetch_lib::https_client;
+
+    fn item(id: i64) -> trade::TradeHistoryItem {
+        trade::TradeHistoryItem::new(
+            Utc.timestamp(1539264159, 0),
+            1.into(),
+            10.into(),
+            trade::Market::Maker,
+            None,
+            Some(id),
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn aggregate(first: u64, last: u64) -> HistoricalAggregate {
+        let json = format!(
+            r#"{{"a":{},"p":"10","q":"1","f":{},"l":{},"T":1539264159000,"m":true}}"#,
+            first, first, last,
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn sequential_trades_pass_straight_through() {
+        let client = https_client::produce(1).unwrap();
+        let (aggregates, _) = mpsc::unbounded();
+        let mut gap_fill = GapFill::new(client, "http://localhost:1", None, aggregates);
+
+        let (_, first) = gap_fill.fill(asset::BNB_BTC, item(10)).wait().unwrap();
+        let (_, next) = gap_fill.fill(asset::BNB_BTC, item(11)).wait().unwrap();
+        let (_, repeat) = gap_fill.fill(asset::BNB_BTC, item(11)).wait().unwrap();
+        let (_, other) = gap_fill.fill(asset::BNB_ETH, item(500)).wait().unwrap();
+
+        assert_eq!(first, vec![item(10)]);
+        assert_eq!(next, vec![item(11)]);
+        assert_eq!(repeat, vec![item(11)]);
+        assert_eq!(other, vec![item(500)]);
+    }
+
+    #[test]
+    fn long_gaps_only_page_the_latest_windows() {
+        let hour = MAX_WINDOW_MILLIS + 1;
+        let mut gap = Gap {
+            pair: asset::BNB_BTC,
+            from_id: 11,
+            to_id: 15,
+            from_time: 1000,
+            to_time: 1000 + 2 * hour,
+        };
+        assert_eq!(aggregate_start(&gap), 1000);
+
+        gap.to_time = 1000 + (MAX_WINDOWS + 5) * hour;
+        assert_eq!(aggregate_start(&gap), 1000 + 5 * hour);
+    }
+
+    #[test]
+    fn only_single_trade_aggregates_fill_in_trades() {
+        let gap = Gap {
+            pair: asset::BNB_BTC,
+            from_id: 11,
+            to_id: 15,
+            from_time: 0,
+            to_time: 0,
+        };
+        let page = vec![
+            aggregate(8, 10),
+            aggregate(10, 12),
+            aggregate(13, 13),
+            aggregate(14, 15),
+            aggregate(16, 16),
+        ];
+
+        let mut filled = (Vec::new(), Vec::new());
+        sort_aggregates(&gap, &page, &mut filled);
+
+        let (items, unsplit) = filled;
+        let ids: Vec<i64> = items.iter().filter_map(|item| item.match_id()).collect();
+        assert_eq!(ids, vec![13]);
+
+        // These hold trades placed either side of the gap as well as missed ones.
+        let aggregate_ids: Vec<i64> = unsplit
+            .iter()
+            .map(|aggregate| aggregate.aggregate_id())
+            .collect();
+        assert_eq!(aggregate_ids, vec![10, 14]);
+    }
+}
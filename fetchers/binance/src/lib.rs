@@ -2,17 +2,32 @@
 #[macro_use] extern crate serde_derive;
 #[macro_use] extern crate log;
 extern crate futures;
+extern crate hyper;
 extern crate serde;
 extern crate serde_json;
 extern crate rust_decimal;
 extern crate chrono;
-extern crate ws;
+extern crate tokio;
+extern crate tokio_tungstenite;
+extern crate url;
 
 extern crate common;
+extern crate fetch_lib;
 
 mod subscription;
 mod payload;
 mod fetch;
+mod book;
+mod gap;
+mod control;
+mod admin;
+mod liveness;
 
-pub use self::fetch::stream;
+pub use self::fetch::{stream, Forward, DEFAULT_ROTATE_AFTER_SECS};
+pub use self::liveness::DEFAULT_STALE_AFTER_SECS;
+pub use self::payload::DepthUpdate;
+pub use self::book::BookSync;
+pub use self::gap::{GapFill, fill_gaps};
 pub use self::subscription::StreamRequest;
+pub use self::control::Subscriptions;
+pub use self::admin::serve as serve_admin;
@@ -0,0 +1,137 @@
+//! Tell a live connection from a silently dead one.
+//!
+//! A connection can die without a close frame. Nothing arrives and nothing errors. So we
+//! track when anything last came in, when each symbol last had a message and how long our
+//! pings take to come back. A connection silent for too long is forced to reconnect.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use common::asset;
+
+/// Connections silent for this long are considered dead unless configured otherwise.
+pub const DEFAULT_STALE_AFTER_SECS: u64 = 60;
+
+/// How often a connection pings binance.
+pub const PING_INTERVAL_SECS: u64 = 15;
+
+/// Liveness of a single connection.
+#[derive(Debug)]
+pub struct Liveness {
+    opened: Instant,
+    last_received: Instant,
+    symbols: HashMap<asset::Pair, Instant>,
+    ping: Option<(u64, Instant)>,
+    next_ping: u64,
+    round_trip: Option<Duration>,
+}
+
+impl Liveness {
+    pub fn new(now: Instant) -> Self {
+        Liveness {
+            opened: now,
+            last_received: now,
+            symbols: HashMap::new(),
+            ping: None,
+            next_ping: 0,
+            round_trip: None,
+        }
+    }
+
+    /// Anything at all came in on the connection.
+    pub fn received(&mut self, now: Instant) {
+        self.last_received = now;
+    }
+
+    /// A message for the symbol came in.
+    pub fn received_for(&mut self, pair: asset::Pair, now: Instant) {
+        self.received(now);
+        self.symbols.insert(pair, now);
+    }
+
+    /// Start a ping. Returns the payload to send with it.
+    pub fn ping(&mut self, now: Instant) -> Vec<u8> {
+        let nonce = self.next_ping;
+        self.next_ping += 1;
+        self.ping = Some((nonce, now));
+        nonce.to_string().into_bytes()
+    }
+
+    /// A pong came back. Returns the round trip if it answers the outstanding ping.
+    pub fn pong(&mut self, payload: &[u8], now: Instant) -> Option<Duration> {
+        self.received(now);
+        let nonce: u64 = String::from_utf8_lossy(payload).parse().ok()?;
+        match self.ping {
+            Some((sent_nonce, sent)) if sent_nonce == nonce => {
+                let round_trip = now.duration_since(sent);
+                self.ping = None;
+                self.round_trip = Some(round_trip);
+                Some(round_trip)
+            },
+            _ => None,
+        }
+    }
+
+    /// Last measured ping round trip.
+    pub fn round_trip(&self) -> Option<Duration> {
+        self.round_trip
+    }
+
+    /// How long the whole connection has been silent.
+    pub fn silent_for(&self, now: Instant) -> Duration {
+        now.duration_since(self.last_received)
+    }
+
+    /// Whether the connection has been silent for longer than `stale_after`.
+    pub fn is_stale(&self, stale_after: Duration, now: Instant) -> bool {
+        self.silent_for(now) > stale_after
+    }
+
+    /// Time since the last message for each of the `pairs`. Symbols that have had nothing
+    /// yet are timed from when the connection opened.
+    pub fn symbol_ages(
+        &self, pairs: &[asset::Pair], now: Instant,
+    ) -> Vec<(asset::Pair, Duration)> {
+        pairs
+            .iter()
+            .map(|pair| {
+                let last = self.symbols.get(pair).unwrap_or(&self.opened);
+                (*pair, now.duration_since(*last))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staleness_and_round_trip() {
+        let start = Instant::now();
+        let mut liveness = Liveness::new(start);
+        let stale_after = Duration::from_secs(60);
+
+        let payload = liveness.ping(start + Duration::from_secs(1));
+        assert_eq!(liveness.pong(b"99", start + Duration::from_secs(2)), None);
+        assert_eq!(
+            liveness.pong(&payload, start + Duration::from_secs(3)),
+            Some(Duration::from_secs(2)),
+        );
+        assert_eq!(liveness.round_trip(), Some(Duration::from_secs(2)));
+
+        assert!(!liveness.is_stale(stale_after, start + Duration::from_secs(63)));
+        assert!(liveness.is_stale(stale_after, start + Duration::from_secs(64)));
+
+        liveness.received_for(asset::BTC_USD, start + Duration::from_secs(10));
+        let ages = liveness.symbol_ages(
+            &[asset::BTC_USD, asset::ETH_USD], start + Duration::from_secs(20),
+        );
+        assert_eq!(
+            ages,
+            vec![
+                (asset::BTC_USD, Duration::from_secs(10)),
+                (asset::ETH_USD, Duration::from_secs(20)),
+            ],
+        );
+    }
+}
@@ -4,20 +4,17 @@ extern crate futures;
 extern crate tokio;
 extern crate dotenv;
 extern crate env_logger;
-extern crate ws;
 
 extern crate common;
 extern crate fetch_lib;
 
 extern crate binance_lib as lib;
 
-use std::thread;
-use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
-
-use futures::Stream;
+use futures::{future, Future, Stream};
 use futures::sync::mpsc;
 
+use fetch_lib::deadletter;
+
 mod config;
 
 fn main() {
@@ -33,22 +30,84 @@ fn main() {
     );
 
     let (th_place_tx, th_place_rx) = mpsc::unbounded();
-    let stop = Arc::new(AtomicBool::new(false));
-
-    let _thread_handle = thread::spawn(move || {
-        lib::stream(configuration.subscribe(), stop.clone(), th_place_tx);
-    });
+    let (depth_tx, depth_rx) = mpsc::unbounded();
+    let (candle_tx, candle_rx) = mpsc::unbounded();
+    let (aggregate_tx, aggregate_rx) = mpsc::unbounded();
+    let dead_letters = deadletter::DeadLetters::from_environment("binance")
+        .expect("Can't open dead letters.");
+    let client = fetch_lib::https_client::produce(1).expect("Can't init TLS.");
+    let rest_uri = configuration.rest_uri().to_owned();
+    let gap_fill = lib::GapFill::new(
+        client.clone(), &rest_uri, configuration.api_key(), aggregate_tx.clone(),
+    );
+    let forward = lib::Forward::new(
+        th_place_tx, depth_tx, candle_tx, aggregate_tx, dead_letters,
+    );
+    let subscriptions = lib::Subscriptions::new(configuration.subscribe());
+    let admin_listen = configuration.admin_listen();
+    let rotate_after = configuration.rotate_after();
+    let stale_after = configuration.stale_after();
 
     let place_future = fetch_lib::place::put_trade_history(
-        fetch_lib::https_client::produce(1).expect("Can't init TLS."),
-        target,
-        th_place_rx
-            .map(|(ap, thi)| (ap, vec![thi]))
-            .map_err(|e| error!("Receive failure: {:?}", &e))
+        client.clone(),
+        target.clone(),
+        lib::fill_gaps(gap_fill, th_place_rx.map_err(|e| error!("Receive failure: {:?}", &e)))
             .inspect(|tuple| trace!("PLACING ITEMS: {:?}", &tuple)),
     );
 
-    tokio::run(place_future);
+    let candle_future = fetch_lib::place::put_candles(
+        client.clone(),
+        target.clone(),
+        candle_rx
+            .map(|(ap, candle)| (ap, vec![candle]))
+            .map_err(|e| error!("Candle receive failure: {:?}", &e)),
+    );
+
+    let aggregate_future = fetch_lib::place::put_aggregate_trades(
+        client.clone(),
+        target.clone(),
+        aggregate_rx
+            .map(|(ap, aggregate)| (ap, vec![aggregate]))
+            .map_err(|e| error!("Aggregate trade receive failure: {:?}", &e)),
+    );
+
+    let book_future = fetch_lib::place::put_book_events(
+        client.clone(),
+        target,
+        lib::BookSync::new(
+            client,
+            &rest_uri,
+            depth_rx.map_err(|e| error!("Depth receive failure: {:?}", &e)),
+        )
+            .map(|(pair, event)| (pair, vec![event])),
+    );
+
+    // Once the websocket stream stops, the forward channels close and everything after
+    // them finishes what it has in hand. The runtime exits when all of it is done.
+    tokio::run(future::lazy(move || {
+        let shutdown = fetch_lib::shutdown::signal().shared();
+
+        tokio::spawn(lib::stream(
+            subscriptions.clone(),
+            shutdown.clone().then(|_| Ok::<(), ()>(())),
+            rotate_after,
+            stale_after,
+            forward,
+        ));
+
+        if let Some(addr) = admin_listen {
+            tokio::spawn(
+                lib::serve_admin(addr, subscriptions)
+                    .select(shutdown.then(|_| Ok::<(), ()>(())))
+                    .then(|_| Ok(()))
+            );
+        }
+
+        tokio::spawn(book_future);
+        tokio::spawn(candle_future);
+        tokio::spawn(aggregate_future);
+        place_future
+    }));
 
-    //thread_handle.join().expect("Websocket thread termination error.");
+    info!("Stopped.");
 }
@@ -1,9 +1,11 @@
 //! Payloads from binance.
+use std::fmt;
 use std::num::NonZeroU64;
 
 use rust_decimal::Decimal;
+use chrono::{DateTime, Utc};
 
-use common::{asset, trade, time_util};
+use common::{asset, trade, candle, time_util};
 
 /// Similar to the `asset_amend` function in the subscription module. Will test the symbol
 /// string for particular matches where Binance has a different code from what exists in
@@ -20,6 +22,250 @@ fn asset_pair_parse(symbol: &str) -> Result<asset::Pair, asset::ParseAssetError>
     }
 }
 
+/// Millisecond timestamp from binance. Zero is a broken payload and gives `None`.
+fn timestamp(millis: u64) -> Option<DateTime<Utc>> {
+    NonZeroU64::new(millis).map(time_util::millisecond_timestamp_to_chrono)
+}
+
+/// Why a payload couldn't be converted into the common model.
+#[derive(Debug, Copy, Clone)]
+pub enum ConversionError {
+    ZeroTimestamp,
+    Interval(candle::ParseIntervalError),
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::ZeroTimestamp => write!(f, "Zero timestamp."),
+            ConversionError::Interval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// A price level as binance sends it. Price then quantity.
+pub type Level = (Decimal, Decimal);
+
+/// Diff of the order book. Levels with a quantity of zero are to be removed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthUpdate {
+    #[serde(rename = "E")]
+    event_time: u64,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "U")]
+    first_update_id: u64,
+    #[serde(rename = "u")]
+    final_update_id: u64,
+    #[serde(rename = "b")]
+    bids: Vec<Level>,
+    #[serde(rename = "a")]
+    asks: Vec<Level>,
+}
+
+impl DepthUpdate {
+    /// `None` if binance sent a zero event time.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        timestamp(self.event_time)
+    }
+
+    pub fn first_update_id(&self) -> u64 {
+        self.first_update_id
+    }
+
+    pub fn final_update_id(&self) -> u64 {
+        self.final_update_id
+    }
+
+    pub fn bids(&self) -> &[Level] {
+        self.bids.as_slice()
+    }
+
+    pub fn asks(&self) -> &[Level] {
+        self.asks.as_slice()
+    }
+}
+
+/// Order book snapshot from the REST API. Diffs are applied on top of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    #[serde(rename = "lastUpdateId")]
+    last_update_id: u64,
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+impl DepthSnapshot {
+    pub fn last_update_id(&self) -> u64 {
+        self.last_update_id
+    }
+
+    pub fn bids(&self) -> &[Level] {
+        self.bids.as_slice()
+    }
+
+    pub fn asks(&self) -> &[Level] {
+        self.asks.as_slice()
+    }
+}
+
+/// Candle within a kline payload. Binance sends one every couple of seconds while the
+/// candle is open and a last one with `x` set once it closes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Kline {
+    #[serde(rename = "t")]
+    open_time: u64,
+    #[serde(rename = "T")]
+    close_time: u64,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "i")]
+    interval: String,
+    #[serde(rename = "f")]
+    first_trade_id: i64,
+    #[serde(rename = "L")]
+    last_trade_id: i64,
+    #[serde(rename = "o")]
+    open: Decimal,
+    #[serde(rename = "c")]
+    close: Decimal,
+    #[serde(rename = "h")]
+    high: Decimal,
+    #[serde(rename = "l")]
+    low: Decimal,
+    #[serde(rename = "v")]
+    volume: Decimal,
+    #[serde(rename = "n")]
+    trades: u64,
+    #[serde(rename = "x")]
+    closed: bool,
+    #[serde(rename = "q")]
+    quote_volume: Decimal,
+    #[serde(rename = "V")]
+    taker_volume: Decimal,
+    #[serde(rename = "Q")]
+    taker_quote_volume: Decimal,
+    #[serde(rename = "B")]
+    ignore: String,
+}
+
+/// Trade from the REST `historicalTrades` endpoint. Needs an API key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoricalTrade {
+    id: u64,
+    price: Decimal,
+    qty: Decimal,
+    time: u64,
+    #[serde(rename = "isBuyerMaker")]
+    is_buyer_maker: bool,
+}
+
+impl HistoricalTrade {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// `None` if binance sent a zero time.
+    pub fn as_trade_history_item(&self) -> Option<trade::TradeHistoryItem> {
+        let time = timestamp(self.time)?;
+
+        Some(trade::TradeHistoryItem::new(
+            time,
+            self.qty,
+            self.price,
+            if self.is_buyer_maker {
+                trade::Market::Taker
+            } else {
+                trade::Market::Maker
+            },
+            None,
+            Some(self.id as i64),
+            None,
+            None,
+            Some(time),
+        ))
+    }
+}
+
+/// Aggregate trade from the REST `aggTrades` endpoint. Doesn't need an API key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoricalAggregate {
+    #[serde(rename = "a")]
+    aggregate_id: u64,
+    #[serde(rename = "p")]
+    price: Decimal,
+    #[serde(rename = "q")]
+    quantity: Decimal,
+    #[serde(rename = "f")]
+    first_trade_id: u64,
+    #[serde(rename = "l")]
+    last_trade_id: u64,
+    #[serde(rename = "T")]
+    trade_time: u64,
+    #[serde(rename = "m")]
+    market_buyer: bool,
+}
+
+impl HistoricalAggregate {
+    pub fn id(&self) -> u64 {
+        self.aggregate_id
+    }
+
+    pub fn trade_time(&self) -> u64 {
+        self.trade_time
+    }
+
+    pub fn first_trade_id(&self) -> u64 {
+        self.first_trade_id
+    }
+
+    pub fn last_trade_id(&self) -> u64 {
+        self.last_trade_id
+    }
+
+    /// The aggregate as the trade it is made of. `None` if it's of several trades, as
+    /// those can't be told apart, or if binance sent a zero time.
+    pub fn as_trade_history_item(&self) -> Option<trade::TradeHistoryItem> {
+        if self.first_trade_id != self.last_trade_id {
+            return None;
+        }
+        let time = timestamp(self.trade_time)?;
+
+        Some(trade::TradeHistoryItem::new(
+            time,
+            self.quantity,
+            self.price,
+            self.market(),
+            None,
+            Some(self.first_trade_id as i64),
+            None,
+            None,
+            Some(time),
+        ))
+    }
+
+    /// `None` if binance sent a zero time.
+    pub fn as_aggregate_trade(&self) -> Option<trade::AggregateTrade> {
+        Some(trade::AggregateTrade::new(
+            timestamp(self.trade_time)?,
+            self.quantity,
+            self.price,
+            self.market(),
+            self.aggregate_id as i64,
+            self.first_trade_id as i64,
+            self.last_trade_id as i64,
+        ))
+    }
+
+    fn market(&self) -> trade::Market {
+        if self.market_buyer {
+            trade::Market::Taker
+        } else {
+            trade::Market::Maker
+        }
+    }
+}
+
 /// Universal enum for all Binance websocket payloads. Payloads are internally tagged so
 /// representing with an enum is straightforward.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,18 +293,63 @@ pub enum Payload {
         market_buyer: bool,
         #[serde(rename = "M")]
         ignore: bool,
-    }
+    },
+    #[serde(rename = "depthUpdate")]
+    DepthUpdate(DepthUpdate),
+    #[serde(rename = "kline")]
+    Kline {
+        #[serde(rename = "E")]
+        event_time: u64,
+        #[serde(rename = "s")]
+        symbol: String,
+        #[serde(rename = "k")]
+        kline: Kline,
+    },
+    #[serde(rename = "aggTrade")]
+    AggregateTrade {
+        #[serde(rename = "E")]
+        event_time: u64,
+        #[serde(rename = "s")]
+        symbol: String,
+        #[serde(rename = "a")]
+        aggregate_id: u64,
+        #[serde(rename = "p")]
+        price: Decimal,
+        #[serde(rename = "q")]
+        quantity: Decimal,
+        #[serde(rename = "f")]
+        first_trade_id: u64,
+        #[serde(rename = "l")]
+        last_trade_id: u64,
+        #[serde(rename = "T")]
+        trade_time: u64,
+        #[serde(rename = "m")]
+        market_buyer: bool,
+        #[serde(rename = "M")]
+        ignore: bool,
+    },
 }
 
 impl Payload {
     pub fn asset_pair(&self) -> Result<asset::Pair, asset::ParseAssetError> {
         match self {
             Payload::Trade { symbol, .. } => asset_pair_parse(symbol),
+            Payload::DepthUpdate(update) => asset_pair_parse(&update.symbol),
+            Payload::Kline { symbol, .. } => asset_pair_parse(symbol),
+            Payload::AggregateTrade { symbol, .. } => asset_pair_parse(symbol),
+        }
+    }
+
+    /// Binance's sequential trade ID. `None` if self isn't a trade.
+    pub fn trade_id(&self) -> Option<u64> {
+        match self {
+            Payload::Trade { trade_id, .. } => Some(*trade_id),
+            _ => None,
         }
     }
 
     /// Creates a version of self that is a `TradeHistoryItem`. If self is of the wrong
-    /// enum type, returns `None`.
+    /// enum type or has a zero timestamp, returns `None`.
     pub fn as_trade_history_item(&self) -> Option<trade::TradeHistoryItem> {
         match self {
             Payload::Trade {
@@ -72,13 +363,83 @@ impl Payload {
                 trade_time,
                 market_buyer,
                 ignore: _,
-            } => Some(trade::TradeHistoryItem::new(
-                if let Some(et) = NonZeroU64::new(*event_time) {
-                    time_util::millisecond_timestamp_to_chrono(et)
-                } else {
-                    // Perhaps we can instead use a timestamp of 1 millisecond?
-                    panic!("Invalid timestamp")
-                },
+            } => {
+                // A zero timestamp is a broken payload. Leave it for the caller to quarantine.
+                let event_time = NonZeroU64::new(*event_time)?;
+                let trade_time = NonZeroU64::new(*trade_time)?;
+
+                Some(trade::TradeHistoryItem::new(
+                    time_util::millisecond_timestamp_to_chrono(event_time),
+                    *quantity,
+                    *price,
+                    if *market_buyer {
+                        trade::Market::Taker
+                    } else {
+                        trade::Market::Maker
+                    },
+                    None,
+                    Some(*trade_id as i64),
+                    Some(*buyer_order_id as i64),
+                    Some(*seller_order_id as i64),
+                    Some(time_util::millisecond_timestamp_to_chrono(trade_time)),
+                ))
+            },
+            _ => None,
+        }
+    }
+
+    /// Creates a `Candle` from a kline payload. Only closed candles are returned since the
+    /// open ones are still changing. If self is of the wrong enum type, returns `None`.
+    /// A closed kline with an interval that isn't known or a zero time is an error.
+    pub fn as_candle(&self) -> Result<Option<candle::Candle>, ConversionError> {
+        match self {
+            Payload::Kline { kline, .. } => {
+                if !kline.closed {
+                    return Ok(None);
+                }
+
+                let interval: candle::Interval = kline.interval
+                    .parse()
+                    .map_err(ConversionError::Interval)?;
+                let open_time = timestamp(kline.open_time)
+                    .ok_or(ConversionError::ZeroTimestamp)?;
+                let close_time = timestamp(kline.close_time)
+                    .ok_or(ConversionError::ZeroTimestamp)?;
+
+                Ok(Some(candle::Candle::new(
+                    interval,
+                    open_time,
+                    close_time,
+                    kline.open,
+                    kline.high,
+                    kline.low,
+                    kline.close,
+                    kline.volume,
+                    kline.quote_volume,
+                    kline.trades,
+                )))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Creates an `AggregateTrade`. If self is of the wrong enum type, returns `None`. A
+    /// zero trade time is an error.
+    pub fn as_aggregate_trade(
+        &self
+    ) -> Result<Option<trade::AggregateTrade>, ConversionError> {
+        match self {
+            Payload::AggregateTrade {
+                aggregate_id,
+                price,
+                quantity,
+                first_trade_id,
+                last_trade_id,
+                trade_time,
+                market_buyer,
+                ..
+            } => Ok(Some(trade::AggregateTrade::new(
+                timestamp(*trade_time).ok_or(ConversionError::ZeroTimestamp)?,
                 *quantity,
                 *price,
                 if *market_buyer {
@@ -86,20 +447,11 @@ impl Payload {
                 } else {
                     trade::Market::Maker
                 },
-                None,
-                Some(*trade_id as i64),
-                Some(*buyer_order_id as i64),
-                Some(*seller_order_id as i64),
-                Some(
-                    if let Some(tt) = NonZeroU64::new(*trade_time) {
-                        time_util::millisecond_timestamp_to_chrono(tt)
-                    } else {
-                        // Ditto.
-                        panic!("Invalid trade execution timestamp.")
-                    }
-                )),
-            ),
-            //_ => None,
+                *aggregate_id as i64,
+                *first_trade_id as i64,
+                *last_trade_id as i64,
+            ))),
+            _ => Ok(None),
         }
     }
 }
@@ -143,4 +495,81 @@ mod tests {
         let back = serde_json::to_string(&item).unwrap();
         assert_eq!(back.as_str(), json1);
     }
+
+    #[test]
+    fn depth_update_deserialize() {
+        let json1 = r##"{"stream":"bnbbtc@depth","data":{"e":"depthUpdate","E":1539264159120,"s":"BNBBTC","U":157,"u":160,"b":[["0.0024","10"]],"a":[["0.0026","100"],["0.0027","0"]]}}"##;
+
+        let item: StreamItem = serde_json::from_str(json1).unwrap();
+        match item.data() {
+            Payload::DepthUpdate(update) => {
+                assert_eq!(update.first_update_id(), 157);
+                assert_eq!(update.final_update_id(), 160);
+                assert_eq!(update.bids().len(), 1);
+                assert_eq!(update.asks().len(), 2);
+            },
+            _ => panic!("Expected a depth update."),
+        }
+        assert_eq!(item.data().asset_pair().unwrap(), asset::BNB_BTC);
+        assert!(item.data().as_trade_history_item().is_none());
+    }
+
+    #[test]
+    fn kline_payload_deserialize() {
+        let json1 = r##"{"e":"kline","E":1539264159120,"s":"BNBBTC","k":{"t":1539264120000,"T":1539264179999,"s":"BNBBTC","i":"1m","f":29661690,"L":29661698,"o":"0.00152000","c":"0.00152100","h":"0.00152300","l":"0.00151900","v":"120.00000000","n":9,"x":true,"q":"0.18250000","V":"60.00000000","Q":"0.09125000","B":"0"}}"##;
+
+        let payload: Payload = serde_json::from_str(json1).unwrap();
+        let back = serde_json::to_string(&payload).unwrap();
+        assert_eq!(back.as_str(), json1);
+
+        let candle = payload.as_candle().unwrap().unwrap();
+        assert_eq!(*candle.interval(), candle::Interval::Minute1);
+        assert_eq!(*candle.trades(), 9);
+        assert!(payload.as_trade_history_item().is_none());
+    }
+
+    #[test]
+    fn aggregate_trade_payload_deserialize() {
+        let json1 = r##"{"e":"aggTrade","E":1539264159120,"s":"BNBBTC","a":5933014,"p":"0.00152100","q":"3.00000000","f":29661696,"l":29661698,"T":1539264159104,"m":true,"M":true}"##;
+
+        let payload: Payload = serde_json::from_str(json1).unwrap();
+        let back = serde_json::to_string(&payload).unwrap();
+        assert_eq!(back.as_str(), json1);
+
+        let aggregate = payload.as_aggregate_trade().unwrap().unwrap();
+        assert_eq!(aggregate.aggregate_id(), 5933014);
+        assert_eq!(aggregate.last_trade_id(), 29661698);
+        assert_eq!(aggregate.market(), trade::Market::Taker);
+    }
+
+    #[test]
+    fn zero_timestamp_trade_is_not_converted() {
+        let json1 = r##"{"e":"trade","E":0,"s":"BNBBTC","t":29661698,"p":"0.00152100","q":"1.00000000","b":84391627,"a":84391631,"T":1539264159104,"m":true,"M":true}"##;
+
+        let payload: Payload = serde_json::from_str(json1).unwrap();
+        assert!(payload.as_trade_history_item().is_none());
+    }
+
+    #[test]
+    fn zero_timestamps_are_errors() {
+        let kline = r##"{"e":"kline","E":1539264159120,"s":"BNBBTC","k":{"t":0,"T":1539264179999,"s":"BNBBTC","i":"1m","f":29661690,"L":29661698,"o":"0.00152000","c":"0.00152100","h":"0.00152300","l":"0.00151900","v":"120.00000000","n":9,"x":true,"q":"0.18250000","V":"60.00000000","Q":"0.09125000","B":"0"}}"##;
+        let aggregate = r##"{"e":"aggTrade","E":1539264159120,"s":"BNBBTC","a":5933014,"p":"0.00152100","q":"3.00000000","f":29661696,"l":29661698,"T":0,"m":true,"M":true}"##;
+        let depth = r##"{"e":"depthUpdate","E":0,"s":"BNBBTC","U":157,"u":160,"b":[],"a":[]}"##;
+        let historical = r##"{"a":1,"p":"10","q":"1","f":2,"l":2,"T":0,"m":true}"##;
+
+        let kline: Payload = serde_json::from_str(kline).unwrap();
+        assert!(kline.as_candle().is_err());
+
+        let aggregate: Payload = serde_json::from_str(aggregate).unwrap();
+        assert!(aggregate.as_aggregate_trade().is_err());
+
+        match serde_json::from_str::<Payload>(depth).unwrap() {
+            Payload::DepthUpdate(update) => assert!(update.timestamp().is_none()),
+            _ => panic!("Expected a depth update."),
+        }
+
+        let historical: HistoricalAggregate = serde_json::from_str(historical).unwrap();
+        assert!(historical.as_trade_history_item().is_none());
+        assert!(historical.as_aggregate_trade().is_none());
+    }
 }
@@ -3,6 +3,7 @@ use std::iter::{Iterator, IntoIterator, Extend};
 use std::fmt;
 
 use common::asset;
+use common::candle::Interval;
 
 const DEFAULT_BINANCE_WEBSOCKET_BASE_URI: &str = "wss://stream.binance.com:9443";
 
@@ -15,20 +16,45 @@ fn asset_amend(aa: asset::Asset) -> String {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-enum StreamType {
+/// The symbol binance uses for the asset pair in its REST API. For example `BNBBTC`.
+pub fn symbol(pair: asset::Pair) -> String {
+    format!("{}{}", asset_amend(pair.left()), asset_amend(pair.right())).to_uppercase()
+}
+
+/// A single binance stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StreamType {
     TradeHistoryItems(asset::Pair),
+    OrderBookDiffs(asset::Pair),
+    Klines(asset::Pair, Interval),
+    AggregateTrades(asset::Pair),
 }
 
-/*
 impl StreamType {
+    /// Stream from the kind used in the binance stream name. For example `trade`, `depth`,
+    /// `aggTrade` or `kline_1m`.
+    pub fn from_kind(kind: &str, pair: asset::Pair) -> Option<Self> {
+        match kind {
+            "trade" => Some(StreamType::TradeHistoryItems(pair)),
+            "depth" => Some(StreamType::OrderBookDiffs(pair)),
+            "aggTrade" => Some(StreamType::AggregateTrades(pair)),
+            _ if kind.starts_with("kline_") => kind["kline_".len()..]
+                .parse()
+                .ok()
+                .map(|interval| StreamType::Klines(pair, interval)),
+            _ => None,
+        }
+    }
+
     pub fn asset_pair(&self) -> asset::Pair {
         match self {
             StreamType::TradeHistoryItems(ap) => *ap,
+            StreamType::OrderBookDiffs(ap) => *ap,
+            StreamType::Klines(ap, _) => *ap,
+            StreamType::AggregateTrades(ap) => *ap,
         }
     }
 }
-*/
 
 impl fmt::Display for StreamType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -38,6 +64,21 @@ impl fmt::Display for StreamType {
                 let r = asset_amend(pair.right());
                 write!(f, "{}{}@trade", &l, &r)
             },
+            StreamType::OrderBookDiffs(pair) => {
+                let l = asset_amend(pair.left());
+                let r = asset_amend(pair.right());
+                write!(f, "{}{}@depth", &l, &r)
+            },
+            StreamType::Klines(pair, interval) => {
+                let l = asset_amend(pair.left());
+                let r = asset_amend(pair.right());
+                write!(f, "{}{}@kline_{}", &l, &r, interval)
+            },
+            StreamType::AggregateTrades(pair) => {
+                let l = asset_amend(pair.left());
+                let r = asset_amend(pair.right());
+                write!(f, "{}{}@aggTrade", &l, &r)
+            },
         }
     }
 }
@@ -68,13 +109,51 @@ impl StreamRequest {
         self
     }
 
-    /// Generate URL that can be passed into websocket client.
-    pub fn url(&self) -> String {
+    /// Order book diffs. These need to be synchronised with a REST snapshot to be useful.
+    pub fn add_order_book_stream(mut self, pair: asset::Pair) -> Self {
+        self.streams.push(StreamType::OrderBookDiffs(pair));
+        self
+    }
+
+    /// Candles computed by binance. One stream per interval.
+    pub fn add_kline_stream(mut self, pair: asset::Pair, interval: Interval) -> Self {
+        self.streams.push(StreamType::Klines(pair, interval));
+        self
+    }
+
+    pub fn add_aggregate_trade_stream(mut self, pair: asset::Pair) -> Self {
+        self.streams.push(StreamType::AggregateTrades(pair));
+        self
+    }
+
+    /// Add a stream to a request already built. Returns `false` if it was already there.
+    pub fn add_stream(&mut self, stream: StreamType) -> bool {
+        if self.streams.contains(&stream) {
+            false
+        } else {
+            self.streams.push(stream);
+            true
+        }
+    }
+
+    /// Returns `false` if the stream wasn't there.
+    pub fn remove_stream(&mut self, stream: &StreamType) -> bool {
+        let before = self.streams.len();
+        self.streams.retain(|st| st != stream);
+        before != self.streams.len()
+    }
+
+    /// All the streams requested. Sorted and without repeats.
+    pub fn streams(&self) -> Vec<StreamType> {
         let mut streams = self.streams.clone();
         streams.sort();
         streams.dedup();
+        streams
+    }
 
-        let streams = streams
+    /// Generate URL that can be passed into websocket client.
+    pub fn url(&self) -> String {
+        let streams = self.streams()
             .into_iter()
             .fold(String::new(), |mut all, st| {
                 let item = st.to_string();
@@ -97,7 +176,47 @@ impl StreamRequest {
             .into_iter()
             .filter_map(|st| match st {
                 StreamType::TradeHistoryItems(ap) => Some(ap),
-                // _ => None,
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Asset pairs to fetch order book diff streams for.
+    pub fn order_book_asset_pairs(&self) -> Vec<asset::Pair> {
+        self.streams
+            .clone()
+            .into_iter()
+            .filter_map(|st| match st {
+                StreamType::OrderBookDiffs(ap) => Some(ap),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Asset pairs to fetch kline streams for. Any pair is listed once no matter how many
+    /// intervals it has.
+    pub fn kline_asset_pairs(&self) -> Vec<asset::Pair> {
+        let mut pairs: Vec<asset::Pair> = self.streams
+            .clone()
+            .into_iter()
+            .filter_map(|st| match st {
+                StreamType::Klines(ap, _) => Some(ap),
+                _ => None,
+            })
+            .collect();
+        pairs.sort();
+        pairs.dedup();
+        pairs
+    }
+
+    /// Asset pairs to fetch aggregate trade streams for.
+    pub fn aggregate_trade_asset_pairs(&self) -> Vec<asset::Pair> {
+        self.streams
+            .clone()
+            .into_iter()
+            .filter_map(|st| match st {
+                StreamType::AggregateTrades(ap) => Some(ap),
+                _ => None,
             })
             .collect()
     }
@@ -126,4 +245,54 @@ mod tests {
             "wss://stream.binance.com:9443/stream?streams=bnbbtc@trade/bnbusd@trade"
         );
     }
+
+    #[test]
+    fn subscribe_trade_and_depth_streams() {
+        let req = StreamRequest::new()
+            .add_trade_history_item_stream(asset::BTC_USD)
+            .add_order_book_stream(asset::BTC_USD);
+
+        assert_eq!(
+            req.url(),
+            "wss://stream.binance.com:9443/stream?streams=btcusdt@trade/btcusdt@depth"
+        );
+        assert_eq!(req.order_book_asset_pairs(), vec![asset::BTC_USD]);
+        assert_eq!(symbol(asset::BTC_USD), "BTCUSDT");
+    }
+
+    #[test]
+    fn subscribe_kline_and_aggregate_trade_streams() {
+        let req = StreamRequest::new()
+            .add_kline_stream(asset::BNB_BTC, Interval::Minute1)
+            .add_kline_stream(asset::BNB_BTC, Interval::Hour1)
+            .add_aggregate_trade_stream(asset::BNB_BTC);
+
+        assert_eq!(
+            req.url(),
+            "wss://stream.binance.com:9443/stream?streams=\
+             bnbbtc@kline_1m/bnbbtc@kline_1h/bnbbtc@aggTrade"
+        );
+        assert_eq!(req.kline_asset_pairs(), vec![asset::BNB_BTC]);
+        assert_eq!(req.aggregate_trade_asset_pairs(), vec![asset::BNB_BTC]);
+    }
+
+    #[test]
+    fn stream_from_kind() {
+        assert_eq!(
+            StreamType::from_kind("kline_1h", asset::BTC_USD),
+            Some(StreamType::Klines(asset::BTC_USD, Interval::Hour1)),
+        );
+        assert_eq!(
+            StreamType::from_kind("aggTrade", asset::BTC_USD).unwrap().to_string(),
+            "btcusdt@aggTrade",
+        );
+        assert_eq!(StreamType::from_kind("kline_1y", asset::BTC_USD), None);
+        assert_eq!(StreamType::from_kind("ticker", asset::BTC_USD), None);
+
+        let mut req = StreamRequest::new();
+        assert!(req.add_stream(StreamType::TradeHistoryItems(asset::BNB_BTC)));
+        assert!(!req.add_stream(StreamType::TradeHistoryItems(asset::BNB_BTC)));
+        assert!(req.remove_stream(&StreamType::TradeHistoryItems(asset::BNB_BTC)));
+        assert!(req.streams().is_empty());
+    }
 }
@@ -0,0 +1,46 @@
+//! Which asset pair each channel is for.
+//!
+//! Bitfinex hands out channel IDs as subscriptions are made and channel data only carries
+//! the ID. The IDs belong to the connection so a new connection starts with an empty map.
+use std::collections::HashMap;
+
+use common::asset;
+
+#[derive(Debug, Clone, Default)]
+pub struct Channels {
+    pairs: HashMap<u64, asset::Pair>,
+}
+
+impl Channels {
+    /// Record a subscription. Returns the pair the channel was for if it was reused.
+    pub fn subscribed(&mut self, chan_id: u64, pair: asset::Pair) -> Option<asset::Pair> {
+        self.pairs.insert(chan_id, pair)
+    }
+
+    pub fn unsubscribed(&mut self, chan_id: u64) -> Option<asset::Pair> {
+        self.pairs.remove(&chan_id)
+    }
+
+    pub fn pair(&self, chan_id: u64) -> Option<asset::Pair> {
+        self.pairs.get(&chan_id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_map_to_pairs() {
+        let mut channels = Channels::default();
+
+        assert_eq!(channels.subscribed(17470, asset::BTC_USD), None);
+        assert_eq!(channels.subscribed(17471, asset::ETH_USD), None);
+        assert_eq!(channels.pair(17470), Some(asset::BTC_USD));
+        assert_eq!(channels.pair(17471), Some(asset::ETH_USD));
+        assert_eq!(channels.pair(1), None);
+
+        assert_eq!(channels.unsubscribed(17470), Some(asset::BTC_USD));
+        assert_eq!(channels.pair(17470), None);
+    }
+}
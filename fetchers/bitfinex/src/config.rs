@@ -0,0 +1,66 @@
+//! Configuration
+use std::env;
+use std::time::Duration;
+
+use common::asset;
+use common::errors::ConfigError;
+
+use lib::DEFAULT_STALE_AFTER_SECS;
+
+static COLLECTOR: &str = "SAMMY_COLLECTOR";
+static ASSET_PAIRS: &str = "BITFINEX_ASSET_PAIRS";
+static WS_URI: &str = "BITFINEX_WS_URI";
+static STALE_AFTER: &str = "BITFINEX_STALE_AFTER_SECS";
+
+static DEFAULT_WS_URI: &str = "wss://api.bitfinex.com/ws/2";
+
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    collector: String,
+    ws_uri: String,
+    asset_pairs: Vec<asset::Pair>,
+    stale_after: Duration,
+}
+
+impl Configuration {
+    pub fn collector(&self) -> &str {
+        self.collector.as_str()
+    }
+
+    pub fn ws_uri(&self) -> &str {
+        self.ws_uri.as_str()
+    }
+
+    /// Asset pairs to fetch the trades of.
+    pub fn asset_pairs(&self) -> Vec<asset::Pair> {
+        self.asset_pairs.clone()
+    }
+
+    /// How long the connection can go without receiving anything before it is replaced.
+    pub fn stale_after(&self) -> Duration {
+        self.stale_after
+    }
+}
+
+pub fn config_from_environment() -> Result<Configuration, ConfigError> {
+    let collector = env::var(COLLECTOR).map_err(|e| (COLLECTOR, e))?;
+    let ws_uri = env::var(WS_URI).unwrap_or(DEFAULT_WS_URI.to_owned());
+    let asset_pairs = env::var(ASSET_PAIRS).map_err(|e| (ASSET_PAIRS, e))?;
+
+    let asset_pairs = asset_pairs
+        .split(':')
+        .map(|ap_str| ap_str.parse().map_err(|e| (ASSET_PAIRS, e)))
+        .collect::<Result<Vec<asset::Pair>, _>>()?;
+
+    let stale_after = match env::var(STALE_AFTER) {
+        Ok(secs) => secs.parse().map_err(|e| (STALE_AFTER, e))?,
+        Err(_) => DEFAULT_STALE_AFTER_SECS,
+    };
+
+    Ok(Configuration {
+        collector,
+        ws_uri,
+        asset_pairs,
+        stale_after: Duration::from_secs(stale_after),
+    })
+}
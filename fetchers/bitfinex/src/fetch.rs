@@ -0,0 +1,161 @@
+//! Keep a websocket connection to bitfinex and forward the final trades on. The
+//! connection itself is kept by `fetch_lib::websocket`. When bitfinex asks us to
+//! reconnect, or maintenance ends, we do so straight away and subscribe again.
+use std::time::Duration;
+
+use futures::Future;
+use futures::sync::mpsc::UnboundedSender;
+use serde_json;
+
+use fetch_lib::deadletter::DeadLetters;
+use fetch_lib::websocket::{self, End, Handler};
+use common::{asset, trade};
+
+use payload::{self, Event, Update, Subscribe};
+use channels::Channels;
+
+/// Bitfinex sends a heartbeat on each quiet channel every fifteen seconds.
+pub const DEFAULT_STALE_AFTER_SECS: u64 = 45;
+
+/// Handles what arrives on a connection. Cloned fresh for each one so the channels start
+/// empty.
+#[derive(Clone)]
+struct Client {
+    forward: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
+    dead_letters: DeadLetters,
+    channels: Channels,
+}
+
+impl Handler for Client {
+    fn on_text(&mut self, json: &str) -> Result<(), End> {
+        let message = match payload::parse(json) {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Payload parsing failed: {}", &e);
+                self.dead_letters.record(json, &e);
+                return Ok(());
+            },
+        };
+
+        trace!("Received: {:?}", &message);
+        match message {
+            payload::Message::Event(event) => self.on_event(json, event)?,
+            payload::Message::Update(update) => self.on_update(json, update)?,
+        }
+
+        self.dead_letters.success();
+        Ok(())
+    }
+}
+
+impl Client {
+    fn on_event(&mut self, json: &str, event: Event) -> Result<(), End> {
+        match event {
+            Event::Info { code: Some(code), msg, .. } => match code {
+                payload::INFO_RECONNECT | payload::INFO_MAINTENANCE_END => {
+                    warn!("Bitfinex info {}: {:?}", code, &msg);
+                    return Err(End::Resubscribe(format!("info {}", code)));
+                },
+                payload::INFO_MAINTENANCE_START => {
+                    warn!("Bitfinex maintenance started. Waiting for it to end.");
+                },
+                _ => info!("Bitfinex info {}: {:?}", code, &msg),
+            },
+            Event::Info { version, .. } => debug!("Connected to API version {:?}.", version),
+            Event::Subscribed { chan_id, symbol } => {
+                match payload::asset_pair_parse(&symbol) {
+                    Ok(ap) => {
+                        debug!("Subscribed to {} on channel {}.", &ap, chan_id);
+                        self.channels.subscribed(chan_id, ap);
+                    },
+                    Err(e) => {
+                        error!("Subscribed to unknown symbol: {}", &e);
+                        self.dead_letters.record(json, &e);
+                    },
+                }
+            },
+            Event::Unsubscribed { chan_id } => {
+                let ap = self.channels.unsubscribed(chan_id);
+                warn!("Unsubscribed from channel {} ({:?}).", chan_id, ap);
+            },
+            Event::Error { msg, code } => {
+                error!("Bitfinex error {}: {}", code, &msg);
+                self.dead_letters.record(json, &msg);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Only final trades are forwarded, as each `te` is followed by a `tu` for the same
+    /// trade. The snapshot is forwarded too so that trades made whilst reconnecting aren't
+    /// lost. Those already forwarded before are skipped on their match ID once stored.
+    fn on_update(&mut self, json: &str, update: Update) -> Result<(), End> {
+        let (chan_id, trades) = match update {
+            Update::Updated(chan_id, trade) => (chan_id, vec![trade]),
+            Update::Snapshot(chan_id, mut trades) => {
+                // Newest first. Forwarded oldest first like the rest of the feed.
+                trades.reverse();
+                (chan_id, trades)
+            },
+            Update::Executed(..) | Update::Heartbeat(..) => return Ok(()),
+        };
+
+        let ap = match self.channels.pair(chan_id) {
+            Some(ap) => ap,
+            None => {
+                let e = format!("Unknown channel: {}", chan_id);
+                error!("{}", &e);
+                self.dead_letters.record(json, &e);
+                return Ok(());
+            },
+        };
+
+        for trade in trades {
+            let item = match trade.as_trade_history_item() {
+                Ok(item) => item,
+                Err(e) => {
+                    error!("Unusable trade: {}", &e);
+                    self.dead_letters.record(json, &e);
+                    continue;
+                },
+            };
+
+            self.forward
+                .unbounded_send((ap, item))
+                .map_err(|_| End::Halted)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the future that streams the final trades of the `pairs` from the websocket at
+/// `uri` and forwards them on. Must be run on the tokio runtime.
+///
+/// Resolves once `shutdown` does. The `forward` channel is dropped with it.
+pub fn stream<F>(
+    uri: &str,
+    pairs: Vec<asset::Pair>,
+    shutdown: F,
+    stale_after: Duration,
+    forward: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
+    dead_letters: DeadLetters,
+) -> impl Future<Item = (), Error = ()>
+where F: Future<Item = (), Error = ()> + Send + 'static
+{
+    let subscribes: Vec<String> = pairs
+        .iter()
+        .map(|pair| {
+            serde_json::to_string(&Subscribe::trades(*pair))
+                .expect("Subscribe frame always serializes.")
+        })
+        .collect();
+    let client = Client {
+        forward: forward,
+        dead_letters: dead_letters,
+        channels: Channels::default(),
+    };
+
+    websocket::run("bitfinex", uri, subscribes, stale_after, client, shutdown)
+}
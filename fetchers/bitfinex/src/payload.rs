@@ -0,0 +1,268 @@
+//! Messages on the bitfinex v2 websocket.
+//!
+//! Events about the connection and subscriptions are JSON objects tagged by `event`.
+//! Channel data comes as arrays led by the channel ID. Nothing else in them says which pair
+//! they are for. A trades channel sends a snapshot of recent trades once subscribed, then
+//! a `te` when a trade executes and a `tu` once it is final, then heartbeats when quiet.
+use rust_decimal::Decimal;
+use chrono::{TimeZone, Utc};
+use serde_json::{self, Value};
+
+use common::{asset, trade};
+
+/// Bitfinex is restarting. Everything has to be subscribed again on a new connection.
+pub const INFO_RECONNECT: u32 = 20051;
+
+/// Maintenance started. Nothing will come until it ends.
+pub const INFO_MAINTENANCE_START: u32 = 20060;
+
+/// Maintenance ended. Subscriptions need to be made again.
+pub const INFO_MAINTENANCE_END: u32 = 20061;
+
+/// Bitfinex prefixes trading pair symbols with a `t`. `tBTCUSD` for example.
+pub fn symbol(pair: asset::Pair) -> String {
+    format!("t{}{}", pair.left().as_str(), pair.right().as_str())
+}
+
+pub fn asset_pair_parse(symbol: &str) -> Result<asset::Pair, String> {
+    if !symbol.starts_with('t') {
+        return Err(format!("Not a trading pair: {}", symbol));
+    }
+
+    symbol[1..]
+        .parse()
+        .map_err(|e: asset::ParseAssetError| format!("{}: {}", e, symbol))
+}
+
+/// Sent once for each pair to subscribe to its trades.
+#[derive(Debug, Clone, Serialize)]
+pub struct Subscribe {
+    event: &'static str,
+    channel: &'static str,
+    symbol: String,
+}
+
+impl Subscribe {
+    pub fn trades(pair: asset::Pair) -> Self {
+        Subscribe {
+            event: "subscribe",
+            channel: "trades",
+            symbol: symbol(pair),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    /// Sent on connecting with the version. Later ones carry a code telling us to
+    /// reconnect or that maintenance started or ended.
+    Info {
+        #[serde(default)]
+        version: Option<u32>,
+        #[serde(default)]
+        code: Option<u32>,
+        #[serde(default)]
+        msg: Option<String>,
+    },
+    Subscribed {
+        #[serde(rename = "chanId")]
+        chan_id: u64,
+        symbol: String,
+    },
+    Unsubscribed {
+        #[serde(rename = "chanId")]
+        chan_id: u64,
+    },
+    Error {
+        msg: String,
+        code: u32,
+    },
+}
+
+/// A trade as `[ID, MTS, AMOUNT, PRICE]`. The amount is negative when the taker sold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    id: i64,
+    mts: i64,
+    amount: Decimal,
+    price: Decimal,
+}
+
+impl Trade {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let fields = value.as_array().ok_or("Trade isn't an array.")?;
+        if fields.len() < 4 {
+            return Err(format!("Insufficient trade length: {}", fields.len()));
+        }
+
+        let id = fields[0].as_i64().ok_or("Invalid trade ID.")?;
+        let mts = fields[1].as_i64().ok_or("Invalid trade timestamp.")?;
+        let amount = decimal(&fields[2])?;
+        let price = decimal(&fields[3])?;
+
+        Ok(Trade {
+            id, mts, amount, price,
+        })
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Convert into the common format. Bitfinex gives no order IDs on the public channel.
+    pub fn as_trade_history_item(&self) -> Result<trade::TradeHistoryItem, String> {
+        let timestamp = Utc
+            .timestamp_opt(self.mts / 1000, (self.mts % 1000) as u32 * 1_000_000)
+            .single()
+            .ok_or(format!("Timestamp out of range: {}", self.mts))?;
+
+        // A taker buying is the same as kraken's `b`.
+        let market = if self.amount.is_sign_negative() {
+            trade::Market::Maker
+        } else {
+            trade::Market::Taker
+        };
+
+        Ok(trade::TradeHistoryItem::new(
+            timestamp,
+            self.amount.abs(),
+            self.price,
+            market,
+            None,
+            Some(self.id),
+            None,
+            None,
+            None,
+        ))
+    }
+}
+
+/// Bitfinex sends numbers as JSON numbers. Going through their text keeps the decimal
+/// digits as sent.
+fn decimal(value: &Value) -> Result<Decimal, String> {
+    match value {
+        Value::Number(n) => n.to_string().parse().map_err(|_| format!("Invalid number: {}", n)),
+        other => Err(format!("Expected a number. Got {}", other)),
+    }
+}
+
+/// Data on a channel. Each carries the channel ID.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update {
+    /// Recent trades. Sent once on subscribing.
+    Snapshot(u64, Vec<Trade>),
+
+    /// A trade executed. `te`. Not yet final.
+    Executed(u64, Trade),
+
+    /// The trade is final. `tu`.
+    Updated(u64, Trade),
+    Heartbeat(u64),
+}
+
+impl Update {
+    fn parse(fields: &[Value]) -> Result<Self, String> {
+        let chan_id = fields.get(0).and_then(Value::as_u64).ok_or("Missing channel ID.")?;
+        match fields.get(1) {
+            Some(Value::Array(trades)) => trades
+                .iter()
+                .map(Trade::from_value)
+                .collect::<Result<Vec<Trade>, String>>()
+                .map(|trades| Update::Snapshot(chan_id, trades)),
+            Some(Value::String(kind)) => match kind.as_str() {
+                "hb" => Ok(Update::Heartbeat(chan_id)),
+                "te" => trade_at(fields, 2).map(|trade| Update::Executed(chan_id, trade)),
+                "tu" => trade_at(fields, 2).map(|trade| Update::Updated(chan_id, trade)),
+                other => Err(format!("Unknown update: {}", other)),
+            },
+            _ => Err("Missing update body.".to_owned()),
+        }
+    }
+}
+
+fn trade_at(fields: &[Value], index: usize) -> Result<Trade, String> {
+    fields.get(index).ok_or("Missing trade.".to_owned()).and_then(Trade::from_value)
+}
+
+/// Anything that comes in on the websocket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Event(Event),
+    Update(Update),
+}
+
+pub fn parse(json: &str) -> Result<Message, String> {
+    let value: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    match value {
+        Value::Object(_) => serde_json::from_value(value)
+            .map(Message::Event)
+            .map_err(|e| e.to_string()),
+        Value::Array(fields) => Update::parse(&fields).map(Message::Update),
+        other => Err(format!("Unexpected message: {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_events() {
+        assert_eq!(
+            parse(include_str!("../fixtures/subscribed.json")).unwrap(),
+            Message::Event(Event::Subscribed { chan_id: 17470, symbol: "tBTCUSD".to_owned() }),
+        );
+        assert_eq!(
+            parse(include_str!("../fixtures/info_reconnect.json")).unwrap(),
+            Message::Event(Event::Info {
+                version: None,
+                code: Some(INFO_RECONNECT),
+                msg: Some("Stopping. Please try to reconnect".to_owned()),
+            }),
+        );
+        assert_eq!(
+            parse(include_str!("../fixtures/error.json")).unwrap(),
+            Message::Event(Event::Error { msg: "symbol: invalid".to_owned(), code: 10300 }),
+        );
+    }
+
+    #[test]
+    fn parse_updates() {
+        match parse(include_str!("../fixtures/snapshot.json")).unwrap() {
+            Message::Update(Update::Snapshot(17470, trades)) => assert_eq!(trades.len(), 2),
+            other => panic!("Expected a snapshot. Got {:?}", other),
+        }
+        match parse(include_str!("../fixtures/te.json")).unwrap() {
+            Message::Update(Update::Executed(17470, trade)) => assert_eq!(trade.id(), 401597397),
+            other => panic!("Expected an execution. Got {:?}", other),
+        }
+        assert_eq!(
+            parse(include_str!("../fixtures/heartbeat.json")).unwrap(),
+            Message::Update(Update::Heartbeat(17470)),
+        );
+        assert!(parse(r#"[17470,"tx",[]]"#).is_err());
+    }
+
+    #[test]
+    fn final_trade_into_common() {
+        let trade = match parse(include_str!("../fixtures/tu.json")).unwrap() {
+            Message::Update(Update::Updated(17470, trade)) => trade,
+            other => panic!("Expected an update. Got {:?}", other),
+        };
+
+        let item = trade.as_trade_history_item().unwrap();
+        assert_eq!(item.timestamp(), Utc.ymd(2018, 10, 23).and_hms_milli(8, 0, 43, 117));
+        assert_eq!(item.size(), "0.02".parse().unwrap());
+        assert_eq!(item.price(), "6479.3".parse().unwrap());
+        assert_eq!(item.market(), trade::Market::Maker);
+        assert_eq!(item.match_id(), Some(401597397));
+    }
+
+    #[test]
+    fn symbols() {
+        assert_eq!(symbol(asset::ETH_BTC), "tETHBTC");
+        assert_eq!(asset_pair_parse("tBTCUSD").unwrap(), asset::BTC_USD);
+        assert!(asset_pair_parse("fUSD").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+//! Configuration
+use std::env;
+use std::time::Duration;
+
+use common::asset;
+use common::errors::ConfigError;
+
+static COLLECTOR: &str = "SAMMY_COLLECTOR";
+static ASSET_PAIRS: &str = "BITSTAMP_ASSET_PAIRS";
+static POLL_DELAY: &str = "BITSTAMP_POLL_DELAY_SECS";
+
+/// Each poll returns the last minute of transactions. Polling well within that leaves room
+/// for failed polls before any trades are missed.
+const DEFAULT_POLL_DELAY_SECS: u64 = 15;
+
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    collector: String,
+    asset_pairs: Vec<asset::Pair>,
+    poll_delay: Duration,
+}
+
+impl Configuration {
+    pub fn collector(&self) -> &str {
+        self.collector.as_str()
+    }
+
+    pub fn asset_pairs(&self) -> Vec<asset::Pair> {
+        self.asset_pairs.clone()
+    }
+
+    /// Time between polls of each asset pair.
+    pub fn poll_delay(&self) -> Duration {
+        self.poll_delay
+    }
+}
+
+pub fn config_from_environment() -> Result<Configuration, ConfigError> {
+    let collector = env::var(COLLECTOR).map_err(|e| (COLLECTOR, e))?;
+    let asset_pairs = env::var(ASSET_PAIRS).map_err(|e| (ASSET_PAIRS, e))?;
+
+    let asset_pairs = asset_pairs
+        .split(':')
+        .map(|ap_str| ap_str.parse().map_err(|e| (ASSET_PAIRS, e)))
+        .collect::<Result<Vec<asset::Pair>, _>>()?;
+
+    let poll_delay = match env::var(POLL_DELAY) {
+        Ok(secs) => secs.parse().map_err(|e| (POLL_DELAY, e))?,
+        Err(_) => DEFAULT_POLL_DELAY_SECS,
+    };
+
+    Ok(Configuration {
+        collector,
+        asset_pairs,
+        poll_delay: Duration::from_secs(poll_delay),
+    })
+}
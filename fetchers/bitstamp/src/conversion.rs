@@ -0,0 +1,169 @@
+//! Code to convert from internal models to common models.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rust_decimal::Decimal;
+use chrono::{DateTime, TimeZone, Utc};
+
+use common::{trade, asset};
+
+use model::{TradeHistory, Transaction};
+
+/// Running count of transactions that couldn't be parsed since the process started.
+static PARSE_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of transactions rejected so far. A steady climb means Bitstamp changed its API.
+pub fn parse_error_count() -> usize {
+    PARSE_ERRORS.load(Ordering::Relaxed)
+}
+
+/// A parsed bitstamp transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeRow {
+    tid: i64,
+    price: Decimal,
+    size: Decimal,
+    timestamp: DateTime<Utc>,
+    market: trade::Market,
+}
+
+impl TradeRow {
+    pub fn parse(transaction: &Transaction) -> Result<Self, String> {
+        let tid: i64 = transaction.tid()
+            .parse()
+            .map_err(|_| format!("Invalid tid: {}", transaction.tid()))?;
+
+        let price: Decimal = transaction.price()
+            .parse()
+            .map_err(|_| "Invalid price number format.".to_string())?;
+
+        let size: Decimal = transaction.amount()
+            .parse()
+            .map_err(|_| "Invalid amount number format.".to_string())?;
+
+        // Bitstamp dates are whole Unix seconds.
+        let seconds: i64 = transaction.date()
+            .parse()
+            .map_err(|_| format!("Invalid date: {}", transaction.date()))?;
+        let timestamp = Utc
+            .timestamp_opt(seconds, 0)
+            .single()
+            .ok_or(format!("Date out of range: {}", seconds))?;
+
+        // A buy is the same as kraken's `b`. The taker bought off the book.
+        let market = match transaction.side() {
+            "0" => trade::Market::Taker,
+            "1" => trade::Market::Maker,
+            side => return Err(format!("Invalid type: {}", side)),
+        };
+
+        Ok(TradeRow {
+            tid, price, size, timestamp, market,
+        })
+    }
+
+    pub fn tid(&self) -> i64 {
+        self.tid
+    }
+
+    /// Build the common trade history item. Bitstamp doesn't say whether the order was
+    /// limit or market nor does it give order IDs on this endpoint.
+    pub fn to_trade_history_item(&self) -> trade::TradeHistoryItem {
+        trade::TradeHistoryItem::new(
+            self.timestamp,
+            self.size,
+            self.price,
+            self.market,
+            None,
+            Some(self.tid),
+            None,
+            None,
+            None,
+        )
+    }
+}
+
+/// Convert the internal bitstamp transactions into the common model, oldest first. Bitstamp
+/// sends the newest first. Also returns how many transactions were rejected.
+pub fn trade_history(
+    history: &TradeHistory
+) -> (asset::Pair, Vec<trade::TradeHistoryItem>, usize) {
+    let mut rows = Vec::new();
+    let mut rejected = 0;
+
+    for transaction in history.transactions() {
+        match TradeRow::parse(transaction) {
+            Ok(row) => rows.push(row),
+            Err(e) => {
+                PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                warn!("Rejected bitstamp transaction: {}. Transaction: {:?}", &e, transaction);
+                rejected += 1;
+            },
+        }
+    }
+
+    rows.sort_by_key(|row| row.tid());
+    let items = rows
+        .iter()
+        .map(|row| row.to_trade_history_item())
+        .collect();
+
+    (history.pair(), items, rejected)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::*;
+
+    fn history(json: &str) -> TradeHistory {
+        let transactions = serde_json::from_str(json).expect("Failed to deserialize.");
+        TradeHistory::new(asset::BTC_USD, transactions)
+    }
+
+    #[test]
+    fn can_convert_trade_history() {
+        let th = history(r##"[
+            {"date": "1540205137", "tid": "75624518", "price": "6512.43", "type": "1", "amount": "0.035"},
+            {"date": "1540205101", "tid": "75624497", "price": "6513.00", "type": "0", "amount": "1.2"}
+        ]"##);
+        let (pair, items, rejected) = trade_history(&th);
+
+        assert_eq!(pair, asset::BTC_USD);
+        assert_eq!(rejected, 0);
+        assert_eq!(items.len(), 2);
+
+        // Oldest first.
+        assert_eq!(items[0].match_id(), Some(75624497));
+        assert_eq!(items[0].market(), trade::Market::Taker);
+        assert_eq!(items[0].price(), "6513.00".parse().unwrap());
+        assert_eq!(items[0].size(), "1.2".parse().unwrap());
+        assert_eq!(items[0].trade(), None);
+
+        assert_eq!(items[1].match_id(), Some(75624518));
+        assert_eq!(items[1].market(), trade::Market::Maker);
+    }
+
+    #[test]
+    fn timestamp_conversion() {
+        let th = history(r##"[
+            {"date": "1539957355", "tid": "1", "price": "10", "type": "0", "amount": "1"}
+        ]"##);
+        let item = trade_history(&th).1[0];
+        assert_eq!(item.timestamp().to_rfc3339(), "2018-10-19T13:55:55+00:00");
+    }
+
+    #[test]
+    fn bad_transactions_are_counted_not_fatal() {
+        let th = history(r##"[
+            {"date": "1539957355", "tid": "1", "price": "10", "type": "2", "amount": "1"},
+            {"date": "soon", "tid": "2", "price": "10", "type": "0", "amount": "1"},
+            {"date": "1539957355", "tid": "3", "price": "10", "type": "0", "amount": "1"}
+        ]"##);
+        let (_, items, rejected) = trade_history(&th);
+
+        assert_eq!(rejected, 2);
+        assert_eq!(items.len(), 1);
+        assert!(parse_error_count() >= 2);
+    }
+}
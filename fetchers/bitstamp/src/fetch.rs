@@ -0,0 +1,215 @@
+//! Fetching code
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::{Future, Stream};
+use futures::stream;
+use tokio_timer;
+
+use fetch_lib::https_client::{HttpsClient, FetchError, parse_json};
+use fetch_lib::deadletter::DeadLetters;
+use common::{asset, trade};
+
+use super::BitstampFetchTargets;
+use model::{Transaction, TradeHistory};
+use conversion::{trade_history, parse_error_count};
+
+/// Return stream that polls the transactions of a single asset pair. Each poll returns the
+/// transactions of the last minute so the `poll_delay` must stay under that or trades will
+/// be missed. An asset pair that Bitstamp doesn't trade ends the stream with an error.
+pub fn poll_trade_history(
+    client: HttpsClient,
+    pair: asset::Pair,
+    targets: BitstampFetchTargets,
+    poll_delay: Duration,
+) -> impl Stream<Item = TradeHistory, Error = FetchError> {
+    let uri = targets.trade_history(pair).ok_or(FetchError::UnsupportedPair(pair));
+
+    stream::once(uri)
+        .map(move |uri| {
+            let client = client.clone();
+            tokio_timer::Interval::new_interval(poll_delay)
+                .from_err::<FetchError>()
+                .and_then(move |_| client.get(uri.clone()).from_err())
+        })
+        .flatten()
+        .and_then(|res| {
+            let status = res.status().as_u16();
+            res.into_body()
+                .concat2()
+                .from_err::<FetchError>()
+                .and_then(move |body| {
+                    if status != 200 {
+                        return Err(FetchError::Status(status));
+                    }
+                    let transactions: Vec<Transaction> = parse_json(&body)?;
+                    Ok(transactions)
+                })
+        })
+        .map(move |transactions| TradeHistory::new(pair, transactions))
+}
+
+/// Poll several asset pairs at once. The fetch streams are merged with `select`.
+pub fn poll_trade_histories(
+    client: HttpsClient,
+    pairs: Vec<asset::Pair>,
+    targets: BitstampFetchTargets,
+    poll_delay: Duration,
+) -> impl Stream<Item = TradeHistory, Error = FetchError> {
+    let empty: Box<Stream<Item = TradeHistory, Error = FetchError> + Send> =
+        Box::new(stream::empty());
+
+    pairs
+        .into_iter()
+        .fold(empty, move |merged, pair| {
+            let fetch = poll_trade_history(client.clone(), pair, targets.clone(), poll_delay);
+            Box::new(merged.select(fetch))
+        })
+}
+
+/// Takes in the fetch stream and logs the errors. A failed poll is tried again on the next
+/// tick so none of them end the stream. Responses that can't be deserialized are
+/// quarantined in the `dead_letters`.
+pub fn filter_benign_errors(
+    input: impl Stream<Item = TradeHistory, Error = FetchError>,
+    dead_letters: DeadLetters,
+) -> impl Stream<Item = TradeHistory, Error = ()> {
+    input
+        .then(move |result| {
+            match result {
+                Ok(history) => {
+                    dead_letters.success();
+                    Ok(Some(history))
+                },
+                Err(FetchError::Payload(e, body)) => {
+                    error!("Deserialize error: {}. Quarantined.", &e);
+                    dead_letters.record(&body, &e.to_string());
+                    Ok(None)
+                },
+                Err(FetchError::Status(status)) => {
+                    // Bitstamp throttles with a 400 or the like when polled too often.
+                    error!("HTTP Status: {}", &status);
+                    Ok(None)
+                },
+                Err(FetchError::UnsupportedPair(pair)) => {
+                    error!("Bitstamp doesn't trade {}. It won't be polled.", &pair);
+                    Ok(None)
+                },
+                Err(e) => {
+                    error!("Fetch failed: {}", &e);
+                    Ok(None)
+                },
+            }
+        })
+        .filter_map(|item| item)
+}
+
+/// Takes a filtered fetch stream and converts it into the common format for placement.
+/// Transactions that can't be converted are dropped (and counted) rather than ending the
+/// stream.
+pub fn convert_into_common(
+    input: impl Stream<Item = TradeHistory, Error = ()>
+) -> impl Stream<Item = (asset::Pair, Vec<trade::TradeHistoryItem>), Error = ()> {
+    input.map(|history| {
+        let (asset_pair, items, rejected) = trade_history(&history);
+        if rejected > 0 {
+            warn!(
+                "{} of {} {} transactions rejected. {} rejected in total.",
+                rejected,
+                rejected + items.len(),
+                &asset_pair,
+                parse_error_count(),
+            );
+        }
+        (asset_pair, items)
+    })
+}
+
+/// Highest `tid` placed for each asset pair.
+#[derive(Debug, Default)]
+struct Seen {
+    last_tids: HashMap<asset::Pair, i64>,
+}
+
+impl Seen {
+    /// Keep the items newer than the last ones seen for the pair. The items must be oldest
+    /// first as they come from `convert_into_common`.
+    fn fresh(
+        &mut self, pair: asset::Pair, items: Vec<trade::TradeHistoryItem>,
+    ) -> Vec<trade::TradeHistoryItem> {
+        let last = self.last_tids.get(&pair).cloned();
+        let fresh: Vec<trade::TradeHistoryItem> = items
+            .into_iter()
+            .filter(|item| match (item.match_id(), last) {
+                (Some(tid), Some(last)) => tid > last,
+                _ => true,
+            })
+            .collect();
+
+        if let Some(tid) = fresh.last().and_then(|item| item.match_id()) {
+            self.last_tids.insert(pair, tid);
+        }
+        fresh
+    }
+}
+
+/// Each poll returns the whole last minute so consecutive polls overlap. Drop what has
+/// already been passed on along with the polls that had nothing new.
+pub fn drop_seen(
+    input: impl Stream<Item = (asset::Pair, Vec<trade::TradeHistoryItem>), Error = ()>
+) -> impl Stream<Item = (asset::Pair, Vec<trade::TradeHistoryItem>), Error = ()> {
+    let mut seen = Seen::default();
+    input
+        .map(move |(pair, items)| (pair, seen.fresh(pair, items)))
+        .filter(|(_, items)| !items.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn item(tid: i64) -> trade::TradeHistoryItem {
+        trade::TradeHistoryItem::new(
+            Utc::now(), 1.into(), 10.into(), trade::Market::Taker, None, Some(tid), None, None,
+            None,
+        )
+    }
+
+    fn tids(items: &[trade::TradeHistoryItem]) -> Vec<i64> {
+        items.iter().filter_map(|item| item.match_id()).collect()
+    }
+
+    #[test]
+    fn overlapping_polls_are_deduped() {
+        let mut seen = Seen::default();
+
+        let first = seen.fresh(asset::BTC_USD, vec![item(1), item(2), item(3)]);
+        assert_eq!(tids(&first), vec![1, 2, 3]);
+
+        let second = seen.fresh(asset::BTC_USD, vec![item(2), item(3), item(4)]);
+        assert_eq!(tids(&second), vec![4]);
+
+        let quiet = seen.fresh(asset::BTC_USD, vec![item(3), item(4)]);
+        assert!(quiet.is_empty());
+
+        // Pairs are tracked separately.
+        let other = seen.fresh(asset::ETH_USD, vec![item(2)]);
+        assert_eq!(tids(&other), vec![2]);
+    }
+
+    #[test]
+    fn unsupported_pair_ends_the_poll() {
+        let client = ::fetch_lib::https_client::produce(1).unwrap();
+        let mut polls = poll_trade_history(
+            client, asset::BNB_BTC, BitstampFetchTargets, Duration::from_secs(10),
+        ).wait();
+
+        match polls.next() {
+            Some(Err(FetchError::UnsupportedPair(pair))) => assert_eq!(pair, asset::BNB_BTC),
+            other => panic!("Expected an unsupported pair. Got {:?}", other),
+        }
+        assert!(polls.next().is_none());
+    }
+}
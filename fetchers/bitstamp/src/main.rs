@@ -0,0 +1,43 @@
+//! Entrypoint
+#[macro_use] extern crate log;
+extern crate env_logger;
+extern crate futures;
+extern crate tokio;
+extern crate dotenv;
+
+extern crate common;
+extern crate fetch_lib;
+
+extern crate bitstamp_lib as lib;
+
+use common::exchange;
+use fetch_lib::{https_client, place, deadletter};
+
+mod config;
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let config = config::config_from_environment().expect("Can't load config.");
+    debug!("Configuration: {:?}", &config);
+
+    let targets = place::Target::new(
+        config.collector(), exchange::Exchange::Bitstamp, config.asset_pairs(),
+    );
+    let client = https_client::produce(1).expect("Can't init TLS.");
+    let dead_letters = deadletter::DeadLetters::from_environment("bitstamp")
+        .expect("Can't open dead letters.");
+
+    let raw_fetch_stream = lib::poll_trade_histories(
+        client.clone(),
+        config.asset_pairs(),
+        lib::BitstampFetchTargets,
+        config.poll_delay(),
+    );
+    let filtered_fetch_stream = lib::filter_benign_errors(raw_fetch_stream, dead_letters);
+    let converted_stream = lib::convert_into_common(filtered_fetch_stream);
+    let fresh_stream = lib::drop_seen(converted_stream);
+    let place_future = place::put_trade_history(client, targets, fresh_stream);
+
+    tokio::run(place_future);
+}
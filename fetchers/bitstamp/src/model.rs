@@ -0,0 +1,85 @@
+//! Bitstamp models.
+use common::asset;
+
+/// A single transaction as [returned by bitstamp](https://www.bitstamp.net/api/#transactions).
+/// Every field comes as a string.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Transaction {
+    /// Unix timestamp in seconds.
+    date: String,
+
+    /// Transaction ID. Goes up with every trade of the pair.
+    tid: String,
+    price: String,
+    amount: String,
+
+    /// `0` is a buy and `1` a sell.
+    #[serde(rename = "type")]
+    side: String,
+}
+
+impl Transaction {
+    pub fn date(&self) -> &str {
+        self.date.as_str()
+    }
+
+    pub fn tid(&self) -> &str {
+        self.tid.as_str()
+    }
+
+    pub fn price(&self) -> &str {
+        self.price.as_str()
+    }
+
+    pub fn amount(&self) -> &str {
+        self.amount.as_str()
+    }
+
+    pub fn side(&self) -> &str {
+        self.side.as_str()
+    }
+}
+
+/// Transactions of a single asset pair. Bitstamp doesn't say which pair in the response so
+/// it's attached to it once fetched.
+#[derive(Debug, Clone)]
+pub struct TradeHistory {
+    pair: asset::Pair,
+    transactions: Vec<Transaction>,
+}
+
+impl TradeHistory {
+    pub fn new(pair: asset::Pair, transactions: Vec<Transaction>) -> Self {
+        TradeHistory {
+            pair, transactions,
+        }
+    }
+
+    pub fn pair(&self) -> asset::Pair {
+        self.pair
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        self.transactions.as_slice()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::*;
+
+    static TRANSACTIONS_JSON: &str = r##"[{"date": "1540205137", "tid": "75624518", "price": "6512.43", "type": "1", "amount": "0.03500000"}, {"date": "1540205101", "tid": "75624497", "price": "6513.00", "type": "0", "amount": "1.20000000"}]"##;
+
+    #[test]
+    fn deserialize_transactions() {
+        let transactions: Vec<Transaction> = serde_json::from_str(TRANSACTIONS_JSON)
+            .expect("Failed to deserialize.");
+
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].tid(), "75624518");
+        assert_eq!(transactions[0].side(), "1");
+        assert_eq!(transactions[1].date(), "1540205101");
+    }
+}
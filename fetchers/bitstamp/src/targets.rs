@@ -0,0 +1,26 @@
+//! Bitstamp fetch targets
+use hyper::Uri;
+
+use common::asset;
+
+/// Fetch targets. Unit struct like the kraken one with the base hardcoded.
+#[derive(Debug, Clone)]
+pub struct BitstampFetchTargets;
+
+impl BitstampFetchTargets {
+    /// Return the URI for the transactions of the asset pair over the last minute.
+    pub fn trade_history(&self, ap: asset::Pair) -> Option<Uri> {
+        let base = "https://www.bitstamp.net/api/v2/transactions";
+        let pair = match ap {
+            asset::BTC_USD => "btcusd",
+            asset::ETH_USD => "ethusd",
+            asset::ETH_BTC => "ethbtc",
+            _ => return None,
+        };
+
+        let uri = format!("{}/{}/?time=minute", base, pair);
+
+        // This part shouldn't fail as we're controlling URI construction.
+        Some(uri.parse().expect("Invalid URI constructed. This shouldn't happen. Fix me."))
+    }
+}
@@ -0,0 +1,66 @@
+//! Configuration
+use std::env;
+use std::time::Duration;
+
+use common::asset;
+use common::errors::ConfigError;
+
+use lib::DEFAULT_STALE_AFTER_SECS;
+
+static COLLECTOR: &str = "SAMMY_COLLECTOR";
+static PRODUCTS: &str = "COINBASE_PRODUCTS";
+static WS_URI: &str = "COINBASE_WS_URI";
+static STALE_AFTER: &str = "COINBASE_STALE_AFTER_SECS";
+
+static DEFAULT_WS_URI: &str = "wss://ws-feed.pro.coinbase.com";
+
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    collector: String,
+    ws_uri: String,
+    products: Vec<asset::Pair>,
+    stale_after: Duration,
+}
+
+impl Configuration {
+    pub fn collector(&self) -> &str {
+        self.collector.as_str()
+    }
+
+    pub fn ws_uri(&self) -> &str {
+        self.ws_uri.as_str()
+    }
+
+    /// Asset pairs to fetch the trades of.
+    pub fn products(&self) -> Vec<asset::Pair> {
+        self.products.clone()
+    }
+
+    /// How long the connection can go without receiving anything before it is replaced.
+    pub fn stale_after(&self) -> Duration {
+        self.stale_after
+    }
+}
+
+pub fn config_from_environment() -> Result<Configuration, ConfigError> {
+    let collector = env::var(COLLECTOR).map_err(|e| (COLLECTOR, e))?;
+    let ws_uri = env::var(WS_URI).unwrap_or(DEFAULT_WS_URI.to_owned());
+    let products = env::var(PRODUCTS).map_err(|e| (PRODUCTS, e))?;
+
+    let products = products
+        .split(':')
+        .map(|ap_str| ap_str.parse().map_err(|e| (PRODUCTS, e)))
+        .collect::<Result<Vec<asset::Pair>, _>>()?;
+
+    let stale_after = match env::var(STALE_AFTER) {
+        Ok(secs) => secs.parse().map_err(|e| (STALE_AFTER, e))?,
+        Err(_) => DEFAULT_STALE_AFTER_SECS,
+    };
+
+    Ok(Configuration {
+        collector,
+        ws_uri,
+        products,
+        stale_after: Duration::from_secs(stale_after),
+    })
+}
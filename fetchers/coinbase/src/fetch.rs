@@ -0,0 +1,136 @@
+//! Keep a websocket connection to the coinbase feed and forward the trades on. The
+//! connection itself is kept by `fetch_lib::websocket`.
+//!
+//! The feed doesn't need rotating. Coinbase sends heartbeats every second so a connection
+//! that goes silent is dead and is replaced.
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::Future;
+use futures::sync::mpsc::UnboundedSender;
+use serde_json;
+
+use fetch_lib::deadletter::DeadLetters;
+use fetch_lib::websocket::{self, End, Handler};
+use common::{asset, trade};
+
+use payload::{Feed, Subscribe};
+use sequence::{Sequences, Observed, missed_trade_count};
+
+/// Heartbeats come every second. Half a minute without anything is a dead connection.
+pub const DEFAULT_STALE_AFTER_SECS: u64 = 30;
+
+/// Handles what arrives on a connection. Cloned for each one. The sequences are shared so
+/// trades missed between connections are noticed.
+#[derive(Clone)]
+struct Client {
+    forward: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
+    dead_letters: DeadLetters,
+    sequences: Arc<Mutex<Sequences>>,
+}
+
+impl Handler for Client {
+    /// Forward matches that haven't been seen before. Anything that can't be used is
+    /// quarantined in the dead letters.
+    fn on_text(&mut self, json: &str) -> Result<(), End> {
+        let feed = match serde_json::from_str::<Feed>(json) {
+            Ok(feed) => feed,
+            Err(e) => {
+                error!("Payload deserialization failed: {}", &e);
+                self.dead_letters.record(json, &e.to_string());
+                return Ok(());
+            },
+        };
+
+        trace!("Received: {:?}", &feed);
+        let ap = match feed.asset_pair() {
+            Some(Ok(ap)) => Some(ap),
+            Some(Err(e)) => {
+                error!("Invalid product ID: {}", &e);
+                self.dead_letters.record(json, &e.to_string());
+                return Ok(());
+            },
+            None => None,
+        };
+
+        let mut sequences = self.sequences.lock().expect("Sequences lock poisoned.");
+        match (feed, ap) {
+            (Feed::Match(m), Some(ap)) | (Feed::LastMatch(m), Some(ap)) => {
+                let item = match m.as_trade_history_item() {
+                    Ok(item) => item,
+                    Err(e) => {
+                        error!("Unusable match: {}", &e);
+                        self.dead_letters.record(json, &e);
+                        return Ok(());
+                    },
+                };
+
+                match sequences.observe_match(ap, m.sequence(), m.trade_id()) {
+                    Observed::Stale => {
+                        trace!("Dropped {} trade {} already seen.", &ap, m.trade_id());
+                        return Ok(());
+                    },
+                    Observed::Gap { missed } => warn!(
+                        "{} trade(s) missed for {} before {}. {} missed since start.",
+                        missed,
+                        &ap,
+                        m.trade_id(),
+                        missed_trade_count(),
+                    ),
+                    Observed::Fresh => (),
+                }
+
+                self.forward
+                    .unbounded_send((ap, item))
+                    .map_err(|_| End::Halted)?;
+            },
+            (Feed::Heartbeat { sequence, last_trade_id, .. }, Some(ap)) => {
+                let missed = sequences.observe_heartbeat(ap, sequence, last_trade_id);
+                if let Some(missed) = missed {
+                    warn!(
+                        "{} trade(s) missed for {} up to {}. {} missed since start.",
+                        missed,
+                        &ap,
+                        last_trade_id,
+                        missed_trade_count(),
+                    );
+                }
+            },
+            (Feed::Subscriptions, _) => debug!("Subscribed."),
+            (Feed::Error { message, reason }, _) => {
+                error!("Coinbase error: {} ({:?})", &message, &reason);
+                self.dead_letters.record(json, &message);
+                return Ok(());
+            },
+            _ => (),
+        }
+
+        self.dead_letters.success();
+        Ok(())
+    }
+}
+
+/// Returns the future that streams the matches of the `pairs` from the feed at `uri` and
+/// forwards them on. Must be run on the tokio runtime.
+///
+/// Resolves once `shutdown` does. The `forward` channel is dropped with it.
+pub fn stream<F>(
+    uri: &str,
+    pairs: Vec<asset::Pair>,
+    shutdown: F,
+    stale_after: Duration,
+    forward: UnboundedSender<(asset::Pair, trade::TradeHistoryItem)>,
+    dead_letters: DeadLetters,
+) -> impl Future<Item = (), Error = ()>
+where F: Future<Item = (), Error = ()> + Send + 'static
+{
+    let subscribe = serde_json::to_string(&Subscribe::matches(&pairs))
+        .expect("Subscribe frame always serializes.");
+    let client = Client {
+        forward: forward,
+        dead_letters: dead_letters,
+        sequences: Arc::new(Mutex::new(Sequences::default())),
+    };
+
+    websocket::run("coinbase", uri, vec![subscribe], stale_after, client, shutdown)
+}
@@ -0,0 +1,17 @@
+//! Code
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate log;
+extern crate futures;
+extern crate serde;
+extern crate serde_json;
+extern crate rust_decimal;
+extern crate chrono;
+
+extern crate common;
+extern crate fetch_lib;
+
+mod payload;
+mod sequence;
+mod fetch;
+
+pub use self::fetch::{stream, DEFAULT_STALE_AFTER_SECS};
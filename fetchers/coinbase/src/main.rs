@@ -0,0 +1,60 @@
+//! Program entry
+#[macro_use] extern crate log;
+extern crate futures;
+extern crate tokio;
+extern crate dotenv;
+extern crate env_logger;
+
+extern crate common;
+extern crate fetch_lib;
+
+extern crate coinbase_lib as lib;
+
+use futures::{future, Future, Stream};
+use futures::sync::mpsc;
+
+use fetch_lib::{deadletter, https_client, place, shutdown};
+
+mod config;
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let configuration = config::config_from_environment().expect("Can't load config.");
+    debug!("Configuration: {:?}", &configuration);
+
+    let target = place::Target::new(
+        configuration.collector(),
+        common::exchange::Exchange::Coinbase,
+        configuration.products(),
+    );
+
+    let dead_letters = deadletter::DeadLetters::from_environment("coinbase")
+        .expect("Can't open dead letters.");
+    let client = https_client::produce(1).expect("Can't init TLS.");
+    let (th_place_tx, th_place_rx) = mpsc::unbounded();
+
+    let place_future = place::put_trade_history(
+        client,
+        target,
+        th_place_rx
+            .map(|(ap, item)| (ap, vec![item]))
+            .map_err(|e| error!("Receive failure: {:?}", &e))
+            .inspect(|tuple| trace!("PLACING ITEMS: {:?}", &tuple)),
+    );
+
+    // Once the stream stops the channel closes and placement finishes what it has.
+    tokio::run(future::lazy(move || {
+        tokio::spawn(lib::stream(
+            configuration.ws_uri(),
+            configuration.products(),
+            shutdown::signal(),
+            configuration.stale_after(),
+            th_place_tx,
+            dead_letters,
+        ));
+        place_future
+    }));
+
+    info!("Stopped.");
+}
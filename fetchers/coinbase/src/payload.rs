@@ -0,0 +1,212 @@
+//! Messages on the coinbase websocket feed.
+use std::num;
+
+use rust_decimal::Decimal;
+use chrono::{DateTime, Utc};
+
+use common::{asset, trade};
+
+/// Coinbase names products with a dash. `BTC-USD` for example.
+pub fn product_id(pair: asset::Pair) -> String {
+    format!("{}-{}", pair.left().as_str(), pair.right().as_str())
+}
+
+fn asset_pair_parse(product_id: &str) -> Result<asset::Pair, asset::ParseAssetError> {
+    let mut sides = product_id.splitn(2, '-');
+    let left: asset::Asset = sides.next().unwrap_or("").parse()?;
+    let right: asset::Asset = sides.next().unwrap_or("").parse()?;
+    Ok(asset::Pair::new(left, right))
+}
+
+/// Coinbase order IDs are UUIDs which don't fit the `i64` order IDs elsewhere. The leading
+/// 64 bits are kept. Plenty to tell orders apart and to link trades from the same order.
+fn order_id(uuid: &str) -> Result<i64, num::ParseIntError> {
+    let hex: String = uuid.chars().filter(|c| *c != '-').take(16).collect();
+    u64::from_str_radix(&hex, 16).map(|id| id as i64)
+}
+
+/// Sent once to subscribe to channels.
+#[derive(Debug, Clone, Serialize)]
+pub struct Subscribe {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    product_ids: Vec<String>,
+    channels: Vec<&'static str>,
+}
+
+impl Subscribe {
+    /// Subscribe to the matches of the `pairs` along with heartbeats for them.
+    pub fn matches(pairs: &[asset::Pair]) -> Self {
+        Subscribe {
+            kind: "subscribe",
+            product_ids: pairs.iter().map(|pair| product_id(*pair)).collect(),
+            channels: vec!["matches", "heartbeat"],
+        }
+    }
+}
+
+/// A trade. The `side` is that of the maker order.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Match {
+    trade_id: u64,
+    sequence: u64,
+    maker_order_id: String,
+    taker_order_id: String,
+    time: DateTime<Utc>,
+    product_id: String,
+    size: Decimal,
+    price: Decimal,
+    side: String,
+}
+
+impl Match {
+    pub fn trade_id(&self) -> u64 {
+        self.trade_id
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn asset_pair(&self) -> Result<asset::Pair, asset::ParseAssetError> {
+        asset_pair_parse(&self.product_id)
+    }
+
+    /// Convert into the common format. Fails if the side or order IDs are nonsense.
+    pub fn as_trade_history_item(&self) -> Result<trade::TradeHistoryItem, String> {
+        let maker = order_id(&self.maker_order_id).map_err(|e| e.to_string())?;
+        let taker = order_id(&self.taker_order_id).map_err(|e| e.to_string())?;
+
+        // A buying maker is the same as binance's buyer being the market maker.
+        let (market, buy_order_id, sell_order_id) = match self.side.as_str() {
+            "buy" => (trade::Market::Taker, maker, taker),
+            "sell" => (trade::Market::Maker, taker, maker),
+            side => return Err(format!("Invalid side: {}", side)),
+        };
+
+        Ok(trade::TradeHistoryItem::new(
+            self.time,
+            self.size,
+            self.price,
+            market,
+            None,
+            Some(self.trade_id as i64),
+            Some(buy_order_id),
+            Some(sell_order_id),
+            Some(self.time),
+        ))
+    }
+}
+
+/// Everything the feed sends us on the channels we subscribe to.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Feed {
+    /// Confirms what we are subscribed to.
+    Subscriptions,
+
+    /// Sent every second for each product. Carries the last trade ID so missed trades show
+    /// up even when nothing else is happening.
+    Heartbeat {
+        sequence: u64,
+        last_trade_id: u64,
+        product_id: String,
+        time: DateTime<Utc>,
+    },
+
+    /// The most recent trade. Sent straight after subscribing.
+    LastMatch(Match),
+    Match(Match),
+    Error {
+        message: String,
+        #[serde(default)]
+        reason: Option<String>,
+    },
+}
+
+impl Feed {
+    pub fn asset_pair(&self) -> Option<Result<asset::Pair, asset::ParseAssetError>> {
+        match self {
+            Feed::Heartbeat { product_id, .. } => Some(asset_pair_parse(product_id)),
+            Feed::LastMatch(m) | Feed::Match(m) => Some(m.asset_pair()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+    use chrono::TimeZone;
+
+    #[test]
+    fn deserialize_match() {
+        let feed: Feed = serde_json::from_str(include_str!("../fixtures/match.json")).unwrap();
+        let m = match feed {
+            Feed::Match(m) => m,
+            other => panic!("Expected a match. Got {:?}", other),
+        };
+
+        assert_eq!(m.trade_id(), 52768391);
+        assert_eq!(m.sequence(), 7114496520);
+        assert_eq!(m.asset_pair().unwrap(), asset::BTC_USD);
+
+        let item = m.as_trade_history_item().unwrap();
+        assert_eq!(item.timestamp(), Utc.ymd(2018, 10, 22).and_hms_milli(9, 15, 44, 562));
+        assert_eq!(item.size(), "0.25".parse().unwrap());
+        assert_eq!(item.price(), "6458.5".parse().unwrap());
+        assert_eq!(item.market(), trade::Market::Taker);
+        assert_eq!(item.match_id(), Some(52768391));
+        assert_eq!(item.buy_order_id(), Some(0x5c2a7b9e1f3d4c8a));
+        assert_eq!(item.sell_order_id(), Some(0xe8d4f1a23b6c4d7e_u64 as i64));
+    }
+
+    #[test]
+    fn deserialize_the_rest() {
+        let last: Feed = serde_json::from_str(
+            include_str!("../fixtures/last_match.json")
+        ).unwrap();
+        match last {
+            Feed::LastMatch(ref m) => {
+                let item = m.as_trade_history_item().unwrap();
+                assert_eq!(item.market(), trade::Market::Maker);
+                assert_eq!(item.sell_order_id(), Some(0xb1e6f2a362b54e56_u64 as i64));
+            },
+            other => panic!("Expected a last match. Got {:?}", other),
+        }
+
+        let heartbeat: Feed = serde_json::from_str(
+            include_str!("../fixtures/heartbeat.json")
+        ).unwrap();
+        match heartbeat {
+            Feed::Heartbeat { last_trade_id, sequence, .. } => {
+                assert_eq!(last_trade_id, 52768394);
+                assert_eq!(sequence, 7114496611);
+            },
+            other => panic!("Expected a heartbeat. Got {:?}", other),
+        }
+
+        let subscriptions: Feed = serde_json::from_str(
+            include_str!("../fixtures/subscriptions.json")
+        ).unwrap();
+        assert_eq!(subscriptions, Feed::Subscriptions);
+
+        let error: Feed = serde_json::from_str(include_str!("../fixtures/error.json")).unwrap();
+        assert_eq!(error, Feed::Error {
+            message: "Failed to subscribe".to_owned(),
+            reason: Some("BTC-JPY is not a valid product".to_owned()),
+        });
+    }
+
+    #[test]
+    fn subscribe_frame() {
+        let subscribe = Subscribe::matches(&[asset::BTC_USD, asset::ETH_BTC]);
+        let frame = serde_json::to_string(&subscribe).unwrap();
+        assert_eq!(
+            frame,
+            r#"{"type":"subscribe","product_ids":["BTC-USD","ETH-BTC"],"channels":["matches","heartbeat"]}"#,
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Keep track of where each product is up to on the feed.
+//!
+//! Every message for a product carries a `sequence`. It only ever increases, so a match at
+//! or below the last one seen is a replay and dropped. The matches channel only sees some
+//! of the product's messages though, so the sequence skips numbers even when nothing has
+//! been missed. Missed trades are told by the `trade_id` skipping ahead instead. Those go
+//! up by one for each trade of the product. The heartbeat carries the last trade ID which
+//! catches trades missed whilst the product is otherwise quiet.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::asset;
+
+/// Running count of trades missed on the feed since the process started.
+static MISSED_TRADES: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of trades missed so far. They aren't fetched again here. The trade history gap
+/// detector lists them as skipped match IDs for a backfill to fill in.
+pub fn missed_trade_count() -> usize {
+    MISSED_TRADES.load(Ordering::Relaxed)
+}
+
+/// What to make of a match.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Observed {
+    /// Next in line.
+    Fresh,
+
+    /// Fresh but trades were missed before it.
+    Gap { missed: u64 },
+
+    /// Already seen or older than what has been seen.
+    Stale,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Position {
+    sequence: u64,
+    trade_id: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Sequences {
+    positions: HashMap<asset::Pair, Position>,
+}
+
+impl Sequences {
+    /// Record a match. The first one seen for a product sets the starting point.
+    pub fn observe_match(
+        &mut self, pair: asset::Pair, sequence: u64, trade_id: u64,
+    ) -> Observed {
+        let next = Position { sequence, trade_id };
+        let observed = match self.positions.get(&pair) {
+            None => Observed::Fresh,
+            Some(last) if sequence <= last.sequence || trade_id <= last.trade_id => {
+                return Observed::Stale;
+            },
+            Some(last) if trade_id > last.trade_id + 1 => {
+                let missed = trade_id - last.trade_id - 1;
+                MISSED_TRADES.fetch_add(missed as usize, Ordering::Relaxed);
+                Observed::Gap { missed }
+            },
+            Some(_) => Observed::Fresh,
+        };
+
+        self.positions.insert(pair, next);
+        observed
+    }
+
+    /// Record a heartbeat. Returns how many trades were missed if any.
+    pub fn observe_heartbeat(
+        &mut self, pair: asset::Pair, sequence: u64, last_trade_id: u64,
+    ) -> Option<u64> {
+        let last = self.positions.get_mut(&pair)?;
+        if sequence <= last.sequence || last_trade_id <= last.trade_id {
+            return None;
+        }
+
+        let missed = last_trade_id - last.trade_id;
+        MISSED_TRADES.fetch_add(missed as usize, Ordering::Relaxed);
+        last.sequence = sequence;
+        last.trade_id = last_trade_id;
+        Some(missed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_in_order_replayed_and_skipping() {
+        let mut sequences = Sequences::default();
+
+        assert_eq!(sequences.observe_match(asset::BTC_USD, 100, 10), Observed::Fresh);
+        assert_eq!(sequences.observe_match(asset::BTC_USD, 170, 11), Observed::Fresh);
+        assert_eq!(sequences.observe_match(asset::BTC_USD, 170, 11), Observed::Stale);
+        assert_eq!(sequences.observe_match(asset::BTC_USD, 150, 12), Observed::Stale);
+        assert_eq!(
+            sequences.observe_match(asset::BTC_USD, 200, 15),
+            Observed::Gap { missed: 3 },
+        );
+        assert!(missed_trade_count() >= 3);
+        assert_eq!(sequences.observe_match(asset::ETH_USD, 5, 1), Observed::Fresh);
+    }
+
+    #[test]
+    fn heartbeat_catches_quiet_gaps() {
+        let mut sequences = Sequences::default();
+
+        assert_eq!(sequences.observe_heartbeat(asset::BTC_USD, 90, 9), None);
+        sequences.observe_match(asset::BTC_USD, 100, 10);
+        assert_eq!(sequences.observe_heartbeat(asset::BTC_USD, 110, 10), None);
+        assert_eq!(sequences.observe_heartbeat(asset::BTC_USD, 120, 12), Some(2));
+
+        // The trades the heartbeat told of aren't counted twice.
+        assert_eq!(sequences.observe_match(asset::BTC_USD, 130, 13), Observed::Fresh);
+    }
+}
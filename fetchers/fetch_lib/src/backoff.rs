@@ -0,0 +1,67 @@
+//! Exponential backoff.
+//!
+//! Used wherever a fetcher needs to slow down after a failure. Be it a throttling response
+//! from an exchange or a connection that won't come up.
+use std::cmp;
+use std::time::Duration;
+
+/// Delay that doubles on every failure up to a ceiling. A reset brings it back down to the
+/// floor.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    floor: Duration,
+    ceiling: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(floor: Duration, ceiling: Duration) -> Self {
+        Backoff {
+            floor: floor,
+            ceiling: cmp::max(floor, ceiling),
+            current: floor,
+        }
+    }
+
+    /// The delay to wait before the next attempt.
+    pub fn delay(&self) -> Duration {
+        self.current
+    }
+
+    /// Double the delay without going over the ceiling.
+    pub fn increase(&mut self) {
+        self.current = cmp::min(self.current * 2, self.ceiling);
+    }
+
+    /// Return the delay to wait before the next attempt and then increase it in case that
+    /// attempt fails too.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.increase();
+        delay
+    }
+
+    /// Back to the floor. Call after a success.
+    pub fn reset(&mut self) {
+        self.current = self.floor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_ceiling() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(2));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(4));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+
+        backoff.reset();
+        assert_eq!(backoff.delay(), Duration::from_secs(1));
+    }
+}
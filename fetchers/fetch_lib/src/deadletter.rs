@@ -0,0 +1,336 @@
+//! Dead letters. Payloads from an exchange that couldn't be parsed.
+//!
+//! Each one is written as a line of JSON holding the raw payload, the error and when it
+//! happened. The files are rotated by size so that a broken exchange API can't fill the
+//! disk. When the share of payloads that fail within a window goes over a threshold an
+//! alarm is logged since it most likely means the exchange changed its API.
+use std::{env, error, fmt, fs, io};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde_json;
+
+use common::errors::ConfigError;
+
+static DEAD_LETTER_DIR: &str = "DEAD_LETTER_DIR";
+static DEAD_LETTER_ALARM: &str = "DEAD_LETTER_ALARM_PERCENT";
+
+/// Where dead letters go when `DEAD_LETTER_DIR` isn't set.
+pub static DEFAULT_DIR: &str = "dead_letters";
+
+/// Percentage of failed payloads that sets off the alarm when `DEAD_LETTER_ALARM_PERCENT`
+/// isn't set.
+pub const DEFAULT_ALARM_PERCENT: u32 = 5;
+
+/// Size a file may grow to before it is rotated.
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Rotated files kept on top of the one being written to.
+pub const DEFAULT_MAX_FILES: usize = 5;
+
+/// Window the failure rate is measured over.
+pub const DEFAULT_ALARM_WINDOW_SECS: u64 = 300;
+
+/// Failures needed within the window before the alarm can go off.
+pub const DEFAULT_ALARM_MIN_FAILURES: u32 = 3;
+
+/// Dead letters couldn't be set up from the environment.
+#[derive(Debug)]
+pub enum SetupError {
+    Config(ConfigError),
+    Io(io::Error),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SetupError::Config(err) => write!(f, "Dead letter config: {}", &err),
+            SetupError::Io(err) => write!(f, "Dead letter file: {}", &err),
+        }
+    }
+}
+
+impl error::Error for SetupError {
+    fn description(&self) -> &str {
+        "Error setting up dead letters"
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            SetupError::Config(ref err) => Some(err),
+            SetupError::Io(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<ConfigError> for SetupError {
+    fn from(err: ConfigError) -> Self {
+        SetupError::Config(err)
+    }
+}
+
+impl From<io::Error> for SetupError {
+    fn from(err: io::Error) -> Self {
+        SetupError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DeadLetter<'a> {
+    timestamp: DateTime<Utc>,
+    source: &'a str,
+    error: &'a str,
+    payload: &'a str,
+}
+
+/// Tracks the share of payloads that failed to parse in the current window.
+#[derive(Debug, Clone)]
+pub struct FailureRate {
+    window: Duration,
+    threshold: f64,
+    min_failures: u32,
+    started: Instant,
+    total: u32,
+    failures: u32,
+    raised: bool,
+}
+
+impl FailureRate {
+    /// Alarm when more than `threshold` (0.0 to 1.0) of the payloads in a `window` fail and
+    /// there are at least `min_failures` of them. The minimum stops a single failure on a
+    /// quiet market from setting it off.
+    pub fn new(window: Duration, threshold: f64, min_failures: u32) -> Self {
+        FailureRate {
+            window,
+            threshold,
+            min_failures,
+            started: Instant::now(),
+            total: 0,
+            failures: 0,
+            raised: false,
+        }
+    }
+
+    /// Count a payload. Returns the failure rate if this payload set the alarm off. The
+    /// alarm goes off at most once a window.
+    pub fn observe(&mut self, failed: bool, now: Instant) -> Option<f64> {
+        if now.duration_since(self.started) >= self.window {
+            self.started = now;
+            self.total = 0;
+            self.failures = 0;
+            self.raised = false;
+        }
+
+        self.total += 1;
+        if failed {
+            self.failures += 1;
+        }
+
+        let rate = f64::from(self.failures) / f64::from(self.total);
+        if !self.raised && self.failures >= self.min_failures && rate > self.threshold {
+            self.raised = true;
+            Some(rate)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Sink {
+    source: String,
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Option<File>,
+    written: u64,
+    rate: FailureRate,
+}
+
+impl Sink {
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    /// Shift every file along by one. The oldest is removed.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        self.written = 0;
+
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        let oldest = self.rotated(self.max_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+    }
+
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+
+        if self.file.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            self.written = file.metadata()?.len();
+            self.file = Some(file);
+        }
+
+        let file = self.file.as_mut().expect("File opened above.");
+        file.write_all(line)?;
+        file.flush()?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Where unparseable payloads go. Cheap to clone and can be shared between threads.
+#[derive(Debug, Clone)]
+pub struct DeadLetters {
+    sink: Arc<Mutex<Sink>>,
+}
+
+impl DeadLetters {
+    /// Dead letters from `source` go into `<dir>/<source>.ndjson`. The directory is created
+    /// if it doesn't exist.
+    pub fn new<P: AsRef<Path>>(
+        dir: P, source: &str, max_bytes: u64, max_files: usize, rate: FailureRate,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        let path = dir.as_ref().join(format!("{}.ndjson", source));
+
+        let sink = Sink {
+            source: source.to_owned(),
+            path,
+            max_bytes,
+            max_files,
+            file: None,
+            written: 0,
+            rate,
+        };
+
+        Ok(DeadLetters {
+            sink: Arc::new(Mutex::new(sink)),
+        })
+    }
+
+    /// Dead letters from `source` as configured by the environment. They go into
+    /// `DEAD_LETTER_DIR` and the alarm goes off when more than `DEAD_LETTER_ALARM_PERCENT`
+    /// of the payloads fail. Everything else is left at the defaults.
+    pub fn from_environment(source: &str) -> Result<Self, SetupError> {
+        let dir = env::var(DEAD_LETTER_DIR).unwrap_or_else(|_| DEFAULT_DIR.to_owned());
+
+        let alarm_percent: u32 = match env::var(DEAD_LETTER_ALARM) {
+            Ok(percent) => percent
+                .parse()
+                .map_err(|e| ConfigError::from((DEAD_LETTER_ALARM, e)))?,
+            Err(_) => DEFAULT_ALARM_PERCENT,
+        };
+
+        let rate = FailureRate::new(
+            Duration::from_secs(DEFAULT_ALARM_WINDOW_SECS),
+            f64::from(alarm_percent) / 100.0,
+            DEFAULT_ALARM_MIN_FAILURES,
+        );
+        info!("{} dead letters go to {}. Alarm at {}%.", source, &dir, alarm_percent);
+
+        let letters = DeadLetters::new(
+            dir, source, DEFAULT_MAX_FILE_BYTES, DEFAULT_MAX_FILES, rate,
+        )?;
+        Ok(letters)
+    }
+
+    /// Count a payload that parsed fine.
+    pub fn success(&self) {
+        let mut sink = self.sink.lock().expect("Dead letter sink poisoned.");
+        sink.rate.observe(false, Instant::now());
+    }
+
+    /// Quarantine a payload that failed to parse. Failing to write it is logged and
+    /// otherwise ignored. It mustn't stop the fetcher.
+    pub fn record(&self, payload: &str, error: &str) {
+        let mut sink = self.sink.lock().expect("Dead letter sink poisoned.");
+
+        if let Some(rate) = sink.rate.observe(true, Instant::now()) {
+            error!(
+                "ALARM: {:.1}% of {} payloads are failing to parse. Has the API changed?",
+                rate * 100.0,
+                &sink.source,
+            );
+        }
+
+        let letter = DeadLetter {
+            timestamp: Utc::now(),
+            source: sink.source.as_str(),
+            error,
+            payload,
+        };
+        let mut line = serde_json::to_vec(&letter).expect("Dead letter always serializes.");
+        line.push(b'\n');
+
+        if let Err(e) = sink.write(&line) {
+            error!("Can't write dead letter to {}: {}", sink.path.display(), &e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    #[test]
+    fn alarm_once_per_window() {
+        let start = Instant::now();
+        let mut rate = FailureRate::new(Duration::from_secs(60), 0.5, 2);
+
+        assert_eq!(rate.observe(false, start), None);
+        assert_eq!(rate.observe(true, start), None);
+        assert_eq!(rate.observe(true, start), Some(2.0 / 3.0));
+        assert_eq!(rate.observe(true, start), None);
+
+        let later = start + Duration::from_secs(61);
+        assert_eq!(rate.observe(true, later), None);
+        assert_eq!(rate.observe(true, later), Some(1.0));
+    }
+
+    #[test]
+    fn rotates_files() {
+        let dir = env::temp_dir().join(format!("sammy_dead_letters_{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let rate = FailureRate::new(Duration::from_secs(60), 1.0, 1);
+        let letters = DeadLetters::new(&dir, "test", 200, 2, rate).unwrap();
+
+        for _ in 0..10 {
+            letters.record(r##"{"not":"valid"}"##, "missing field `e`");
+        }
+
+        assert!(dir.join("test.ndjson").exists());
+        assert!(dir.join("test.ndjson.1").exists());
+        assert!(dir.join("test.ndjson.2").exists());
+        assert!(!dir.join("test.ndjson.3").exists());
+
+        let contents = fs::read_to_string(dir.join("test.ndjson")).unwrap();
+        let line = contents.lines().next().unwrap();
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(value["error"], "missing field `e`");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
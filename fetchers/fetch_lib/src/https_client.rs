@@ -1,9 +1,13 @@
 //! Single HTTPS client.
 use std::{fmt, error, convert, string};
 
+use serde::de::DeserializeOwned;
 use serde_json;
 use hyper::{self, Client, Body, client::HttpConnector};
 use hyper_tls::{HttpsConnector, Error};
+use tokio_timer;
+
+use common::asset;
 
 pub type HttpsClient = Client<HttpsConnector<HttpConnector>>;
 
@@ -18,7 +22,21 @@ pub enum FetchError {
     Status(u16),
     Utf8(string::FromUtf8Error),
     SerdeJson(serde_json::Error),
+
+    /// The body didn't deserialize. Holds the body so it can be quarantined.
+    Payload(serde_json::Error, String),
+    Timer(tokio_timer::Error),
     InternalChannel,
+
+    /// The exchange doesn't trade the asset pair so there is nothing to fetch.
+    UnsupportedPair(asset::Pair),
+}
+
+/// Deserialize a response body. On failure the body is kept in the error.
+pub fn parse_json<T: DeserializeOwned>(body: &[u8]) -> Result<T, FetchError> {
+    serde_json::from_slice(body).map_err(|e| {
+        FetchError::Payload(e, String::from_utf8_lossy(body).into_owned())
+    })
 }
 
 impl fmt::Display for FetchError {
@@ -28,7 +46,12 @@ impl fmt::Display for FetchError {
             FetchError::Status(err) => write!(f, "HTTP status code: {}", &err),
             FetchError::Utf8(err) => write!(f, "Response body invalid UTF8: {}", &err),
             FetchError::SerdeJson(err) => write!(f, "JSON serde error: {}", &err),
+            FetchError::Payload(err, _) => write!(f, "Invalid payload: {}", &err),
+            FetchError::Timer(err) => write!(f, "Timer failure: {}", &err),
             FetchError::InternalChannel => write!(f, "Internal channel failure"),
+            FetchError::UnsupportedPair(pair) => {
+                write!(f, "Unsupported asset pair: {}", &pair)
+            },
         }
     }
 }
@@ -44,7 +67,10 @@ impl error::Error for FetchError {
             FetchError::Status(_) => None,
             FetchError::Utf8(ref err) => Some(err),
             FetchError::SerdeJson(ref err) => Some(err),
+            FetchError::Payload(ref err, _) => Some(err),
+            FetchError::Timer(ref err) => Some(err),
             FetchError::InternalChannel => None,
+            FetchError::UnsupportedPair(_) => None,
         }
     }
 }
@@ -73,5 +99,11 @@ impl convert::From<serde_json::Error> for FetchError {
     }
 }
 
+impl convert::From<tokio_timer::Error> for FetchError {
+    fn from(e: tokio_timer::Error) -> Self {
+        FetchError::Timer(e)
+    }
+}
+
 //#[derive(Debug)]
 //pub enum PlaceError;
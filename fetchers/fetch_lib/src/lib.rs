@@ -1,14 +1,24 @@
 //! Common code for all the fetchers.
 #[macro_use] extern crate log;
+#[macro_use] extern crate serde_derive;
 extern crate futures;
 extern crate hyper;
 extern crate hyper_tls;
+extern crate serde;
 extern crate serde_json;
 extern crate tokio;
 extern crate tokio_timer;
+extern crate tokio_signal;
+extern crate tokio_tungstenite;
+extern crate url;
+extern crate chrono;
 
 extern crate common;
 
+pub mod backoff;
+pub mod deadletter;
 pub mod https_client;
 pub mod place;
+pub mod shutdown;
+pub mod websocket;
 mod retry;
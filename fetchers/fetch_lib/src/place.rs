@@ -6,18 +6,20 @@ use std::str::FromStr;
 
 use futures::{Future, Stream};
 use hyper::{Uri, Request};
+use serde::Serialize;
 use serde_json;
 use tokio;
 
-use common::{trade, exchange, asset};
+use common::{trade, exchange, asset, candle, book};
 
 use https_client::HttpsClient;
-use retry::PutRetry;
+use retry::{PutRetry, retryable};
 
 /// Contains placement URI's for the putting information on the `collector` API. This struct
 /// is for a single `Exchange`. Allows 
 #[derive(Debug, Clone)]
 pub struct Target {
+    base: String,
     exchange: exchange::Exchange,
     trade_history_uri: HashMap<asset::Pair, Uri>,
 }
@@ -37,14 +39,41 @@ impl Target {
             });
 
         Target {
+            base: base.to_owned(),
             exchange: exchange,
             trade_history_uri: HashMap::from_iter(trade_history_insert),
         }
     }
 
-     /// Return the PUT URI for the asset pair.
+    /// Return the PUT URI for the asset pair. Pairs not known up front, such as those
+    /// subscribed to at runtime, have theirs built on demand.
     pub fn trade_history_uri(&self, ap: &asset::Pair) -> Option<Uri> {
-        self.trade_history_uri.get(&ap).map(|u| u.clone())
+        self.trade_history_uri
+            .get(&ap)
+            .map(|u| u.clone())
+            .or_else(|| self.uri("trade_history", ap))
+    }
+
+    /// Return the PUT URI for candles of the asset pair.
+    pub fn candle_uri(&self, ap: &asset::Pair) -> Option<Uri> {
+        self.uri("candles", ap)
+    }
+
+    /// Return the PUT URI for aggregate trades of the asset pair.
+    pub fn aggregate_trade_uri(&self, ap: &asset::Pair) -> Option<Uri> {
+        self.uri("aggregate_trades", ap)
+    }
+
+    /// Return the PUT URI for order book events of the asset pair.
+    pub fn order_book_uri(&self, ap: &asset::Pair) -> Option<Uri> {
+        self.uri("order_books", ap)
+    }
+
+    fn uri(&self, resource: &str, ap: &asset::Pair) -> Option<Uri> {
+        let target = format!(
+            "{}/{}/{}/{}/{}", &self.base, resource, &ap.left(), &ap.right(), &self.exchange
+        );
+        Uri::from_str(target.as_str()).ok()
     }
 }
 
@@ -57,6 +86,9 @@ impl Target {
 /// each attempt. The retries are there to deal with minor transmission failures that
 /// sometimes happen as is not a full featured guaranteed deliver system. If all retry
 /// attempts fail, the data is dropped.
+///
+/// A response other than 2xx counts as a failure, except a 422. The collector rejected
+/// those items and would reject them again.
 /// 
 /// ## TODO
 /// 1. Make the retries and delay configurable.
@@ -68,10 +100,49 @@ pub fn put_trade_history(
     client: HttpsClient,
     target: Target,
     stream: impl Stream<Item = (asset::Pair, Vec<trade::TradeHistoryItem>), Error = ()>
-) -> impl Future<Item = (), Error = ()> {    
+) -> impl Future<Item = (), Error = ()> {
+    put(client, move |ap| target.trade_history_uri(ap), stream)
+}
+
+/// Places candles computed by the exchange. Retries the same as `put_trade_history`.
+pub fn put_candles(
+    client: HttpsClient,
+    target: Target,
+    stream: impl Stream<Item = (asset::Pair, Vec<candle::Candle>), Error = ()>
+) -> impl Future<Item = (), Error = ()> {
+    put(client, move |ap| target.candle_uri(ap), stream)
+}
+
+/// Places aggregate trades. Retries the same as `put_trade_history`.
+pub fn put_aggregate_trades(
+    client: HttpsClient,
+    target: Target,
+    stream: impl Stream<Item = (asset::Pair, Vec<trade::AggregateTrade>), Error = ()>
+) -> impl Future<Item = (), Error = ()> {
+    put(client, move |ap| target.aggregate_trade_uri(ap), stream)
+}
+
+/// Places synchronised order book events. Retries the same as `put_trade_history`.
+pub fn put_book_events(
+    client: HttpsClient,
+    target: Target,
+    stream: impl Stream<Item = (asset::Pair, Vec<book::BookEvent>), Error = ()>
+) -> impl Future<Item = (), Error = ()> {
+    put(client, move |ap| target.order_book_uri(ap), stream)
+}
+
+/// PUT every batch of items in the stream as JSON onto the URI for its asset pair.
+fn put<T, F>(
+    client: HttpsClient,
+    uri: F,
+    stream: impl Stream<Item = (asset::Pair, Vec<T>), Error = ()>
+) -> impl Future<Item = (), Error = ()>
+where T: Serialize,
+      F: Fn(&asset::Pair) -> Option<Uri>,
+{
     stream
         .and_then(move |(asset_pair, items)| {
-            let dest = target.trade_history_uri(&asset_pair).expect("Missing asset pair!");
+            let dest = uri(&asset_pair).expect("Missing asset pair!");
             let json = serde_json::to_string(&items).unwrap();
             let r_client = client.clone();
             let req = Request::put(dest.clone())
@@ -79,17 +150,30 @@ pub fn put_trade_history(
                 .unwrap();
             client
                 .request(req)
-                .map_err(move |e| {
-                    warn!("Failed to place history: {}, Retrying.", &e);
+                .then(move |result| {
+                    let failure = match result {
+                        Ok(rsp) => if retryable(rsp.status()) {
+                            format!("Responded with {}", &rsp.status())
+                        } else {
+                            return Ok(rsp);
+                        },
+                        Err(e) => e.to_string(),
+                    };
+                    warn!("Failed to place history: {}, Retrying.", &failure);
                     let retry = PutRetry::new(
                         dest, json, r_client, Duration::from_secs(5), 3,
                     );
                     tokio::spawn(retry);
+                    Err(())
                 })
         })
-        .then(|result| match result {            
+        .then(|result| match result {
             Ok(rsp) => {
-                trace!("Placement success: {}", &rsp.status());
+                if rsp.status().is_success() {
+                    trace!("Placement success: {}", &rsp.status());
+                } else {
+                    warn!("Placement rejected: {}", &rsp.status());
+                }
                 Ok(())
             },
             Err(()) => {
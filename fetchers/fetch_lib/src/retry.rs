@@ -11,7 +11,7 @@ use std::time::{Instant, Duration};
 use std::fmt;
 
 use futures::{Poll, Async, Future};
-use hyper::{self, Uri, Request};
+use hyper::{self, Uri, Request, StatusCode};
 use tokio;
 use tokio_timer::{self, Delay};
 
@@ -19,7 +19,8 @@ use https_client::HttpsClient;
 
 enum RetryError {
     Timer(tokio_timer::Error),
-    Hyper(hyper::Error),    
+    Hyper(hyper::Error),
+    Status(StatusCode),
 }
 
 impl fmt::Display for RetryError {
@@ -27,10 +28,17 @@ impl fmt::Display for RetryError {
         match self {
             RetryError::Timer(ref e) => write!(f, "{}", e),
             RetryError::Hyper(ref e) => write!(f, "{}", e),
+            RetryError::Status(ref status) => write!(f, "Responded with {}", status),
         }
     }
 }
 
+/// Whether a placement that got the response `status` is worth trying again. A 422 means
+/// the collector turned the items away and will do so every time.
+pub fn retryable(status: StatusCode) -> bool {
+    !status.is_success() && status != StatusCode::UNPROCESSABLE_ENTITY
+}
+
 #[derive(Debug, Clone)]
 pub struct PutRetry {
     destination: Option<Uri>,
@@ -110,9 +118,18 @@ impl Future for PutRetry {
                     .request(req)
                     .map_err(|e| RetryError::Hyper(e))
             })
+            .and_then(|rsp| if retryable(rsp.status()) {
+                Err(RetryError::Status(rsp.status()))
+            } else {
+                Ok(rsp)
+            })
             .then(move |result| match result {
                 Ok(rsp) => {
-                    trace!("Retry placement success: {}", &rsp.status());
+                    if rsp.status().is_success() {
+                        trace!("Retry placement success: {}", &rsp.status());
+                    } else {
+                        warn!("Retry placement rejected: {}", &rsp.status());
+                    }
                     Ok(())
                 },
                 Err(e) => {
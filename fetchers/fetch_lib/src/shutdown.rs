@@ -0,0 +1,26 @@
+//! Stop on SIGTERM or ctrl-c.
+use futures::{future, Future, Stream};
+use tokio_signal;
+use tokio_signal::unix::{Signal, SIGTERM};
+
+/// Resolves on the first SIGTERM or SIGINT. Create it from within the tokio runtime. A
+/// signal that can't be listened for is logged and never resolves.
+pub fn signal() -> impl Future<Item = (), Error = ()> {
+    let term = Signal::new(SIGTERM)
+        .flatten_stream()
+        .into_future()
+        .map(|_| info!("SIGTERM received."))
+        .map_err(|(e, _)| error!("Can't listen for SIGTERM: {}", &e))
+        .or_else(|()| future::empty());
+
+    let interrupt = tokio_signal::ctrl_c()
+        .flatten_stream()
+        .into_future()
+        .map(|_| info!("SIGINT received."))
+        .map_err(|(e, _)| error!("Can't listen for SIGINT: {}", &e))
+        .or_else(|()| future::empty());
+
+    term.select(interrupt)
+        .map(|_| ())
+        .map_err(|_| ())
+}
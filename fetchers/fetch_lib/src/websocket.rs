@@ -0,0 +1,158 @@
+//! Keep a websocket connection to an exchange feed. Lost connections are reconnected with
+//! a backoff until the shutdown future resolves. A connection that goes silent for too
+//! long is dead and is replaced.
+//!
+//! What arrives is up to the exchange. Each fetcher supplies a `Handler` for its messages
+//! and the frames to subscribe with once connected.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Sink, Stream};
+use futures::stream;
+use futures::future::{self, Either, Loop};
+use tokio::timer::{Delay, Timeout};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::{self, Message};
+use url::Url;
+
+use backoff::Backoff;
+
+const MIN_RECONNECT_DELAY_SECS: u64 = 1;
+const MAX_RECONNECT_DELAY_SECS: u64 = 120;
+
+/// Why a connection ended.
+#[derive(Debug)]
+pub enum End {
+    Closed,
+    Stale(Duration),
+
+    /// The exchange asked for the subscriptions to be made again. Reconnects straight away.
+    Resubscribe(String),
+
+    /// Nothing is receiving forwarded items anymore.
+    Halted,
+    Failed(String),
+}
+
+impl fmt::Display for End {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            End::Closed => write!(f, "Closed by the exchange"),
+            End::Stale(silent) => write!(f, "Silent for {:?}", silent),
+            End::Resubscribe(why) => write!(f, "Resubscribe requested: {}", why),
+            End::Halted => write!(f, "Forward channel closed"),
+            End::Failed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Handles the text frames that arrive on a connection. Cloned for each new connection.
+/// Anything that should outlive a connection has to be shared between the clones.
+pub trait Handler: Clone + Send + 'static {
+    fn on_text(&mut self, json: &str) -> Result<(), End>;
+}
+
+fn on_message<H: Handler>(handler: &mut H, msg: Message) -> Result<(), End> {
+    match msg {
+        Message::Text(json) => handler.on_text(&json),
+        Message::Close(_) => Err(End::Closed),
+        _ => Ok(()),
+    }
+}
+
+/// Connect, subscribe and handle messages until the connection ends. Resolves to why the
+/// connection ended or `None` if it couldn't be made at all.
+fn session<H: Handler>(
+    mut handler: H, url: Url, subscribes: Vec<String>, stale_after: Duration,
+) -> impl Future<Item = Option<End>, Error = ()> {
+    connect_async(url)
+        .map_err(|e| End::Failed(e.to_string()))
+        .and_then(move |(ws, _)| {
+            debug!("Connected. Subscribing.");
+            let frames = subscribes.into_iter().map(Message::Text);
+            ws.send_all(stream::iter_ok::<_, tungstenite::Error>(frames))
+                .map(|(ws, _)| ws)
+                .map_err(|e| End::Failed(e.to_string()))
+        })
+        .then(move |connection| match connection {
+            Ok(ws) => Either::A(
+                Timeout::new(ws, stale_after)
+                    .map_err(move |e| if e.is_elapsed() {
+                        End::Stale(stale_after)
+                    } else {
+                        End::Failed(e.to_string())
+                    })
+                    .for_each(move |msg| on_message(&mut handler, msg))
+                    .then(|result| {
+                        let end = match result {
+                            Ok(()) => End::Closed,
+                            Err(end) => end,
+                        };
+                        warn!("Connection ended: {}", &end);
+                        Ok(Some(end))
+                    })
+            ),
+            Err(e) => {
+                error!("Can't connect: {}", &e);
+                Either::B(future::ok(None))
+            },
+        })
+}
+
+/// Returns the future that keeps `handler` fed from the websocket at `uri`. The
+/// `subscribes` frames are sent on every new connection. Must be run on the tokio runtime.
+///
+/// Resolves once `shutdown` does. The `handler` is dropped with it.
+pub fn run<H, F>(
+    exchange: &'static str,
+    uri: &str,
+    subscribes: Vec<String>,
+    stale_after: Duration,
+    handler: H,
+    shutdown: F,
+) -> impl Future<Item = (), Error = ()>
+where H: Handler,
+      F: Future<Item = (), Error = ()> + Send + 'static
+{
+    let url = Url::parse(uri)
+        .unwrap_or_else(|e| panic!("Invalid {} websocket URI: {}", exchange, e));
+    let backoff = Backoff::new(
+        Duration::from_secs(MIN_RECONNECT_DELAY_SECS),
+        Duration::from_secs(MAX_RECONNECT_DELAY_SECS),
+    );
+    let shutdown = shutdown.shared();
+
+    future::loop_fn(backoff, move |mut backoff| {
+        let stopping = shutdown.clone().then(|_| Ok::<_, ()>(None));
+        let stopped = shutdown.clone().then(|_| {
+            info!("Shut down whilst waiting to reconnect.");
+            Ok::<_, ()>(Loop::Break(()))
+        });
+        session(handler.clone(), url.clone(), subscribes.clone(), stale_after)
+            .map(Some)
+            .select(stopping)
+            .map_err(|_| ())
+            .and_then(move |(ended, _)| match ended {
+                None => {
+                    info!("Shutting down {} connection.", exchange);
+                    Either::A(future::ok(Loop::Break(())))
+                },
+                Some(Some(End::Resubscribe(_))) => {
+                    backoff.reset();
+                    info!("Reconnecting to resubscribe.");
+                    Either::A(future::ok(Loop::Continue(backoff)))
+                },
+                Some(end) => {
+                    // Only a connection that was made resets the backoff.
+                    if end.is_some() {
+                        backoff.reset();
+                    }
+                    let delay = backoff.next_delay();
+                    warn!("Reconnecting in {:?}.", &delay);
+                    let waited = Delay::new(Instant::now() + delay)
+                        .then(move |_| Ok(Loop::Continue(backoff)));
+                    Either::B(waited.select(stopped).map(|(next, _)| next).map_err(|_| ()))
+                },
+            })
+    })
+}
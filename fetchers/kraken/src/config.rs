@@ -1,16 +1,27 @@
 //! Configuration sourcing
-use std::{env, error, fmt, convert, str};
+use std::{env, error, fmt, convert, str, num};
+use std::time::Duration;
 
 use common::asset;
 
+use lib::KrakenFetchTargets;
+use lib::targets::DEFAULT_BASE_URI;
+
 static TRANSLATOR: &str = "SAMMY_TRANSLATOR";
 static ASSET_PAIRS: &str = "KRAKEN_ASSET_PAIRS";
+static BASE_URI: &str = "KRAKEN_BASE_URI";
 static MODE: &str = "KRAKEN_FETCH_MODE";
+static BACKFILL_FROM: &str = "KRAKEN_BACKFILL_FROM";
+static BACKFILL_PAGE_DELAY: &str = "KRAKEN_BACKFILL_PAGE_DELAY_SECS";
+
+/// Kraken allows roughly one public call every couple of seconds before throttling.
+const DEFAULT_BACKFILL_PAGE_DELAY_SECS: u64 = 3;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FetchMode {
     TradeHistory,
     OrderBook,
+    Backfill,
 }
 
 impl str::FromStr for FetchMode {
@@ -20,6 +31,7 @@ impl str::FromStr for FetchMode {
         match s.to_uppercase().as_str() {
             "TRADE" | "TRADE_HISTORY" | "TRADEHISTORY" => Ok(FetchMode::TradeHistory),
             "ORDER" | "BOOK" | "ORDER_BOOK" | "ORDERBOOK" => Ok(FetchMode::OrderBook),
+            "BACKFILL" => Ok(FetchMode::Backfill),
             _ => Err(ConfigError::InvalidMode(s.to_owned())),
         }
     }
@@ -30,6 +42,9 @@ pub struct Configuration {
     asset_pairs: Vec<asset::Pair>,
     fetch_mode: FetchMode,
     translator: String,
+    targets: KrakenFetchTargets,
+    backfill_from: Option<u64>,
+    backfill_page_delay: Duration,
 }
 
 impl Configuration {
@@ -44,23 +59,66 @@ impl Configuration {
     pub fn translator(&self) -> &str {
         self.translator.as_str()
     }
+
+    /// Where the Kraken API is. Point the base at a mock exchange to run offline.
+    pub fn targets(&self) -> KrakenFetchTargets {
+        self.targets.clone()
+    }
+
+    /// Unix timestamp in seconds to start backfilling from. Always set in backfill mode.
+    pub fn backfill_from(&self) -> Option<u64> {
+        self.backfill_from
+    }
+
+    pub fn backfill_page_delay(&self) -> Duration {
+        self.backfill_page_delay
+    }
 }
 
 pub fn config_from_environment() -> Result<Configuration, ConfigError> {
     let asset_pairs = env::var(ASSET_PAIRS).map_err(|e| (ASSET_PAIRS, e))?;
     let fetch_mode = env::var(MODE).map_err(|e| (MODE, e))?;
     let translator = env::var(TRANSLATOR).map_err(|e| (TRANSLATOR, e))?;
+    let base_uri = env::var(BASE_URI).unwrap_or(DEFAULT_BASE_URI.to_owned());
 
     let asset_pairs: Vec<asset::Pair> = asset_pairs
         .split(':')
         .map(|ap_str| ap_str.parse().expect("Invalid asset pair code."))
         .collect();
+    let untraded = asset_pairs
+        .iter()
+        .cloned()
+        .find(|pair| !KrakenFetchTargets::trades(*pair));
+    if let Some(pair) = untraded {
+        return Err(ConfigError::UnsupportedPair(ASSET_PAIRS.to_owned(), pair));
+    }
+
+    let targets = KrakenFetchTargets::new(&base_uri)
+        .map_err(|e| ConfigError::InvalidUri(BASE_URI.to_owned(), e))?;
+
+    let fetch_mode: FetchMode = fetch_mode.parse()?;
+
+    let backfill_from = match env::var(BACKFILL_FROM) {
+        Ok(from) => Some(from.parse().map_err(|e| (BACKFILL_FROM, e))?),
+        Err(e) => if fetch_mode == FetchMode::Backfill {
+            return Err((BACKFILL_FROM, e).into());
+        } else {
+            None
+        },
+    };
 
+    let backfill_page_delay = match env::var(BACKFILL_PAGE_DELAY) {
+        Ok(delay) => delay.parse().map_err(|e| (BACKFILL_PAGE_DELAY, e))?,
+        Err(_) => DEFAULT_BACKFILL_PAGE_DELAY_SECS,
+    };
 
     Ok(Configuration {
         asset_pairs: asset_pairs,
-        fetch_mode: fetch_mode.parse()?,
+        fetch_mode: fetch_mode,
         translator: translator,
+        targets: targets,
+        backfill_from: backfill_from,
+        backfill_page_delay: Duration::from_secs(backfill_page_delay),
     })
 }
 
@@ -69,6 +127,9 @@ pub enum ConfigError {
     MissingEnv(String, env::VarError),
     InvalidAsset(String, asset::ParseAssetError),
     InvalidMode(String),
+    InvalidInt(String, num::ParseIntError),
+    InvalidUri(String, String),
+    UnsupportedPair(String, asset::Pair),
 }
 
 impl fmt::Display for ConfigError {
@@ -77,6 +138,11 @@ impl fmt::Display for ConfigError {
             ConfigError::MissingEnv(var, err) => write!(f, "Missing {}:{}", &var, &err),
             ConfigError::InvalidAsset(var, err) => write!(f, "Invalid {}:{}", &var, &err),
             ConfigError::InvalidMode(var) => write!(f, "Invalid fetch mode: {}", &var),
+            ConfigError::InvalidInt(var, err) => write!(f, "Invalid {}:{}", &var, &err),
+            ConfigError::InvalidUri(var, err) => write!(f, "Invalid {}:{}", &var, &err),
+            ConfigError::UnsupportedPair(var, pair) => {
+                write!(f, "Kraken doesn't trade {} in {}", &pair, &var)
+            },
         }
     }
 }
@@ -98,3 +164,9 @@ impl<'a> convert::From<(&'a str, asset::ParseAssetError)> for ConfigError {
         ConfigError::InvalidAsset(e_tuple.0.to_owned(), e_tuple.1)
     }
 }
+
+impl<'a> convert::From<(&'a str, num::ParseIntError)> for ConfigError {
+    fn from(e_tuple: (&str, num::ParseIntError)) -> Self {
+        ConfigError::InvalidInt(e_tuple.0.to_owned(), e_tuple.1)
+    }
+}
@@ -1,9 +1,11 @@
 //! Code to convert from internal models to common models.
 use std::ops::Mul;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use num_traits::ToPrimitive;
 use rust_decimal::Decimal;
 use chrono::{NaiveDateTime, DateTime, Utc};
+use serde_json;
 
 use common::{trade, asset};
 
@@ -16,40 +18,61 @@ lazy_static! {
     static ref NANOS_MUL: Decimal =  1_000_000_000_u64.into();
 }
 
-/// Convert the internal kraken trade match history model into the common model. This is
-/// done for transmission.
-pub fn trade_history(
-    history: &TradeHistory
-) -> Result<(asset::Pair, Vec<trade::TradeHistoryItem>), String> {
-    let mut output: Vec<trade::TradeHistoryItem> = Vec::new();
-
-    // Get the asset pair. All the items are of the same asset pair
-    let asset_pair = history.pair();
-    
-    for trade_match in history.items().iter() {
-        // Each trade_match is a vector of six elements.
-        // TODO: Consider... could we define a minimum of five elements and any higher than
-        //       six are ignored?
-        if trade_match.len() != 6 {
-            return Err("Insufficient vector length".to_string())
+/// Kraken trade rows have at least this many elements. Anything past it is extra that we
+/// either know about (the trade ID) or ignore.
+const MIN_ROW_LENGTH: usize = 6;
+
+/// Where Kraken puts the trade ID when it sends one.
+const TRADE_ID_INDEX: usize = 6;
+
+/// Running count of trade rows that couldn't be parsed since the process started.
+static PARSE_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of trade rows rejected so far. A steady climb means Kraken changed its API.
+pub fn parse_error_count() -> usize {
+    PARSE_ERRORS.load(Ordering::Relaxed)
+}
+
+/// A single row of a Kraken trade history response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeRow {
+    price: Decimal,
+    size: Decimal,
+    timestamp: DateTime<Utc>,
+    market: trade::Market,
+    trade: trade::Type,
+
+    /// Miscellaneous flags. Usually blank.
+    misc: String,
+
+    /// Only present on rows that carry the trailing trade ID.
+    trade_id: Option<i64>,
+}
+
+impl TradeRow {
+    /// Parse a row. The first six elements must be in the documented format. A seventh is
+    /// taken to be the trade ID. Anything beyond that is ignored.
+    pub fn parse(row: &[TradeMatchItem]) -> Result<Self, String> {
+        if row.len() < MIN_ROW_LENGTH {
+            return Err(format!("Insufficient vector length: {}", row.len()));
         }
 
         // 1st is the price
-        let price: Decimal = if let TradeMatchItem::Text(ref p) = trade_match[0] {
+        let price: Decimal = if let TradeMatchItem::Text(ref p) = row[0] {
             p.parse().map_err(|_| "Invalid number format.".to_string())?
         } else {
             return Err("Invalid price item at index 0.".to_string());
         };
 
         // 2nd is the size
-        let size: Decimal = if let TradeMatchItem::Text(ref s) = trade_match[1] {
+        let size: Decimal = if let TradeMatchItem::Text(ref s) = row[1] {
             s.parse().map_err(|_| "Invalid number format.".to_string())?
         } else {
             return Err("Invalid size item at index 1.".to_string());
         };
 
         // 3rd is the timestamp
-        let ts: DateTime<Utc> = if let TradeMatchItem::Timestamp(ts) = trade_match[2] {
+        let timestamp: DateTime<Utc> = if let TradeMatchItem::Timestamp(ts) = row[2] {
             // First, we need to split the second and millison second components.
             let seconds = ts
                 .trunc()
@@ -65,41 +88,110 @@ pub fn trade_history(
                 .to_u32()
                 .ok_or("Nansoseconds exceed u32 in timestamp at index 2.".to_owned())?;
 
-            // Now we build out our chrono object            
+            // Now we build out our chrono object
             DateTime::from_utc(NaiveDateTime::from_timestamp(seconds, nanos), Utc)
         } else {
             return Err("Timestamp must be a number field at index 2.".to_string());
         };
 
         // 4th is the side
-        let side: trade::Market = if let TradeMatchItem::Text(ref t) = trade_match[3] {
+        let market: trade::Market = if let TradeMatchItem::Text(ref t) = row[3] {
             t.parse().map_err(|_| "Invalid market side.".to_string())?
         } else {
             return Err("Market side must be a string at index 3.".to_string());
         };
 
         // 5th is the type
-        let trade: trade::Type = if let TradeMatchItem::Text(ref t) = trade_match[4] {
+        let trade: trade::Type = if let TradeMatchItem::Text(ref t) = row[4] {
             t.parse().map_err(|_| "Invalid trade type.".to_string())?
         } else {
             return Err("Trade type must be a string at index 4.".to_string());
         };
 
-        // 6th is the meta (usually blank).
-        let _meta = if let TradeMatchItem::Text(ref s) = trade_match[5] {
+        // 6th is the misc (usually blank).
+        let misc = if let TradeMatchItem::Text(ref s) = row[5] {
             s.to_owned()
         } else {
-            return Err("Meta data must be a string at index 5.".to_string());
+            return Err("Misc must be a string at index 5.".to_string());
         };
 
-        // Build the TradeHistoryItem and add it to our output. The last four `None`s are
-        // for optional data that is not provided by kraken.
-        output.push(trade::TradeHistoryItem::new(
-            ts, size, price, side, Some(trade), None, None, None, None
-        ));
+        // 7th, if there, is the trade ID. Kraken sends it as a number but be lenient.
+        let trade_id = match row.get(TRADE_ID_INDEX) {
+            Some(TradeMatchItem::Timestamp(id)) if id.trunc() == *id => id.to_i64(),
+            Some(TradeMatchItem::Text(id)) => id.parse().ok(),
+            _ => None,
+        };
+
+        Ok(TradeRow {
+            price, size, timestamp, market, trade, misc, trade_id,
+        })
+    }
+
+    pub fn misc(&self) -> &str {
+        self.misc.as_str()
     }
 
-    Ok((asset_pair, output))
+    pub fn trade_id(&self) -> Option<i64> {
+        self.trade_id
+    }
+
+    /// Build the common trade history item. Kraken doesn't provide order IDs or a separate
+    /// match time so those stay `None`.
+    pub fn to_trade_history_item(&self) -> trade::TradeHistoryItem {
+        trade::TradeHistoryItem::new(
+            self.timestamp,
+            self.size,
+            self.price,
+            self.market,
+            Some(self.trade),
+            self.trade_id,
+            None,
+            None,
+            None,
+        )
+    }
+}
+
+/// A trade row that couldn't be parsed, as the row itself and the reason it was rejected.
+pub type RejectedRow = (String, String);
+
+/// Parse every row of the trade history. Rows that can't be parsed are logged, counted and
+/// returned as rejects alongside the good ones so one odd row doesn't sink the whole batch.
+pub fn trade_rows(
+    history: &TradeHistory
+) -> (asset::Pair, Vec<TradeRow>, Vec<RejectedRow>) {
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, trade_match) in history.items().iter().enumerate() {
+        match TradeRow::parse(trade_match) {
+            Ok(row) => rows.push(row),
+            Err(e) => {
+                PARSE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                let raw = serde_json::to_string(trade_match)
+                    .expect("Trade row always serializes.");
+                warn!("Rejected kraken trade row {}: {}. Row: {}", index, &e, &raw);
+                errors.push((raw, e));
+            },
+        }
+    }
+
+    (history.pair(), rows, errors)
+}
+
+/// Convert the internal kraken trade match history model into the common model. This is
+/// done for transmission. Also returns the rows that were rejected.
+pub fn trade_history(
+    history: &TradeHistory
+) -> (asset::Pair, Vec<trade::TradeHistoryItem>, Vec<RejectedRow>) {
+    let (asset_pair, rows, errors) = trade_rows(history);
+
+    let items = rows
+        .iter()
+        .map(|row| row.to_trade_history_item())
+        .collect();
+
+    (asset_pair, items, errors)
 }
 
 #[cfg(test)]
@@ -131,11 +223,9 @@ mod tests {
         ];
 
         let th = TradeHistory::new(Items::XXBTZUSD(items), "123456".to_owned());
-        let result = trade_history(&th);
-        
-        assert!(result.is_ok());
+        let (_, trade_history, rejected) = trade_history(&th);
 
-        let trade_history = result.unwrap().1;
+        assert!(rejected.is_empty());
 
         assert!(trade_history.len() == 2);
         
@@ -172,9 +262,56 @@ mod tests {
         ];
 
         let th = TradeHistory::new(Items::XXBTZUSD(items), "123456".to_owned());
-        let thi1 = trade_history(&th).unwrap().1[0];
-        let thi2 = trade_history(&th).unwrap().1[1];
+        let thi1 = trade_history(&th).1[0];
+        let thi2 = trade_history(&th).1[1];
         assert_eq!(thi1.timestamp().to_rfc3339(), "2018-10-19T13:55:55.300900+00:00");
         assert_eq!(thi2.timestamp().to_rfc3339(), "2018-10-19T13:40:48.421+00:00");
     }
+
+    #[test]
+    fn trailing_trade_id_and_misc_are_kept() {
+        let row = vec![
+            TradeMatchItem::Text("10".to_owned()),
+            TradeMatchItem::Text("1".to_owned()),
+            TradeMatchItem::Timestamp(Decimal::from_str("1539957355.3009").unwrap()),
+            TradeMatchItem::Text("b".to_owned()),
+            TradeMatchItem::Text("m".to_owned()),
+            TradeMatchItem::Text("a".to_owned()),
+            TradeMatchItem::Timestamp(61123.into()),
+            TradeMatchItem::Text("unknown extra".to_owned()),
+        ];
+
+        let parsed = TradeRow::parse(&row).unwrap();
+        assert_eq!(parsed.misc(), "a");
+        assert_eq!(parsed.trade_id(), Some(61123));
+        assert_eq!(parsed.to_trade_history_item().match_id(), Some(61123));
+    }
+
+    #[test]
+    fn bad_rows_are_counted_not_fatal() {
+        let items = vec![
+            vec![
+                TradeMatchItem::Text("10".to_owned()),
+                TradeMatchItem::Text("1".to_owned()),
+                TradeMatchItem::Timestamp(1535271158.into()),
+            ],
+            vec![
+                TradeMatchItem::Text("20".to_owned()),
+                TradeMatchItem::Text("2".to_owned()),
+                TradeMatchItem::Timestamp(1535271158.into()),
+                TradeMatchItem::Text("s".to_owned()),
+                TradeMatchItem::Text("l".to_owned()),
+                TradeMatchItem::Text(String::new()),
+            ],
+        ];
+
+        let th = TradeHistory::new(Items::XXBTZUSD(items), "123456".to_owned());
+        let (_, trade_history, rejected) = trade_history(&th);
+
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].0, r##"["10","1",1535271158]"##);
+        assert_eq!(trade_history.len(), 1);
+        assert_eq!(trade_history[0].match_id(), None);
+        assert!(parse_error_count() >= 1);
+    }
 }
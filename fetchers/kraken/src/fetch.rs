@@ -1,19 +1,21 @@
 //! Fetching code
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::{Future, Stream};
+use futures::stream;
 use futures::sync::mpsc;
 use futures::future::{lazy, result, FutureResult};
-use serde_json;
-use tokio_timer;
+use tokio_timer::{self, Delay};
 use tokio;
 
-use fetch_lib::https_client::{HttpsClient, FetchError};
+use fetch_lib::https_client::{HttpsClient, FetchError, parse_json};
+use fetch_lib::deadletter::DeadLetters;
+use fetch_lib::backoff::Backoff;
 use common::{asset, trade};
 
 use super::KrakenFetchTargets;
 use model::{Outer, TradeHistory};
-use conversion::trade_history;
+use conversion::{trade_history, parse_error_count};
 
 /// Return stream that polls the trade history. Only polls for a single asset pair. This
 /// stream is expected to have combinators attached to it to drive it and deal with the
@@ -37,7 +39,7 @@ pub fn poll_trade_history(
         .map_err(|e| error!("Couldn't setup timer: {}", &e))
         .then(move |_| {
             let uri = targets.trade_history(pair, since)
-                .expect("Invalid asset pair. TODO: Return error here.");
+                .expect("Asset pairs are checked to be traded in the config.");
 
             // Can have a receiver here to get the results from the previous request
             // and back off if there was a throttle warning. The sent future can be
@@ -62,7 +64,7 @@ pub fn poll_trade_history(
         })
         .from_err::<FetchError>()
         .and_then(|body| {
-            let history: Outer<TradeHistory> = serde_json::from_slice(&body)?;
+            let history: Outer<TradeHistory> = parse_json(&body)?;
             Ok(history)
         })
 }
@@ -110,17 +112,159 @@ pub fn poll_trade_histories(
         .flatten_stream()
 }
 
+/// Kraken's `since` parameter is a timestamp in nanoseconds.
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Longest we'll wait between backfill pages when Kraken keeps throttling us.
+const MAX_BACKFILL_DELAY_SECS: u64 = 300;
+
+/// Where a backfill is up to in the trade history of a single asset pair.
+struct Backfill {
+    pair: asset::Pair,
+    since: u64,
+    backoff: Backoff,
+}
+
+impl Backfill {
+    /// Work out the next page to fetch from the result of the last one. Returns `None` once
+    /// Kraken has nothing newer to give which means we have caught up.
+    fn advance(mut self, page: &Result<Outer<TradeHistory>, FetchError>) -> Option<Self> {
+        let outer = match page {
+            Ok(outer) => outer,
+            Err(e) => {
+                warn!("{} backfill page failed: {}. Backing off.", &self.pair, e);
+                self.backoff.increase();
+                return Some(self);
+            },
+        };
+
+        if let Some(history) = outer.result() {
+            let last: u64 = match history.last().parse() {
+                Ok(last) => last,
+                Err(_) => {
+                    error!(
+                        "{} backfill stopped. Bad `last`: {}", &self.pair, history.last(),
+                    );
+                    return None;
+                },
+            };
+
+            if history.items().is_empty() || last <= self.since {
+                info!("{} backfill has caught up.", &self.pair);
+                return None;
+            }
+
+            self.since = last;
+            self.backoff.reset();
+            Some(self)
+        } else if outer.error().iter().all(|e| is_transient(e)) {
+            warn!("{} backfill throttled: {:?}. Backing off.", &self.pair, outer.error());
+            self.backoff.increase();
+            Some(self)
+        } else {
+            error!("{} backfill stopped. Kraken said: {:?}", &self.pair, outer.error());
+            None
+        }
+    }
+}
+
+/// Kraken errors that will go away if we wait a while.
+fn is_transient(error: &str) -> bool {
+    error.starts_with("EAPI:Rate limit") || error.starts_with("EService")
+}
+
+/// Return a stream that walks the trade history of a single asset pair forward from `from`
+/// (Unix seconds) one page at a time until it catches up with the present. Use it to fill
+/// in holes left when the fetcher was down.
+///
+/// Pages are requested no faster than `page_delay` apart. Throttling and service errors
+/// double the delay (up to five minutes) and the same page is asked for again.
+pub fn backfill_trade_history(
+    client: HttpsClient,
+    pair: asset::Pair,
+    targets: KrakenFetchTargets,
+    from: u64,
+    page_delay: Duration,
+) -> impl Stream<Item = Outer<TradeHistory>, Error = FetchError> {
+    let start = Backfill {
+        pair: pair,
+        since: from * NANOS_PER_SECOND,
+        backoff: Backoff::new(page_delay, Duration::from_secs(MAX_BACKFILL_DELAY_SECS)),
+    };
+
+    stream::unfold(Some(start), move |state| {
+        let backfill = match state {
+            Some(backfill) => backfill,
+            None => return None,
+        };
+
+        let client = client.clone();
+        let uri = match targets.trade_history(backfill.pair, Some(backfill.since)) {
+            Some(uri) => uri,
+            None => {
+                error!("{} backfill stopped. Kraken doesn't trade it.", &backfill.pair);
+                return None;
+            },
+        };
+        debug!("Backfilling {} since {}", &backfill.pair, &backfill.since);
+
+        let page = Delay::new(Instant::now() + backfill.backoff.delay())
+            .from_err::<FetchError>()
+            .and_then(move |()| client.get(uri).from_err())
+            .and_then(|res| {
+                let status = res.status().as_u16();
+                res.into_body()
+                    .concat2()
+                    .from_err::<FetchError>()
+                    .and_then(move |body| {
+                        if status != 200 {
+                            return Err(FetchError::Status(status));
+                        }
+                        let history: Outer<TradeHistory> = parse_json(&body)?;
+                        Ok(history)
+                    })
+            })
+            .then(move |page| {
+                let next = backfill.advance(&page);
+                Ok::<_, FetchError>((page, next))
+            });
+
+        Some(page)
+    })
+        .and_then(|page| page)
+}
+
+/// Backfill several asset pairs one after the other. See `backfill_trade_history`.
+pub fn backfill_trade_histories(
+    client: HttpsClient,
+    pairs: Vec<asset::Pair>,
+    targets: KrakenFetchTargets,
+    from: u64,
+    page_delay: Duration,
+) -> impl Stream<Item = Outer<TradeHistory>, Error = FetchError> {
+    stream::iter_ok::<_, FetchError>(pairs)
+        .map(move |pair| {
+            backfill_trade_history(client.clone(), pair, targets.clone(), from, page_delay)
+        })
+        .flatten()
+}
+
 /// Takes in the fetch stream and deals with all benign errors only propagating the stream
-/// killing errors that would need to be handled by an overarching process.
+/// killing errors that would need to be handled by an overarching process. Responses that
+/// can't be deserialized are quarantined in the `dead_letters`.
 pub fn filter_benign_errors(
-    input: impl Stream<Item = Outer<TradeHistory>, Error = FetchError>
+    input: impl Stream<Item = Outer<TradeHistory>, Error = FetchError>,
+    dead_letters: DeadLetters,
 ) -> impl Stream<Item = TradeHistory, Error = ()> {
     
     // First we remove fetch errors depending on their severity.
     let trimmed = input
-        .then(|result| {
+        .then(move |result| {
             match result {
-                Ok(history) => Ok(Some(history)),
+                Ok(history) => {
+                    dead_letters.success();
+                    Ok(Some(history))
+                },
                 Err(fetch_err) => match fetch_err {
                     FetchError::Client(e) => {
                         error!("Client error: {}", &e);
@@ -131,6 +275,11 @@ pub fn filter_benign_errors(
                         // This could be serious? Kraken API may have changed.
                         Ok(None)
                     },
+                    FetchError::Payload(e, body) => {
+                        error!("Deserialize error: {}. Quarantined.", &e);
+                        dead_letters.record(&body, &e.to_string());
+                        Ok(None)
+                    },
                     FetchError::Status(e) => {
                         error!("HTTP Status: {}", &e);
                         // Likely we got throttled.
@@ -141,6 +290,10 @@ pub fn filter_benign_errors(
                         // Possibly a transmission error. Perhaps we can continue.
                         Ok(None)
                     },
+                    FetchError::Timer(e) => {
+                        error!("Timer: {}", &e);
+                        Ok(None)
+                    },
                     FetchError::InternalChannel => {
                         error!("Internal channel failure");
                         // This error should never happen. Unbounded channels were being
@@ -152,6 +305,10 @@ pub fn filter_benign_errors(
                         // then rely on orchestration to restart the fetcher.
                         Ok(None)
                     },
+                    FetchError::UnsupportedPair(pair) => {
+                        error!("Kraken doesn't trade {}.", &pair);
+                        Ok(None)
+                    },
                 },
             }
         })
@@ -180,15 +337,26 @@ pub fn filter_benign_errors(
 }
 
 /// Takes a filtered fetch stream and converts it into the common format for placement into
-/// other systems, likely the translator.
+/// other systems, likely the translator. Rows that can't be converted are dropped (and
+/// dead lettered) rather than ending the stream.
 pub fn convert_into_common(
-    input: impl Stream<Item = TradeHistory, Error = ()>
+    input: impl Stream<Item = TradeHistory, Error = ()>,
+    dead_letters: DeadLetters,
 ) -> impl Stream<Item = (asset::Pair, Vec<trade::TradeHistoryItem>), Error = ()> {
-    input.and_then(|history| {
-        trade_history(&history).map_err(|e| {
-            error!("Failure to convert into common format: {}", &e);
-            // TODO: Should we continue with the stream? For now we consider this error
-            //       terminal (perhaps the kraken API has changed?) and we exit the stream.
-        })
+    input.map(move |history| {
+        let (asset_pair, items, rejected) = trade_history(&history);
+        if !rejected.is_empty() {
+            warn!(
+                "{} of {} {} trade rows rejected. {} rejected in total.",
+                rejected.len(),
+                rejected.len() + items.len(),
+                &asset_pair,
+                parse_error_count(),
+            );
+        }
+        for (row, error) in rejected {
+            dead_letters.record(&row, &error);
+        }
+        (asset_pair, items)
     })
 }
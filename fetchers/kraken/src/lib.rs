@@ -25,6 +25,8 @@ pub use self::targets::KrakenFetchTargets;
 pub use self::fetch::{
     poll_trade_history,
     poll_trade_histories,
+    backfill_trade_history,
+    backfill_trade_histories,
     filter_benign_errors,
     convert_into_common,
 };
@@ -16,7 +16,7 @@ use std::time::Duration;
 use futures::future::{Either, lazy, result, FutureResult};
 
 use common::{exchange, asset};
-use fetch_lib::{https_client, place};
+use fetch_lib::{https_client, place, deadletter};
 
 mod config;
 
@@ -33,29 +33,57 @@ fn main() {
         vec![asset::BTC_USD, asset::ETH_USD, asset::ETH_BTC],
     );
     let client = https_client::produce(1).expect("Can't init TLS.");
+    let dead_letters = deadletter::DeadLetters::from_environment("kraken")
+        .expect("Can't open dead letters.");
     let future = match config.fetch_mode() {
         config::FetchMode::TradeHistory => {
             debug!("Trade history fetching chosen.");
             let raw_fetch_stream = lib::poll_trade_histories(
                 client.clone(),
                 fetch_aps,
-                lib::KrakenFetchTargets,
+                config.targets(),
                 Duration::from_secs(60),
             );
             
-            let filtered_fetch_stream = lib::filter_benign_errors(raw_fetch_stream);
-            let converted_stream = lib::convert_into_common(filtered_fetch_stream);
+            let filtered_fetch_stream = lib::filter_benign_errors(
+                raw_fetch_stream, dead_letters.clone(),
+            );
+            let converted_stream = lib::convert_into_common(
+                filtered_fetch_stream, dead_letters.clone(),
+            );
             let place_future = place::put_trade_history(
                 client.clone(), targets, converted_stream,
             );
             Either::A(place_future)
         },
+        config::FetchMode::Backfill => {
+            let from = config.backfill_from().expect("Backfill mode always has a start.");
+            debug!("Trade history backfill from {} chosen.", &from);
+            let raw_fetch_stream = lib::backfill_trade_histories(
+                client.clone(),
+                fetch_aps,
+                config.targets(),
+                from,
+                config.backfill_page_delay(),
+            );
+
+            let filtered_fetch_stream = lib::filter_benign_errors(
+                raw_fetch_stream, dead_letters.clone(),
+            );
+            let converted_stream = lib::convert_into_common(
+                filtered_fetch_stream, dead_letters.clone(),
+            );
+            let place_future = place::put_trade_history(
+                client.clone(), targets, converted_stream,
+            );
+            Either::B(Either::A(place_future))
+        },
         config::FetchMode::OrderBook => {
             debug!("Order book fetching chosen.");
-            Either::B(lazy(|| -> FutureResult<(), ()> {
+            Either::B(Either::B(lazy(|| -> FutureResult<(), ()> {
                 println!("TODO: Implement order book fetcher.");
                 result::<(), ()>(Ok(()))
-            }))
+            })))
         },
     };
 
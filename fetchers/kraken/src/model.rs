@@ -1,7 +1,9 @@
 //! Kraken models.
 use std::fmt::Debug;
 
+use num_traits::ToPrimitive;
 use rust_decimal::Decimal;
+use serde::{Serialize, Serializer};
 
 use common::asset;
 
@@ -15,6 +17,26 @@ pub enum TradeMatchItem {
     Timestamp(Decimal),
 }
 
+/// Written back out the way Kraken sent it so that rejected rows can be replayed.
+impl Serialize for TradeMatchItem {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            TradeMatchItem::Text(text) => serializer.serialize_str(text),
+            // A JSON number. Kraken's few decimal places survive the trip through an f64.
+            TradeMatchItem::Timestamp(number) if number.scale() == 0 => {
+                match number.to_i64() {
+                    Some(whole) => serializer.serialize_i64(whole),
+                    None => serializer.serialize_str(&number.to_string()),
+                }
+            },
+            TradeMatchItem::Timestamp(number) => match number.to_f64() {
+                Some(float) => serializer.serialize_f64(float),
+                None => serializer.serialize_str(&number.to_string()),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub enum Items {
     XXBTZUSD(Vec<Vec<TradeMatchItem>>),
@@ -112,4 +134,18 @@ mod tests {
             .expect("Failed to deserialize.");
         assert!(history.result().unwrap().last.as_str() == "1535290179989384853");
     }
+
+    #[test]
+    fn trade_rows_serialize_as_sent() {
+        let history: Outer<TradeHistory> = serde_json::from_str(TRADE_HISTORY_BTC_USD_JSON)
+            .expect("Failed to deserialize.");
+        let row = &history.result().unwrap().items()[0];
+
+        assert_eq!(
+            serde_json::to_string(row).unwrap(),
+            r##"["6650.00000","0.00100000",1535271158.4026,"b","m",""]"##,
+        );
+        let whole = vec![TradeMatchItem::Timestamp(1535271158.into())];
+        assert_eq!(serde_json::to_string(&whole).unwrap(), "[1535271158]");
+    }
 }
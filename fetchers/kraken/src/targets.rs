@@ -3,30 +3,88 @@ use hyper::Uri;
 
 use common::asset;
 
-/// Fetch targets. Unit struct for now with hardcoded values. We're just trying to explore
-/// an API. In the future we can instantiate and load in the base paths and other stuff.
+/// Where the real Kraken API lives.
+pub const DEFAULT_BASE_URI: &str = "https://api.kraken.com";
+
+/// Fetch targets. Only the base is configurable so the fetcher can be pointed at a mock
+/// exchange. The paths are those of the Kraken public API.
 #[derive(Debug, Clone)]
-pub struct KrakenFetchTargets;
+pub struct KrakenFetchTargets {
+    base: String,
+}
 
 impl KrakenFetchTargets {
+    /// The `base` must be an absolute URI without a query, `http://127.0.0.1:8101` say.
+    pub fn new(base: &str) -> Result<Self, String> {
+        let base = base.trim_right_matches('/');
+        let uri: Uri = base.parse().map_err(|e| format!("`{}` {}", base, e))?;
+        if uri.scheme_part().is_none() || uri.authority_part().is_none() {
+            return Err(format!("`{}` isn't absolute.", base));
+        }
+        if uri.query().is_some() {
+            return Err(format!("`{}` has a query.", base));
+        }
+
+        Ok(KrakenFetchTargets {
+            base: base.to_owned(),
+        })
+    }
+
+    /// Whether Kraken trades the asset pair.
+    pub fn trades(ap: asset::Pair) -> bool {
+        pair_code(ap).is_some()
+    }
 
-    /// Return the URI for the asset pair.
+    /// Return the URI for the asset pair. `None` if Kraken doesn't trade it.
     pub fn trade_history(&self, ap: asset::Pair, since: Option<u64>) -> Option<Uri> {
-        let base = "https://api.kraken.com/0/public/Trades";
-        let pair = match ap {
-            asset::BTC_USD => "?pair=XBTUSD",
-            asset::ETH_USD => "?pair=ETHUSD",
-            asset::ETH_BTC => "?pair=ETHXBT",
-            _ => return None,
-        };
+        let pair = pair_code(ap)?;
 
         let uri = if let Some(since) = since {
-            format!("{}{}&since={}", base, pair, &since)
+            format!("{}/0/public/Trades?pair={}&since={}", &self.base, pair, &since)
         } else {
-            format!("{}{}", base, pair)
+            format!("{}/0/public/Trades?pair={}", &self.base, pair)
         };
 
-        // This part shouldn't fail as we're controlling URI construction.
-        Some(uri.parse().expect("Invalid URI constructed. This shouldn't happen. Fix me."))
+        Some(uri.parse().expect("The base was checked to be a URI."))
+    }
+}
+
+fn pair_code(ap: asset::Pair) -> Option<&'static str> {
+    match ap {
+        asset::BTC_USD => Some("XBTUSD"),
+        asset::ETH_USD => Some("ETHUSD"),
+        asset::ETH_BTC => Some("ETHXBT"),
+        _ => None,
+    }
+}
+
+impl Default for KrakenFetchTargets {
+    fn default() -> Self {
+        KrakenFetchTargets::new(DEFAULT_BASE_URI).expect("The default base is a URI.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_is_configurable() {
+        let kraken = KrakenFetchTargets::default();
+        assert_eq!(
+            kraken.trade_history(asset::BTC_USD, None).unwrap(),
+            "https://api.kraken.com/0/public/Trades?pair=XBTUSD",
+        );
+
+        let mock = KrakenFetchTargets::new("http://127.0.0.1:8101/").unwrap();
+        assert_eq!(
+            mock.trade_history(asset::ETH_BTC, Some(5)).unwrap(),
+            "http://127.0.0.1:8101/0/public/Trades?pair=ETHXBT&since=5",
+        );
+        assert_eq!(mock.trade_history(asset::BNB_BTC, None), None);
+
+        assert!(KrakenFetchTargets::new("api.kraken.com").is_err());
+        assert!(KrakenFetchTargets::new("http://bad host").is_err());
+        assert!(KrakenFetchTargets::new("http://127.0.0.1:8101/?pair=XBTUSD").is_err());
     }
 }
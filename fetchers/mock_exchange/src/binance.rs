@@ -0,0 +1,295 @@
+//! Binance shaped combined websocket stream.
+//!
+//! Clients connect on `/stream?streams={a}/{b}` and may change the streams later with
+//! `SUBSCRIBE` and `UNSUBSCRIBE` frames. Every interval the connection is sent the next
+//! fixture line for one of its streams. The fixtures go round forever so a connection never
+//! runs dry. Binance trade IDs repeat as they do so the fetcher's dedupe is exercised too.
+use std::{fs, io};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{Future, Sink, Stream};
+use futures::future;
+use futures::sync::{mpsc, oneshot};
+use serde_json::{self, Value};
+use tokio;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::timer::Interval;
+use tokio_tungstenite::{accept_hdr_async, WebSocketStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::Request;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+use common::exchange::Exchange;
+
+use fault::{Fault, Faults};
+
+/// A fixture line and the stream it belongs to.
+#[derive(Debug, Clone)]
+pub struct Line {
+    stream: String,
+    json: String,
+}
+
+/// Load the combined stream messages in the NDJSON file at `path`.
+pub fn load_lines(path: &Path) -> io::Result<Vec<Line>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let value: Value = serde_json::from_str(line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let stream = value.get("stream")
+                .and_then(Value::as_str)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No stream name."))?;
+            Ok(Line {
+                stream: stream.to_owned(),
+                json: line.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Streams asked for in the connection path.
+fn streams_from_path(path: &str) -> HashSet<String> {
+    path.splitn(2, "streams=")
+        .nth(1)
+        .map(|streams| {
+            streams
+                .split('/')
+                .filter(|stream| !stream.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Deserialize)]
+struct Frame {
+    method: String,
+    #[serde(default)]
+    params: Vec<String>,
+    id: u64,
+}
+
+/// Act on a frame from the client. Returns the reply if it was a frame binance replies to.
+fn on_frame(streams: &Mutex<HashSet<String>>, text: &str) -> Option<String> {
+    let frame: Frame = serde_json::from_str(text).ok()?;
+    let mut streams = streams.lock().expect("Streams lock poisoned.");
+    match frame.method.as_str() {
+        "SUBSCRIBE" => streams.extend(frame.params),
+        "UNSUBSCRIBE" => for stream in frame.params.iter() {
+            streams.remove(stream);
+        },
+        _ => {
+            return Some(format!(
+                r#"{{"error":{{"code":2,"msg":"Invalid request"}},"id":{}}}"#, frame.id,
+            ));
+        },
+    }
+
+    Some(format!(r#"{{"result":null,"id":{}}}"#, frame.id))
+}
+
+/// Where a connection is up to in the fixtures.
+struct Feed {
+    lines: Arc<Vec<Line>>,
+    next: usize,
+    streams: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Feed {
+    /// The next line for a stream the connection wants. `None` if there are none.
+    fn next_line(&mut self) -> Option<String> {
+        let streams = self.streams.lock().expect("Streams lock poisoned.");
+        for _ in 0..self.lines.len() {
+            let line = &self.lines[self.next];
+            self.next = (self.next + 1) % self.lines.len();
+            if streams.contains(&line.stream) {
+                return Some(line.json.clone());
+            }
+        }
+
+        None
+    }
+}
+
+/// Run a connection. The reading, writing and feeding each run on their own. A scripted
+/// disconnect kills the reading and writing halves so the socket is dropped on the spot.
+fn connection(
+    ws: WebSocketStream<TcpStream>,
+    streams: HashSet<String>,
+    lines: Arc<Vec<Line>>,
+    interval: Duration,
+    faults: Faults,
+) {
+    let streams = Arc::new(Mutex::new(streams));
+    let (sink, stream) = ws.split();
+    let (tx, rx) = mpsc::unbounded();
+    let (kill_tx, kill_rx) = oneshot::channel::<()>();
+
+    // Only an actual kill counts. The sender being dropped is not one.
+    let killed = kill_rx
+        .or_else(|_| future::empty::<(), oneshot::Canceled>())
+        .shared();
+
+    let replies = tx.clone();
+    let control = streams.clone();
+    let reader = stream
+        .map_err(|e| warn!("Binance connection read failure: {}", &e))
+        .for_each(move |msg| {
+            if let Message::Text(text) = msg {
+                if let Some(reply) = on_frame(&control, &text) {
+                    replies.unbounded_send(Message::Text(reply)).map_err(|_| ())?;
+                }
+            }
+            Ok(())
+        })
+        .select2(killed.clone())
+        .then(|_| Ok::<(), ()>(()));
+
+    let writer = sink
+        .sink_map_err(|e| warn!("Binance connection write failure: {}", &e))
+        .send_all(rx)
+        .select2(killed)
+        .then(|_| Ok::<(), ()>(()));
+
+    let mut feed = Feed {
+        lines: lines,
+        next: 0,
+        streams: streams,
+    };
+    let mut kill = Some(kill_tx);
+    let ticker = Interval::new_interval(interval)
+        .map_err(|e| error!("Timer failure: {}", &e))
+        .for_each(move |_| {
+            let json = match feed.next_line() {
+                Some(json) => json,
+                None => return Ok(()),
+            };
+
+            let msg = match faults.next(Exchange::Binance) {
+                None => Message::Text(json),
+                Some(Fault::Malformed) => {
+                    Message::Text(json.chars().take(json.len() / 2).collect())
+                },
+                Some(Fault::RateLimit) => {
+                    warn!("Closing binance connection for sending too much.");
+                    let close = CloseFrame {
+                        code: CloseCode::Policy,
+                        reason: "Too many requests".into(),
+                    };
+                    let _ = tx.unbounded_send(Message::Close(Some(close)));
+                    return Err(());
+                },
+                Some(Fault::Disconnect) => {
+                    warn!("Dropping binance connection.");
+                    if let Some(kill) = kill.take() {
+                        let _ = kill.send(());
+                    }
+                    return Err(());
+                },
+            };
+
+            tx.unbounded_send(msg).map_err(|_| ())
+        });
+
+    tokio::spawn(reader);
+    tokio::spawn(writer);
+    tokio::spawn(ticker);
+}
+
+/// Accept websocket connections on `addr`. Each connection is sent a fixture line every
+/// `interval`. Must be run on the tokio runtime.
+pub fn serve(
+    addr: SocketAddr, lines: Vec<Line>, interval: Duration, faults: Faults,
+) -> impl Future<Item = (), Error = ()> {
+    info!("Mock binance listening on {} with {} line(s).", &addr, lines.len());
+    let lines = Arc::new(lines);
+    let listener = TcpListener::bind(&addr).expect("Can't bind the mock binance listener.");
+
+    listener
+        .incoming()
+        .map_err(|e| error!("Mock binance listener failure: {}", &e))
+        .for_each(move |tcp| {
+            let path = Arc::new(Mutex::new(String::new()));
+            let requested = path.clone();
+            let lines = lines.clone();
+            let faults = faults.clone();
+
+            let accept = accept_hdr_async(tcp, move |req: &Request| {
+                *requested.lock().expect("Path lock poisoned.") = req.path.clone();
+                Ok(None)
+            })
+                .map_err(|e| warn!("Mock binance handshake failed: {}", &e))
+                .map(move |ws| {
+                    let path = path.lock().expect("Path lock poisoned.").clone();
+                    let streams = streams_from_path(&path);
+                    debug!("Binance connection for {:?}.", &streams);
+                    connection(ws, streams, lines, interval, faults);
+                });
+
+            tokio::spawn(accept);
+            Ok(())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(stream: &str) -> Line {
+        Line {
+            stream: stream.to_owned(),
+            json: format!(r#"{{"stream":"{}"}}"#, stream),
+        }
+    }
+
+    #[test]
+    fn path_streams() {
+        let streams = streams_from_path("/stream?streams=btcusdt@trade/ethbtc@trade");
+        assert_eq!(streams.len(), 2);
+        assert!(streams.contains("btcusdt@trade"));
+        assert!(streams_from_path("/ws").is_empty());
+    }
+
+    #[test]
+    fn frames_change_streams() {
+        let streams = Mutex::new(streams_from_path("/stream?streams=btcusdt@trade"));
+
+        let reply = on_frame(
+            &streams, r#"{"method":"SUBSCRIBE","params":["ethbtc@trade"],"id":7}"#,
+        );
+        assert_eq!(reply.unwrap(), r#"{"result":null,"id":7}"#);
+        let reply = on_frame(
+            &streams, r#"{"method":"UNSUBSCRIBE","params":["btcusdt@trade"],"id":8}"#,
+        );
+        assert_eq!(reply.unwrap(), r#"{"result":null,"id":8}"#);
+
+        let streams = streams.into_inner().unwrap();
+        assert_eq!(streams.len(), 1);
+        assert!(streams.contains("ethbtc@trade"));
+    }
+
+    #[test]
+    fn feed_goes_round_the_wanted_streams() {
+        let mut streams = HashSet::new();
+        streams.insert("ethbtc@trade".to_owned());
+        let mut feed = Feed {
+            lines: Arc::new(vec![line("btcusdt@trade"), line("ethbtc@trade")]),
+            next: 0,
+            streams: Arc::new(Mutex::new(streams)),
+        };
+
+        let first = feed.next_line().unwrap();
+        assert!(first.contains("ethbtc"));
+        assert_eq!(feed.next_line().unwrap(), first);
+
+        feed.streams.lock().unwrap().clear();
+        assert_eq!(feed.next_line(), None);
+    }
+}
@@ -0,0 +1,99 @@
+//! Configuration
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use common::errors::ConfigError;
+
+use lib::fault::Script;
+use lib::kraken::DEFAULT_PAGE_ROWS;
+
+static FIXTURE_DIR: &str = "MOCK_FIXTURE_DIR";
+static KRAKEN_LISTEN: &str = "MOCK_KRAKEN_LISTEN";
+static KRAKEN_PAGE_ROWS: &str = "MOCK_KRAKEN_PAGE_ROWS";
+static BINANCE_LISTEN: &str = "MOCK_BINANCE_LISTEN";
+static BINANCE_INTERVAL: &str = "MOCK_BINANCE_INTERVAL_MS";
+static FAULTS: &str = "MOCK_FAULTS";
+
+static DEFAULT_FIXTURE_DIR: &str = "fixtures";
+static DEFAULT_KRAKEN_LISTEN: &str = "127.0.0.1:8101";
+static DEFAULT_BINANCE_LISTEN: &str = "127.0.0.1:8102";
+const DEFAULT_BINANCE_INTERVAL_MS: u64 = 500;
+
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    fixture_dir: PathBuf,
+    kraken_listen: SocketAddr,
+    kraken_page_rows: usize,
+    binance_listen: SocketAddr,
+    binance_interval: Duration,
+    faults: Script,
+}
+
+impl Configuration {
+    /// Kraken fixtures are in `kraken/` below it and binance ones in `binance/`.
+    pub fn fixture_dir(&self) -> &PathBuf {
+        &self.fixture_dir
+    }
+
+    pub fn kraken_listen(&self) -> SocketAddr {
+        self.kraken_listen
+    }
+
+    /// Most trades in a kraken response to a request with `since`.
+    pub fn kraken_page_rows(&self) -> usize {
+        self.kraken_page_rows
+    }
+
+    pub fn binance_listen(&self) -> SocketAddr {
+        self.binance_listen
+    }
+
+    /// Time between messages on each binance connection.
+    pub fn binance_interval(&self) -> Duration {
+        self.binance_interval
+    }
+
+    pub fn faults(&self) -> Script {
+        self.faults.clone()
+    }
+}
+
+pub fn config_from_environment() -> Result<Configuration, ConfigError> {
+    let fixture_dir = env::var(FIXTURE_DIR).unwrap_or(DEFAULT_FIXTURE_DIR.to_owned());
+
+    let kraken_listen = env::var(KRAKEN_LISTEN)
+        .unwrap_or(DEFAULT_KRAKEN_LISTEN.to_owned())
+        .parse()
+        .map_err(|e| (KRAKEN_LISTEN, e))?;
+
+    let kraken_page_rows = match env::var(KRAKEN_PAGE_ROWS) {
+        Ok(rows) => rows.parse().map_err(|e| (KRAKEN_PAGE_ROWS, e))?,
+        Err(_) => DEFAULT_PAGE_ROWS,
+    };
+
+    let binance_listen = env::var(BINANCE_LISTEN)
+        .unwrap_or(DEFAULT_BINANCE_LISTEN.to_owned())
+        .parse()
+        .map_err(|e| (BINANCE_LISTEN, e))?;
+
+    let binance_interval = match env::var(BINANCE_INTERVAL) {
+        Ok(millis) => millis.parse().map_err(|e| (BINANCE_INTERVAL, e))?,
+        Err(_) => DEFAULT_BINANCE_INTERVAL_MS,
+    };
+
+    let faults = match env::var(FAULTS) {
+        Ok(script) => script.parse().map_err(ConfigError::InvalidMode)?,
+        Err(_) => Script::default(),
+    };
+
+    Ok(Configuration {
+        fixture_dir: PathBuf::from(fixture_dir),
+        kraken_listen,
+        kraken_page_rows,
+        binance_listen,
+        binance_interval: Duration::from_millis(binance_interval),
+        faults,
+    })
+}
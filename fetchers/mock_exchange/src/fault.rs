@@ -0,0 +1,124 @@
+//! Scripted faults.
+//!
+//! A script is a comma separated list of `{exchange}@{n}={fault}`. The `n`th kraken
+//! request, or the `n`th binance message counted across all connections, gets the fault
+//! instead of the fixture. Counting starts at one. For example
+//! `kraken@3=rate_limit,binance@10=disconnect`.
+use std::collections::HashMap;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+use common::exchange::Exchange;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Kraken answers with its rate limit error. Binance closes the connection for sending
+    /// too much.
+    RateLimit,
+
+    /// The payload is cut short so it isn't valid JSON.
+    Malformed,
+
+    /// The connection is dropped without a response or close frame.
+    Disconnect,
+}
+
+impl str::FromStr for Fault {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rate_limit" => Ok(Fault::RateLimit),
+            "malformed" => Ok(Fault::Malformed),
+            "disconnect" => Ok(Fault::Disconnect),
+            _ => Err(format!("Unknown fault: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    faults: HashMap<(Exchange, u64), Fault>,
+}
+
+impl str::FromStr for Script {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut faults = HashMap::new();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let invalid = || format!("Invalid fault entry: {}", entry);
+            let mut sides = entry.splitn(2, '=');
+            let mut at = sides.next().unwrap_or("").splitn(2, '@');
+            let exchange: Exchange = at.next().unwrap_or("").parse().map_err(|_| invalid())?;
+            let n: u64 = at.next().unwrap_or("").parse().map_err(|_| invalid())?;
+            let fault: Fault = sides.next().ok_or_else(invalid)?.parse()?;
+            faults.insert((exchange, n), fault);
+        }
+
+        Ok(Script { faults })
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    script: Script,
+    served: HashMap<Exchange, u64>,
+}
+
+/// Counts what is served for each exchange and hands out the scripted faults. Shared by
+/// every connection.
+#[derive(Debug, Clone)]
+pub struct Faults {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Faults {
+    pub fn new(script: Script) -> Self {
+        Faults {
+            inner: Arc::new(Mutex::new(Inner {
+                script: script,
+                served: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Count the next thing served for the exchange. Returns the fault if one is scripted.
+    pub fn next(&self, exchange: Exchange) -> Option<Fault> {
+        let mut inner = self.inner.lock().expect("Faults lock poisoned.");
+        let n = {
+            let n = inner.served.entry(exchange).or_insert(0);
+            *n += 1;
+            *n
+        };
+        inner.script.faults.get(&(exchange, n)).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_script() {
+        let script: Script = "kraken@3=rate_limit, binance@10=disconnect".parse().unwrap();
+        assert_eq!(script.faults.len(), 2);
+        assert_eq!(script.faults.get(&(Exchange::Kraken, 3)), Some(&Fault::RateLimit));
+        assert_eq!(script.faults.get(&(Exchange::Binance, 10)), Some(&Fault::Disconnect));
+
+        assert_eq!("".parse::<Script>().unwrap(), Script::default());
+        assert!("kraken@x=malformed".parse::<Script>().is_err());
+        assert!("kraken@1=flood".parse::<Script>().is_err());
+        assert!("kraken@1".parse::<Script>().is_err());
+    }
+
+    #[test]
+    fn faults_are_counted_per_exchange() {
+        let faults = Faults::new("kraken@2=malformed,binance@1=disconnect".parse().unwrap());
+
+        assert_eq!(faults.next(Exchange::Kraken), None);
+        assert_eq!(faults.next(Exchange::Binance), Some(Fault::Disconnect));
+        assert_eq!(faults.next(Exchange::Kraken), Some(Fault::Malformed));
+        assert_eq!(faults.next(Exchange::Kraken), None);
+    }
+}
@@ -0,0 +1,252 @@
+//! Kraken shaped REST API. Only `GET /0/public/Trades?pair={pair}` is served. The response
+//! is the fixture named after the pair, `kraken/XBTUSD.json` for example. Kraken answers
+//! errors with a `200` and an `error` list so the mock does too.
+//!
+//! With `since` the response is paged like Kraken's. Only the trades after it are returned,
+//! up to a page of them, and `last` is the time of the last one in nanoseconds.
+use std::{cmp, fs, io};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::Future;
+use futures::future;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::service::service_fn;
+use serde_json::{self, Value};
+
+use common::exchange::Exchange;
+
+use fault::{Fault, Faults};
+
+static RATE_LIMIT: &str = r#"{"error":["EAPI:Rate limit exceeded"]}"#;
+static UNKNOWN_PAIR: &str = r#"{"error":["EQuery:Unknown asset pair"]}"#;
+static INVALID_SINCE: &str = r#"{"error":["EGeneral:Invalid arguments"]}"#;
+
+/// Most trades Kraken returns at once.
+pub const DEFAULT_PAGE_ROWS: usize = 1000;
+
+/// Response bodies by kraken pair name.
+pub type Fixtures = HashMap<String, String>;
+
+/// Load every `.json` file in `dir`. The file name without the extension is the pair.
+pub fn load_fixtures(dir: &Path) -> io::Result<Fixtures> {
+    let mut fixtures = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        if let Some(pair) = path.file_stem().and_then(|stem| stem.to_str()) {
+            fixtures.insert(pair.to_owned(), fs::read_to_string(&path)?);
+        }
+    }
+
+    Ok(fixtures)
+}
+
+fn json(body: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .expect("Valid response.")
+}
+
+fn empty(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .expect("Valid response.")
+}
+
+fn from_query<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .filter_map(|param| {
+            let mut kv = param.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(key), Some(value)) if key == name => Some(value),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+/// Time of a kraken trade row in nanoseconds.
+fn nanos(row: &Value) -> Option<u64> {
+    row.get(2)?.as_f64().map(|seconds| (seconds * 1e9) as u64)
+}
+
+/// The trades of the fixture `body` after `since`, no more than `rows` of them.
+fn page(body: &str, since: u64, rows: usize) -> serde_json::Result<String> {
+    let mut outer: Value = serde_json::from_str(body)?;
+
+    if let Some(result) = outer.get_mut("result").and_then(Value::as_object_mut) {
+        let mut last = since;
+        for (key, trades) in result.iter_mut() {
+            if key == "last" {
+                continue;
+            }
+            if let Some(trades) = trades.as_array_mut() {
+                let after: Vec<Value> = trades
+                    .drain(..)
+                    .filter(|row| nanos(row).map(|at| at > since).unwrap_or(false))
+                    .take(rows)
+                    .collect();
+                if let Some(at) = after.last().and_then(nanos) {
+                    last = cmp::max(last, at);
+                }
+                *trades = after;
+            }
+        }
+        result.insert("last".to_owned(), Value::String(last.to_string()));
+    }
+
+    serde_json::to_string(&outer)
+}
+
+/// Response to the request. `None` means the connection is to be dropped.
+fn respond(
+    fixtures: &Fixtures, page_rows: usize, faults: &Faults, req: &Request<Body>,
+) -> Option<Response<Body>> {
+    if req.method() != &Method::GET {
+        return Some(empty(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    if req.uri().path() != "/0/public/Trades" {
+        return Some(empty(StatusCode::NOT_FOUND));
+    }
+
+    let query = req.uri().query();
+    let fixture = match from_query(query, "pair").and_then(|pair| fixtures.get(pair)) {
+        Some(fixture) => fixture,
+        None => return Some(json(UNKNOWN_PAIR.to_owned())),
+    };
+
+    let body = match from_query(query, "since").map(|since| since.parse()) {
+        Some(Ok(since)) => match page(fixture, since, page_rows) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Can't page kraken fixture. Serving it whole: {}", &e);
+                fixture.clone()
+            },
+        },
+        Some(Err(_)) => return Some(json(INVALID_SINCE.to_owned())),
+        None => fixture.clone(),
+    };
+
+    match faults.next(Exchange::Kraken) {
+        None => Some(json(body)),
+        Some(Fault::RateLimit) => Some(json(RATE_LIMIT.to_owned())),
+        Some(Fault::Malformed) => Some(json(body.chars().take(body.len() / 2).collect())),
+        Some(Fault::Disconnect) => None,
+    }
+}
+
+/// Serve the `fixtures`. Responses to requests with `since` hold up to `page_rows` trades.
+pub fn serve(
+    addr: SocketAddr, fixtures: Fixtures, page_rows: usize, faults: Faults,
+) -> impl Future<Item = (), Error = ()> {
+    info!("Mock kraken listening on {} with {} pair(s).", &addr, fixtures.len());
+    let fixtures = Arc::new(fixtures);
+    Server::bind(&addr)
+        .serve(move || {
+            let fixtures = fixtures.clone();
+            let faults = faults.clone();
+            service_fn(move |req| match respond(&fixtures, page_rows, &faults, &req) {
+                Some(rsp) => future::ok(rsp),
+                None => {
+                    // Hyper drops the connection when the service fails.
+                    warn!("Dropping kraken connection.");
+                    future::err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted, "Scripted disconnect",
+                    ))
+                },
+            })
+        })
+        .map_err(|e| error!("Mock kraken failure: {}", &e))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+
+    use super::*;
+    use fault::Script;
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    fn body(rsp: Response<Body>) -> String {
+        let bytes = rsp.into_body().concat2().wait().unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn query_pair() {
+        assert_eq!(from_query(Some("pair=XBTUSD&since=5"), "pair"), Some("XBTUSD"));
+        assert_eq!(from_query(Some("since=5&pair=ETHXBT"), "pair"), Some("ETHXBT"));
+        assert_eq!(from_query(Some("since=5"), "pair"), None);
+        assert_eq!(from_query(Some("since=5"), "since"), Some("5"));
+        assert_eq!(from_query(None, "pair"), None);
+    }
+
+    #[test]
+    fn pages_follow_since() {
+        let mut fixtures = Fixtures::new();
+        fixtures.insert(
+            "XBTUSD".to_owned(),
+            r#"{"error":[],"result":{"XXBTZUSD":[
+                ["1.0","1.0",10.5,"b","l",""],
+                ["2.0","1.0",11.5,"b","l",""],
+                ["3.0","1.0",12.5,"b","l",""]
+            ],"last":"12500000000"}}"#.to_owned(),
+        );
+        let faults = Faults::new(Script::default());
+        let trades = |since: &str| -> Value {
+            let uri = format!("/0/public/Trades?pair=XBTUSD&since={}", since);
+            let rsp = respond(&fixtures, 2, &faults, &get(&uri)).unwrap();
+            serde_json::from_str(&body(rsp)).unwrap()
+        };
+
+        let first = trades("0");
+        assert_eq!(first["result"]["XXBTZUSD"].as_array().unwrap().len(), 2);
+        let last = first["result"]["last"].as_str().unwrap().to_owned();
+        assert_eq!(last, "11500000000");
+
+        let second = trades(&last);
+        assert_eq!(second["result"]["XXBTZUSD"][0][0], "3.0");
+        let last = second["result"]["last"].as_str().unwrap().to_owned();
+
+        // Caught up. `last` stays where it was.
+        let caught_up = trades(&last);
+        assert!(caught_up["result"]["XXBTZUSD"].as_array().unwrap().is_empty());
+        assert_eq!(caught_up["result"]["last"].as_str().unwrap(), last);
+    }
+
+    #[test]
+    fn scripted_responses() {
+        let mut fixtures = Fixtures::new();
+        fixtures.insert("XBTUSD".to_owned(), r#"{"error":[],"result":{}}"#.to_owned());
+        let faults = Faults::new("kraken@2=rate_limit,kraken@3=malformed,kraken@4=disconnect"
+            .parse()
+            .unwrap());
+        let trades = "/0/public/Trades?pair=XBTUSD";
+        let request = |uri: &str| respond(&fixtures, DEFAULT_PAGE_ROWS, &faults, &get(uri));
+
+        let ok = request(trades).unwrap();
+        assert_eq!(body(ok), r#"{"error":[],"result":{}}"#);
+        let limited = request(trades).unwrap();
+        assert_eq!(body(limited), RATE_LIMIT);
+        let malformed = request(trades).unwrap();
+        assert_eq!(body(malformed), r#"{"error":[],"#);
+        assert!(request(trades).is_none());
+
+        let unknown = request("/0/public/Trades?pair=DOGEUSD");
+        assert_eq!(body(unknown.unwrap()), UNKNOWN_PAIR);
+        let missing = request("/0/public/Depth").unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+}
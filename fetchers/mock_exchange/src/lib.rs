@@ -0,0 +1,16 @@
+//! Mock exchange. Serves fixtures shaped like the exchange APIs so the fetchers can be run
+//! without the internet, along with scripted faults to see how they cope.
+#[macro_use] extern crate log;
+#[macro_use] extern crate serde_derive;
+extern crate futures;
+extern crate tokio;
+extern crate tokio_tungstenite;
+extern crate hyper;
+extern crate serde;
+extern crate serde_json;
+
+extern crate common;
+
+pub mod fault;
+pub mod kraken;
+pub mod binance;
@@ -0,0 +1,39 @@
+//! Entrypoint
+#[macro_use] extern crate log;
+extern crate env_logger;
+extern crate futures;
+extern crate tokio;
+extern crate dotenv;
+
+extern crate common;
+
+extern crate mock_exchange_lib as lib;
+
+use futures::future;
+
+use lib::{binance, kraken, fault};
+
+mod config;
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let config = config::config_from_environment().expect("Can't load config.");
+    debug!("Configuration: {:?}", &config);
+
+    let kraken_fixtures = kraken::load_fixtures(&config.fixture_dir().join("kraken"))
+        .expect("Can't load kraken fixtures.");
+    let binance_lines = binance::load_lines(
+        &config.fixture_dir().join("binance").join("streams.ndjson")
+    ).expect("Can't load binance fixtures.");
+    let faults = fault::Faults::new(config.faults());
+
+    tokio::run(future::lazy(move || {
+        tokio::spawn(kraken::serve(
+            config.kraken_listen(), kraken_fixtures, config.kraken_page_rows(), faults.clone(),
+        ));
+        binance::serve(
+            config.binance_listen(), binance_lines, config.binance_interval(), faults,
+        )
+    }));
+}
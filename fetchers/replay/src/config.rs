@@ -0,0 +1,98 @@
+//! Configuration
+use std::env;
+use std::path::PathBuf;
+
+use common::exchange;
+use common::errors::ConfigError;
+
+use lib::read::Format;
+
+static COLLECTOR: &str = "SAMMY_COLLECTOR";
+static FILES: &str = "REPLAY_FILES";
+static FORMAT: &str = "REPLAY_FORMAT";
+static EXCHANGE: &str = "REPLAY_EXCHANGE";
+static SPEED: &str = "REPLAY_SPEED";
+static BATCH_SIZE: &str = "REPLAY_BATCH_SIZE";
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    collector: String,
+    files: Vec<PathBuf>,
+    format: Option<Format>,
+    exchange: Option<exchange::Exchange>,
+    speed: Option<u32>,
+    batch_size: usize,
+}
+
+impl Configuration {
+    pub fn collector(&self) -> &str {
+        self.collector.as_str()
+    }
+
+    /// Files to replay in the order given.
+    pub fn files(&self) -> &[PathBuf] {
+        self.files.as_slice()
+    }
+
+    /// Format of all the files. Otherwise each file's extension decides.
+    pub fn format(&self) -> Option<Format> {
+        self.format
+    }
+
+    /// Exchange to place everything as instead of the one named in each row.
+    pub fn exchange(&self) -> Option<exchange::Exchange> {
+        self.exchange
+    }
+
+    /// How many times faster than it happened to replay. `None` for as fast as possible.
+    pub fn speed(&self) -> Option<u32> {
+        self.speed
+    }
+
+    /// Most trades to place at once.
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+}
+
+pub fn config_from_environment() -> Result<Configuration, ConfigError> {
+    let collector = env::var(COLLECTOR).map_err(|e| (COLLECTOR, e))?;
+    let files = env::var(FILES).map_err(|e| (FILES, e))?;
+
+    let files = files
+        .split(':')
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect();
+
+    let format = match env::var(FORMAT) {
+        Ok(format) => Some(format.parse()?),
+        Err(_) => None,
+    };
+
+    let exchange = match env::var(EXCHANGE) {
+        Ok(exchange) => Some(exchange.parse().map_err(|e| (EXCHANGE, e))?),
+        Err(_) => None,
+    };
+
+    let speed = match env::var(SPEED) {
+        Ok(speed) => Some(speed.parse().map_err(|e| (SPEED, e))?),
+        Err(_) => None,
+    };
+
+    let batch_size = match env::var(BATCH_SIZE) {
+        Ok(size) => size.parse().map_err(|e| (BATCH_SIZE, e))?,
+        Err(_) => DEFAULT_BATCH_SIZE,
+    };
+
+    Ok(Configuration {
+        collector,
+        files,
+        format,
+        exchange,
+        speed,
+        batch_size,
+    })
+}
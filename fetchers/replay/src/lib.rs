@@ -0,0 +1,20 @@
+//! Code
+#[macro_use] extern crate log;
+#[macro_use] extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
+extern crate csv;
+extern crate rust_decimal;
+extern crate chrono;
+extern crate futures;
+extern crate tokio;
+
+extern crate common;
+extern crate fetch_lib;
+
+pub mod read;
+mod record;
+mod replay;
+
+pub use self::record::{Record, Trade};
+pub use self::replay::{replay, Batch, Batches};
@@ -0,0 +1,63 @@
+//! Entrypoint
+#[macro_use] extern crate log;
+extern crate env_logger;
+extern crate tokio;
+extern crate dotenv;
+
+extern crate common;
+extern crate fetch_lib;
+
+extern crate replay_lib as lib;
+
+use fetch_lib::https_client;
+
+use lib::read::{self, Format};
+
+mod config;
+
+fn main() {
+    dotenv::dotenv().ok();
+    env_logger::init();
+    let config = config::config_from_environment().expect("Can't load config.");
+    debug!("Configuration: {:?}", &config);
+
+    let files: Vec<read::Records> = config
+        .files()
+        .iter()
+        .map(|path| {
+            let format = config.format()
+                .or_else(|| Format::from_path(path))
+                .expect("Can't tell the file format. Set REPLAY_FORMAT.");
+            read::open(path, format)
+                .unwrap_or_else(|e| panic!("Can't open {}: {}", path.display(), &e))
+        })
+        .collect();
+
+    let exchange = config.exchange();
+    let trades = files
+        .into_iter()
+        .flat_map(|records| records)
+        .filter_map(move |record| match record.and_then(|record| record.parse(exchange)) {
+            Ok(trade) => Some(trade),
+            Err(e) => {
+                warn!("Skipped row: {}", &e);
+                None
+            },
+        });
+
+    // Batching trades that happened apart would place the later ones early.
+    let batch_size = match config.speed() {
+        Some(_) => 1,
+        None => config.batch_size(),
+    };
+
+    let client = https_client::produce(1).expect("Can't init TLS.");
+    tokio::run(lib::replay(
+        client,
+        config.collector().to_owned(),
+        lib::Batches::new(trades, batch_size),
+        config.speed(),
+    ));
+
+    info!("Replay finished.");
+}
@@ -0,0 +1,123 @@
+//! Read records out of NDJSON or CSV trade files.
+use std::{fs, io, str};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use csv;
+use serde_json;
+
+use common::errors::ConfigError;
+
+use record::Record;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object a line. Blank lines are skipped.
+    Ndjson,
+
+    /// Comma separated with a header row naming the columns.
+    Csv,
+}
+
+impl Format {
+    /// Tell the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Some(Format::Csv),
+            Some("ndjson") | Some("jsonl") => Some(Format::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+impl str::FromStr for Format {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            _ => Err(ConfigError::InvalidFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Records in the order they appear. Rows that can't be read are errors naming the line.
+pub type Records = Box<Iterator<Item = Result<Record, String>> + Send>;
+
+pub fn records<R: Read + Send + 'static>(reader: R, format: Format) -> Records {
+    match format {
+        Format::Ndjson => Box::new(
+            BufReader::new(reader)
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| match line {
+                    Ok(line) => !line.trim().is_empty(),
+                    Err(_) => true,
+                })
+                .map(|(index, line)| {
+                    let line = line.map_err(|e| format!("Line {}: {}", index + 1, e))?;
+                    serde_json::from_str(&line)
+                        .map_err(|e| format!("Line {}: {}", index + 1, e))
+                })
+        ),
+        Format::Csv => Box::new(
+            csv::Reader::from_reader(reader)
+                .into_deserialize()
+                .map(|result| result.map_err(|e: csv::Error| e.to_string()))
+        ),
+    }
+}
+
+/// Open the file at `path` for reading records.
+pub fn open(path: &Path, format: Format) -> io::Result<Records> {
+    fs::File::open(path).map(|file| records(file, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use common::exchange::Exchange;
+
+    use super::*;
+
+    #[test]
+    fn read_csv() {
+        let csv = Cursor::new(include_str!("../fixtures/trades.csv"));
+        let records: Vec<Record> = records(csv, Format::Csv)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(records.len(), 2);
+        let kraken = records[0].parse(None).unwrap();
+        assert_eq!(kraken.exchange, Exchange::Kraken);
+        assert_eq!(kraken.item.match_id(), None);
+        let binance = records[1].parse(None).unwrap();
+        assert_eq!(binance.item.match_id(), Some(80713418));
+        assert_eq!(binance.item.sell_order_id(), Some(198110301));
+    }
+
+    #[test]
+    fn read_ndjson() {
+        let ndjson = Cursor::new(include_str!("../fixtures/trades.ndjson"));
+        let records: Vec<Record> = records(ndjson, Format::Ndjson)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].parse(None).unwrap().exchange, Exchange::Coinbase);
+        assert_eq!(records[1].parse(None).unwrap().item.buy_order_id(), None);
+
+        let broken = Cursor::new("{\"exchange\":\"kraken\"}\n");
+        let error = records(broken, Format::Ndjson).next().unwrap().unwrap_err();
+        assert!(error.starts_with("Line 1:"));
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(Format::from_path(Path::new("a/trades.csv")), Some(Format::Csv));
+        assert_eq!(Format::from_path(Path::new("trades.jsonl")), Some(Format::Ndjson));
+        assert_eq!(Format::from_path(Path::new("trades")), None);
+    }
+}
@@ -0,0 +1,150 @@
+//! A row of a trade file and the trade it describes.
+//!
+//! Prices and sizes are read as text so no precision is lost on the way. Timestamps are
+//! either RFC3339 or Unix seconds with an optional fraction. The side is anything
+//! `trade::Market` parses. A buy is the taker.
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
+
+use common::{asset, exchange, trade};
+
+/// Nanosecond digits chrono can hold.
+const MAX_FRACTION_DIGITS: usize = 9;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Record {
+    exchange: String,
+    pair: String,
+    timestamp: String,
+    price: String,
+    size: String,
+    side: String,
+    #[serde(default)]
+    match_id: Option<i64>,
+    #[serde(default)]
+    buy_order_id: Option<i64>,
+    #[serde(default)]
+    sell_order_id: Option<i64>,
+}
+
+impl Record {
+    /// Parse into a trade. When `exchange` is given it's used instead of the row's own.
+    pub fn parse(&self, exchange: Option<exchange::Exchange>) -> Result<Trade, String> {
+        let exchange = match exchange {
+            Some(exchange) => exchange,
+            None => self.exchange
+                .parse()
+                .map_err(|_| format!("Invalid exchange: {}", &self.exchange))?,
+        };
+        let pair: asset::Pair = self.pair
+            .parse()
+            .map_err(|_| format!("Invalid pair: {}", &self.pair))?;
+        let timestamp = parse_timestamp(&self.timestamp)?;
+        let price: Decimal = self.price
+            .parse()
+            .map_err(|_| format!("Invalid price: {}", &self.price))?;
+        let size: Decimal = self.size
+            .parse()
+            .map_err(|_| format!("Invalid size: {}", &self.size))?;
+        let market: trade::Market = self.side
+            .parse()
+            .map_err(|_| format!("Invalid side: {}", &self.side))?;
+
+        let item = trade::TradeHistoryItem::new(
+            timestamp,
+            size,
+            price,
+            market,
+            None,
+            self.match_id,
+            self.buy_order_id,
+            self.sell_order_id,
+            None,
+        );
+
+        Ok(Trade {
+            exchange, pair, item,
+        })
+    }
+}
+
+fn parse_timestamp(text: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(text) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    let invalid = || format!("Invalid timestamp: {}", text);
+    let mut parts = text.splitn(2, '.');
+    let seconds: i64 = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+    let nanos: u32 = match parts.next() {
+        Some(fraction) => {
+            let mut digits: String = fraction.chars().take(MAX_FRACTION_DIGITS).collect();
+            while digits.len() < MAX_FRACTION_DIGITS {
+                digits.push('0');
+            }
+            digits.parse().map_err(|_| invalid())?
+        },
+        None => 0,
+    };
+
+    Utc.timestamp_opt(seconds, nanos).single().ok_or_else(invalid)
+}
+
+/// A trade ready to be placed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    pub exchange: exchange::Exchange,
+    pub pair: asset::Pair,
+    pub item: trade::TradeHistoryItem,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            parse_timestamp("2018-10-22T09:15:44.562Z").unwrap(),
+            Utc.ymd(2018, 10, 22).and_hms_milli(9, 15, 44, 562),
+        );
+        assert_eq!(
+            parse_timestamp("1540199745.5").unwrap(),
+            Utc.ymd(2018, 10, 22).and_hms_milli(9, 15, 45, 500),
+        );
+        assert_eq!(
+            parse_timestamp("1540199745").unwrap(),
+            Utc.ymd(2018, 10, 22).and_hms(9, 15, 45),
+        );
+        assert!(parse_timestamp("yesterday").is_err());
+        assert!(parse_timestamp("1540199745.5x").is_err());
+    }
+
+    #[test]
+    fn parse_and_override_exchange() {
+        let record = Record {
+            exchange: "kraken".to_owned(),
+            pair: "ETH/BTC".to_owned(),
+            timestamp: "1540199745".to_owned(),
+            price: "0.031245".to_owned(),
+            size: "1.2".to_owned(),
+            side: "sell".to_owned(),
+            match_id: Some(80713418),
+            buy_order_id: None,
+            sell_order_id: None,
+        };
+
+        let parsed = record.parse(None).unwrap();
+        assert_eq!(parsed.exchange, exchange::Exchange::Kraken);
+        assert_eq!(parsed.pair, asset::ETH_BTC);
+        assert_eq!(parsed.item.price(), "0.031245".parse().unwrap());
+        assert_eq!(parsed.item.market(), trade::Market::Maker);
+        assert_eq!(parsed.item.match_id(), Some(80713418));
+
+        let parsed = record.parse(Some(exchange::Exchange::Binance)).unwrap();
+        assert_eq!(parsed.exchange, exchange::Exchange::Binance);
+
+        let bad = Record { side: "sideways".to_owned(), ..record };
+        assert!(bad.parse(None).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Place trades read from files on the collector.
+//!
+//! Consecutive trades of the same exchange and pair go out together in batches. Each
+//! exchange gets its own placement so trades are put on the collector as if they came from
+//! that exchange's fetcher.
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures::{future, Future, Stream};
+use futures::future::Either;
+use futures::stream;
+use futures::sync::mpsc;
+use tokio;
+use tokio::timer::Delay;
+
+use fetch_lib::https_client::HttpsClient;
+use fetch_lib::place;
+use common::{asset, exchange, trade};
+
+use record::Trade;
+
+/// Trades of a single exchange and pair to place in one go.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    exchange: exchange::Exchange,
+    pair: asset::Pair,
+    items: Vec<trade::TradeHistoryItem>,
+}
+
+impl Batch {
+    /// When the first trade in the batch happened.
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.items[0].timestamp()
+    }
+}
+
+/// Groups consecutive trades into batches of at most `max` trades.
+pub struct Batches<I: Iterator<Item = Trade>> {
+    trades: Peekable<I>,
+    max: usize,
+}
+
+impl<I: Iterator<Item = Trade>> Batches<I> {
+    pub fn new(trades: I, max: usize) -> Self {
+        Batches {
+            trades: trades.peekable(),
+            max: max.max(1),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Trade>> Iterator for Batches<I> {
+    type Item = Batch;
+
+    fn next(&mut self) -> Option<Batch> {
+        let first = self.trades.next()?;
+        let mut batch = Batch {
+            exchange: first.exchange,
+            pair: first.pair,
+            items: vec![first.item],
+        };
+
+        while batch.items.len() < self.max {
+            let same = match self.trades.peek() {
+                Some(next) => next.exchange == batch.exchange && next.pair == batch.pair,
+                None => false,
+            };
+            if !same {
+                break;
+            }
+            let next = self.trades.next().expect("Just peeked.");
+            batch.items.push(next.item);
+        }
+
+        Some(batch)
+    }
+}
+
+/// Works out when to place a batch so the gaps between trades are kept, sped up by the
+/// `speed` factor. The first trade goes out straight away.
+#[derive(Debug)]
+struct Pacer {
+    speed: u32,
+    start: Option<(DateTime<Utc>, Instant)>,
+}
+
+impl Pacer {
+    fn new(speed: u32) -> Self {
+        Pacer {
+            speed: speed.max(1),
+            start: None,
+        }
+    }
+
+    /// Trades older than the first are due straight away.
+    fn due(&mut self, at: DateTime<Utc>, now: Instant) -> Instant {
+        let (first, started) = *self.start.get_or_insert((at, now));
+        let elapsed = at
+            .signed_duration_since(first)
+            .to_std()
+            .unwrap_or(Duration::from_secs(0));
+        started + elapsed / self.speed
+    }
+}
+
+/// Returns the future that places all the `batches` on the `collector`. With a `speed` the
+/// trades are placed at that many times the pace they happened at. Without one they are
+/// placed as fast as the collector takes them.
+///
+/// Must be run on the tokio runtime. Placement is spawned and finishes after the returned
+/// future resolves.
+pub fn replay(
+    client: HttpsClient,
+    collector: String,
+    batches: impl Iterator<Item = Batch> + Send + 'static,
+    speed: Option<u32>,
+) -> impl Future<Item = (), Error = ()> {
+    let mut pacer = speed.map(Pacer::new);
+    let mut placements = HashMap::new();
+
+    stream::iter_ok::<_, ()>(batches)
+        .and_then(move |batch| match pacer {
+            Some(ref mut pacer) => {
+                let due = pacer.due(batch.timestamp(), Instant::now());
+                Either::A(Delay::new(due).then(move |_| Ok(batch)))
+            },
+            None => Either::B(future::ok(batch)),
+        })
+        .for_each(move |batch| {
+            let Batch { exchange, pair, items } = batch;
+            let placement = placements.entry(exchange).or_insert_with(|| {
+                info!("Replaying {} trades.", &exchange);
+                let (tx, rx) = mpsc::unbounded();
+                let target = place::Target::new(&collector, exchange, Vec::new());
+                tokio::spawn(place::put_trade_history(client.clone(), target, rx));
+                tx
+            });
+
+            debug!("Placing {} {} {} trade(s).", items.len(), &exchange, &pair);
+            placement
+                .unbounded_send((pair, items))
+                .map_err(|e| error!("Placement of {} stopped: {}", &exchange, &e))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(exchange: exchange::Exchange, pair: asset::Pair, secs: i64) -> Trade {
+        Trade {
+            exchange: exchange,
+            pair: pair,
+            item: trade::TradeHistoryItem::new(
+                Utc.timestamp(secs, 0), 1.into(), 10.into(), trade::Market::Taker, None,
+                None, None, None, None,
+            ),
+        }
+    }
+
+    #[test]
+    fn consecutive_trades_are_batched() {
+        use common::exchange::Exchange::{Kraken, Binance};
+
+        let trades = vec![
+            at(Kraken, asset::BTC_USD, 1),
+            at(Kraken, asset::BTC_USD, 2),
+            at(Kraken, asset::BTC_USD, 3),
+            at(Kraken, asset::ETH_USD, 4),
+            at(Binance, asset::ETH_USD, 5),
+        ];
+
+        let sizes: Vec<(exchange::Exchange, asset::Pair, usize)> = Batches::new(
+            trades.into_iter(), 2,
+        )
+            .map(|batch| (batch.exchange, batch.pair, batch.items.len()))
+            .collect();
+
+        assert_eq!(sizes, vec![
+            (Kraken, asset::BTC_USD, 2),
+            (Kraken, asset::BTC_USD, 1),
+            (Kraken, asset::ETH_USD, 1),
+            (Binance, asset::ETH_USD, 1),
+        ]);
+    }
+
+    #[test]
+    fn pacing_keeps_gaps() {
+        let now = Instant::now();
+        let mut pacer = Pacer::new(10);
+
+        assert_eq!(pacer.due(Utc.timestamp(1000, 0), now), now);
+        assert_eq!(
+            pacer.due(Utc.timestamp(1060, 0), now + Duration::from_secs(1)),
+            now + Duration::from_secs(6),
+        );
+
+        // Out of order trades don't wait.
+        assert_eq!(pacer.due(Utc.timestamp(900, 0), now + Duration::from_secs(7)), now);
+    }
+}
@@ -1,11 +1,13 @@
 //! Database synchronous actor
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use actix::prelude::*;
 
 use common::{exchange, asset};
 use trade_history::{crud, model};
+use trade_history::error::Error;
 
 use output;
+use gaps;
 
 /// Request a table summary of the trade history data set within the criteria
 #[derive(Debug, Copy, Clone)]
@@ -135,3 +137,186 @@ impl Handler<TradeSummaryRequest> for TradeHistoryFetcher {
         Ok(summary)
     }
 }
+
+/// Scan the trade history of every exchange and asset pair delivered since its last scan up
+/// until `to` for gaps and store them. Gaps stored by earlier scans are checked again in
+/// case a backfill has filled them in. Resolves to how many new gaps were found.
+#[derive(Debug, Copy, Clone)]
+pub struct DetectGaps {
+    to: DateTime<Utc>,
+}
+
+impl DetectGaps {
+    pub fn new(to: DateTime<Utc>) -> Self {
+        DetectGaps { to }
+    }
+}
+
+impl Message for DetectGaps {
+    type Result = Result<u64, String>;
+}
+
+impl Handler<DetectGaps> for TradeHistoryFetcher {
+    type Result = Result<u64, String>;
+
+    fn handle(&mut self, msg: DetectGaps, _ctx: &mut Self::Context) -> Self::Result {
+        let delivered = self.fetcher.delivered().map_err(|e| e.to_string())?;
+        let baseline_from = msg.to - Duration::days(gaps::BASELINE_DAYS);
+        let mut found = 0;
+
+        for (exchange, asset_pair) in delivered {
+            let scan = self.fetcher
+                .read_gap_scan(exchange, asset_pair)
+                .map_err(|e| e.to_string())?;
+            let (scanned_from, from) = match scan {
+                Some(scan) => (*scan.scanned_from(), *scan.scanned_until()),
+                None => {
+                    let from = msg.to - Duration::days(gaps::FIRST_SCAN_DAYS);
+                    (from, from)
+                },
+            };
+            if msg.to <= from {
+                continue;
+            }
+
+            let baseline = self.fetcher
+                .read_range_summary(exchange, asset_pair, baseline_from, msg.to)
+                .map_err(|e| e.to_string())?;
+            let threshold = baseline.as_ref().and_then(gaps::silence_threshold);
+
+            let stored = self.fetcher
+                .read_gaps(exchange, asset_pair, scanned_from, from)
+                .map_err(|e| e.to_string())?;
+            for gap in stored.iter() {
+                found += recheck_gap(&self.fetcher, gap, threshold)
+                    .map_err(|e| e.to_string())?;
+            }
+
+            let mut detected = Vec::new();
+            if let Some(threshold) = threshold {
+                let silences = self.fetcher
+                    .read_silences(exchange, asset_pair, from, msg.to, threshold)
+                    .map_err(|e| e.to_string())?;
+                detected.extend(silences);
+            }
+
+            if gaps::numbers_matches(exchange) {
+                let jumps = self.fetcher
+                    .read_match_id_jumps(exchange, asset_pair, from, msg.to)
+                    .map_err(|e| e.to_string())?;
+                detected.extend(jumps);
+            }
+
+            if !detected.is_empty() {
+                let inserted = self.fetcher.create_gaps(&detected)
+                    .map_err(|e| e.to_string())?;
+                trace!(
+                    "{} {} {} gap(s) found. {} new.",
+                    &exchange,
+                    &asset_pair,
+                    detected.len(),
+                    inserted,
+                );
+                found += inserted;
+            }
+
+            self.fetcher
+                .update_gap_scan(exchange, asset_pair, scanned_from, msg.to)
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(found)
+    }
+}
+
+/// Look for the `gap` again. Anything found in its place now replaces it. Returns how many
+/// gaps were inserted in its place.
+///
+/// Silences can't be checked without a `threshold` and are left as they are.
+fn recheck_gap(
+    trades: &crud::Trades, gap: &model::Gap, threshold: Option<f64>,
+) -> Result<u64, Error> {
+    let (exchange, asset_pair) = (*gap.exchange(), *gap.asset_pair());
+    let started = *gap.started();
+    // Just past the end so that the trade ending the gap is read.
+    let until = *gap.ended() + Duration::microseconds(1);
+
+    let found = match (*gap.kind(), threshold) {
+        (model::GapKind::Silence, Some(threshold)) => {
+            trades.read_silences(exchange, asset_pair, started, until, threshold)?
+        },
+        (model::GapKind::Silence, None) => return Ok(0),
+        (model::GapKind::MatchId, _) => {
+            trades.read_match_id_jumps(exchange, asset_pair, started, until)?
+        },
+    };
+
+    // The trade before `started` is read as well. Gaps before this one are left alone.
+    let found: Vec<model::Gap> = found
+        .into_iter()
+        .filter(|found| *found.started() >= started)
+        .collect();
+    if found.len() == 1 && found[0] == *gap {
+        return Ok(0);
+    }
+
+    debug!("Gap {:?} is now {:?}.", gap, &found);
+    trades.replace_gap(gap, &found)
+}
+
+/// Request the gaps of an exchange and asset pair between `from` and `to` along with how
+/// much of it is covered.
+#[derive(Debug, Copy, Clone)]
+pub struct GapsRequest {
+    exchange: exchange::Exchange,
+    asset_pair: asset::Pair,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+impl GapsRequest {
+    pub fn new(
+        exchange: exchange::Exchange,
+        asset_pair: asset::Pair,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Self {
+        GapsRequest {
+            exchange, asset_pair, from, to,
+        }
+    }
+}
+
+impl Message for GapsRequest {
+    type Result = Result<output::Coverage, String>;
+}
+
+impl Handler<GapsRequest> for TradeHistoryFetcher {
+    type Result = Result<output::Coverage, String>;
+
+    fn handle(&mut self, msg: GapsRequest, _ctx: &mut Self::Context) -> Self::Result {
+        let found = self.fetcher
+            .read_gaps(msg.exchange, msg.asset_pair, msg.from, msg.to)
+            .map_err(|e| e.to_string())?;
+        let scan = self.fetcher
+            .read_gap_scan(msg.exchange, msg.asset_pair)
+            .map_err(|e| e.to_string())?;
+
+        let baseline_from = msg.to - Duration::days(gaps::BASELINE_DAYS);
+        let baseline = self.fetcher
+            .read_range_summary(msg.exchange, msg.asset_pair, baseline_from, msg.to)
+            .map_err(|e| e.to_string())?;
+        let threshold = baseline.as_ref().and_then(gaps::silence_threshold);
+
+        let trades = self.fetcher
+            .read_range_summary(msg.exchange, msg.asset_pair, msg.from, msg.to)
+            .map_err(|e| e.to_string())?;
+
+        let coverage = gaps::coverage(
+            msg.from, msg.to, scan.as_ref(), trades.as_ref(), threshold, &found,
+        );
+        Ok(output::Coverage::new(
+            msg.exchange, msg.asset_pair, msg.from, msg.to, coverage, found,
+        ))
+    }
+}
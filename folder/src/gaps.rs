@@ -0,0 +1,252 @@
+//! Gap detection. Trade history is scanned every so often for holes left by fetchers that
+//! were down. A hole is either a silence far longer than the usual time between trades of
+//! the exchange and asset pair or match IDs that skip ahead. What's found is stored along
+//! with how far the scans have got.
+use std::cmp;
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc};
+use actix::prelude::*;
+
+use common::exchange::Exchange;
+use trade_history::model::{Gap, GapScan, TradeSetSummary};
+
+use database::{TradeHistoryFetcher, DetectGaps};
+
+/// How often new trade history is scanned.
+const SCAN_INTERVAL: u64 = 600;
+
+/// The most recent trade history is left alone for this long in case more of it is on the
+/// way.
+const SETTLE_MINUTES: i64 = 5;
+
+/// How far back the first scan goes.
+pub const FIRST_SCAN_DAYS: i64 = 7;
+
+/// How far back trades are counted to find the usual time between them.
+pub const BASELINE_DAYS: i64 = 7;
+
+/// A silence this many times longer than the usual time between trades is a gap.
+const SILENCE_FACTOR: f64 = 20.0;
+
+/// No silence shorter than this is a gap however busy the asset pair usually is.
+const MIN_SILENCE_SECONDS: f64 = 60.0;
+
+/// Fewer trades than this over the baseline can't tell what's usual.
+const MIN_TRADES: i64 = 10;
+
+/// Silences longer than this many seconds are gaps. `None` if there were too few trades
+/// over the baseline to tell.
+pub fn silence_threshold(baseline: &TradeSetSummary) -> Option<f64> {
+    if *baseline.count() < MIN_TRADES {
+        return None;
+    }
+
+    let span = (*baseline.last() - *baseline.first()).num_milliseconds() as f64 / 1000.0;
+    let usual = span / (*baseline.count() - 1) as f64;
+    Some((usual * SILENCE_FACTOR).max(MIN_SILENCE_SECONDS))
+}
+
+/// Whether the exchange numbers the trades of each asset pair one after the other. Others
+/// number them across every asset pair so skipped match IDs mean nothing.
+///
+/// Kraken trades only sometimes carry an ID. Those without are left out of the jumps.
+pub fn numbers_matches(exchange: Exchange) -> bool {
+    match exchange {
+        Exchange::Binance | Exchange::Coinbase | Exchange::Kraken => true,
+        Exchange::Bitstamp | Exchange::Bitfinex => false,
+    }
+}
+
+/// Percentage of `from` to `to` that is known to be covered. Gaps, stretches that haven't
+/// been scanned and stretches without trades longer than a silence are uncovered. Overlaps
+/// are only counted once.
+///
+/// `None` if nothing has been scanned yet or there were too few trades over the baseline to
+/// tell what a silence is.
+pub fn coverage(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    scan: Option<&GapScan>,
+    trades: Option<&TradeSetSummary>,
+    threshold: Option<f64>,
+    gaps: &[Gap],
+) -> Option<f64> {
+    let total = (to - from).num_milliseconds();
+    let (scan, threshold) = match (scan, threshold) {
+        (Some(scan), Some(threshold)) if total > 0 => (scan, threshold),
+        _ => return None,
+    };
+    let silence = Duration::milliseconds((threshold * 1000.0) as i64);
+
+    let mut spans: Vec<(DateTime<Utc>, DateTime<Utc>)> = gaps
+        .iter()
+        .map(|gap| (*gap.started(), *gap.ended()))
+        .collect();
+
+    spans.push((from, *scan.scanned_from()));
+    spans.push((*scan.scanned_until(), to));
+
+    // A silence at either end has no trade on the far side of it to be found by.
+    match trades {
+        Some(trades) => {
+            if *trades.first() - from > silence {
+                spans.push((from, *trades.first()));
+            }
+            if to - *trades.last() > silence {
+                spans.push((*trades.last(), to));
+            }
+        },
+        None => spans.push((from, to)),
+    }
+
+    let missing = uncovered(from, to, spans);
+    Some(100.0 * (total - missing) as f64 / total as f64)
+}
+
+/// Milliseconds of `from` to `to` within any of the `spans`.
+fn uncovered(
+    from: DateTime<Utc>, to: DateTime<Utc>, spans: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+) -> i64 {
+    let mut spans: Vec<(DateTime<Utc>, DateTime<Utc>)> = spans
+        .into_iter()
+        .map(|(started, ended)| (cmp::max(started, from), cmp::min(ended, to)))
+        .filter(|&(started, ended)| started < ended)
+        .collect();
+    spans.sort();
+
+    let mut missing = 0;
+    let mut reached = from;
+    for (started, ended) in spans {
+        let started = cmp::max(started, reached);
+        if ended > started {
+            missing += (ended - started).num_milliseconds();
+            reached = ended;
+        }
+    }
+
+    missing
+}
+
+/// Scans the trade history delivered since the last scan for gaps.
+pub struct GapDetector {
+    fetcher: Addr<TradeHistoryFetcher>,
+    scanning: bool,
+}
+
+impl GapDetector {
+    pub fn new(fetcher: Addr<TradeHistoryFetcher>) -> Self {
+        GapDetector {
+            fetcher,
+            scanning: false,
+        }
+    }
+
+    fn scan(&mut self, ctx: &mut Context<Self>) {
+        // A slow scan isn't piled on top of.
+        if self.scanning {
+            return;
+        }
+
+        let to = Utc::now() - Duration::minutes(SETTLE_MINUTES);
+
+        self.scanning = true;
+        let scanning = self.fetcher
+            .send(DetectGaps::new(to))
+            .into_actor(self)
+            .then(move |result, act, _ctx| {
+                act.scanning = false;
+                match result {
+                    Ok(Ok(found)) => debug!("{} new gap(s) found up to {}.", found, &to),
+                    Ok(Err(e)) => error!("Can't scan for gaps: {}", &e),
+                    Err(e) => error!("Trade history fetcher failure: {}", &e),
+                }
+                actix::fut::ok(())
+            });
+
+        ctx.spawn(scanning);
+    }
+}
+
+impl Actor for GapDetector {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.scan(ctx);
+        ctx.run_interval(StdDuration::from_secs(SCAN_INTERVAL), |act, ctx| act.scan(ctx));
+        debug!("Gap detector started.");
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        debug!("Gap detector stopped.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    use common::asset;
+    use trade_history::model::GapKind;
+
+    fn gap(started: i64, ended: i64) -> Gap {
+        Gap::new(
+            Exchange::Kraken,
+            asset::BTC_USD,
+            GapKind::Silence,
+            Utc.timestamp(started, 0),
+            Utc.timestamp(ended, 0),
+            None,
+        )
+    }
+
+    #[test]
+    fn silences_are_measured_against_the_usual_rate() {
+        let first = Utc.timestamp(0, 0);
+        let busy = TradeSetSummary::new(first, Utc.timestamp(99, 0), 100);
+        assert_eq!(silence_threshold(&busy), Some(MIN_SILENCE_SECONDS));
+
+        let quiet = TradeSetSummary::new(first, Utc.timestamp(3300, 0), 12);
+        assert_eq!(silence_threshold(&quiet), Some(6000.0));
+
+        let too_few = TradeSetSummary::new(first, Utc.timestamp(3300, 0), 3);
+        assert_eq!(silence_threshold(&too_few), None);
+    }
+
+    #[test]
+    fn overlapping_and_outlying_gaps_count_once() {
+        let (from, to) = (Utc.timestamp(100, 0), Utc.timestamp(200, 0));
+        let scan = GapScan::new(Utc.timestamp(0, 0), Utc.timestamp(1000, 0));
+        let trades = TradeSetSummary::new(Utc.timestamp(100, 0), Utc.timestamp(199, 0), 50);
+        let covered = |gaps: &[Gap]| {
+            coverage(from, to, Some(&scan), Some(&trades), Some(60.0), gaps)
+        };
+        assert_eq!(covered(&[]), Some(100.0));
+
+        let gaps = vec![gap(50, 110), gap(140, 160), gap(150, 170), gap(300, 400)];
+        assert_eq!(covered(&gaps), Some(60.0));
+    }
+
+    #[test]
+    fn unscanned_and_tradeless_stretches_are_uncovered() {
+        let (from, to) = (Utc.timestamp(1000, 0), Utc.timestamp(2000, 0));
+        let scan = GapScan::new(Utc.timestamp(1100, 0), Utc.timestamp(1900, 0));
+        let all = TradeSetSummary::new(from, Utc.timestamp(1999, 0), 500);
+        let unscanned = coverage(from, to, Some(&scan), Some(&all), Some(60.0), &[]);
+        assert_eq!(unscanned, Some(80.0));
+
+        // Trades stopped at 1500 and haven't started again.
+        let scan = GapScan::new(Utc.timestamp(0, 0), Utc.timestamp(2000, 0));
+        let stopped = TradeSetSummary::new(from, Utc.timestamp(1500, 0), 250);
+        let ongoing = coverage(from, to, Some(&scan), Some(&stopped), Some(60.0), &[]);
+        assert_eq!(ongoing, Some(50.0));
+        assert_eq!(coverage(from, to, Some(&scan), None, Some(60.0), &[]), Some(0.0));
+
+        // Can't tell.
+        assert_eq!(coverage(from, to, None, Some(&all), Some(60.0), &[]), None);
+        assert_eq!(coverage(from, to, Some(&scan), Some(&all), None, &[]), None);
+        assert_eq!(coverage(to, from, Some(&scan), Some(&all), Some(60.0), &[]), None);
+    }
+}
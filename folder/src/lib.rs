@@ -14,4 +14,5 @@ extern crate trade_history;
 pub mod database;
 pub mod restful;
 pub mod fold;
+pub mod gaps;
 mod output;
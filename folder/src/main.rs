@@ -10,7 +10,7 @@ extern crate folder_lib as lib;
 use actix::prelude::*;
 use actix_web::{server::HttpServer, App, http::Method};
 
-use lib::{database, restful, fold};
+use lib::{database, restful, fold, gaps};
 
 mod config;
 
@@ -31,6 +31,13 @@ fn main() {
     let th_fold = fold::TradeHistoryFolder::new(th_fetch_addr.clone());
     let th_fold_addr = th_fold.start();
 
+    // Scans get their own DB actor so they don't hold up requests.
+    let db_url = config.database_url().to_owned();
+    let gap_fetch_addr = SyncArbiter::start(1, move || {
+        database::TradeHistoryFetcher::new(db_url.as_str())
+    });
+    gaps::GapDetector::new(gap_fetch_addr).start();
+
     let state = restful::State::new(th_fetch_addr, th_fold_addr);
 
     HttpServer::new(move || {
@@ -55,6 +62,9 @@ fn main() {
                     .resource("/{left_asset}/{right_asset}/{exchange}/tick", |r| {
                         r.method(Method::GET).f(restful::thf_match_exchange_tick)
                     })
+                    .resource("/{left_asset}/{right_asset}/{exchange}/gaps", |r| {
+                        r.method(Method::GET).f(restful::thf_match_exchange_gaps)
+                    })
             })
     })
         .bind(config.listen())
@@ -3,6 +3,7 @@
 use chrono::{DateTime, Utc};
 
 use common::{asset, exchange};
+use trade_history::model::Gap;
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum FoldOperation {
@@ -49,6 +50,34 @@ impl TradeHistorySummary {
     }
 }
 
+/// The gaps in the trade history of an exchange and asset pair over a time range. The
+/// coverage is the percentage of the range known to be covered. `None` when it can't be
+/// told.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coverage {
+    exchange: exchange::Exchange,
+    assetpair: asset::Pair,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    coverage: Option<f64>,
+    gaps: Vec<Gap>,
+}
+
+impl Coverage {
+    pub fn new(
+        exchange: exchange::Exchange,
+        assetpair: asset::Pair,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        coverage: Option<f64>,
+        gaps: Vec<Gap>,
+    ) -> Self {
+        Coverage {
+            exchange, assetpair, from, to, coverage, gaps,
+        }
+    }
+}
+
 /*
 /// A single tick. The time from/to, asset_pair, exchange(s) are not present and to be
 /// determined via the calling context.
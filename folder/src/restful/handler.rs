@@ -191,3 +191,47 @@ pub fn thf_match_exchange_tick(
     
     generate_tick(request, folder)
 }
+
+pub fn thf_match_exchange_gaps(
+    req: &HttpRequest<State>
+) -> Box<Future<Item = HttpResponse, Error = error::Error>> {
+    let params = req.match_info();
+    let lasset = params.get("left_asset")
+        .expect("Invalid use of function. Need to have {left_asset} on path.");
+    let rasset = params.get("right_asset")
+        .expect("Invalid use of function. Need to have {right_asset} on path.");
+    let exchange = params.get("exchange")
+        .expect("Invalid use of function. Need to have {exchange} on path.");
+
+    let query = req.query();
+    let from: u64 = extract_query!("from", query);
+    let to: u64 = extract_query!("to", query);
+
+    let from = DateTime::from_utc(NaiveDateTime::from_timestamp(from as i64, 0), Utc);
+    let to = DateTime::from_utc(NaiveDateTime::from_timestamp(to as i64, 0), Utc);
+    if from >= to {
+        return Box::new(future::ok(HttpResponse::BadRequest().finish()));
+    }
+
+    let left: Asset = parse_path_segment!(lasset);
+    let right: Asset = parse_path_segment!(rasset);
+    let exchange: Exchange = parse_path_segment!(exchange);
+    let pair = asset::Pair::new(left, right);
+    let state = req.state();
+    let fetcher_addr = state.trade_history_fetcher().clone();
+    let request = database::GapsRequest::new(exchange, pair, from, to);
+
+    fetcher_addr.send(request)
+        .then(move |result| match result {
+            Ok(Ok(coverage)) => Ok(HttpResponse::Ok().json(coverage)),
+            Ok(Err(e)) => {
+                error!("Database fetch failure: {}", &e);
+                Ok(HttpResponse::InternalServerError().finish())
+            },
+            Err(e) => {
+                error!("Database actor failure: {}", &e);
+                Ok(HttpResponse::InternalServerError().finish())
+            },
+        })
+        .responder()
+}
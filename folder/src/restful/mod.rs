@@ -12,4 +12,5 @@ pub use self::handler::{
     thf_match_asset_pair_tick,
     thf_match_exchange,
     thf_match_exchange_tick,
+    thf_match_exchange_gaps,
 };